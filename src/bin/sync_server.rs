@@ -0,0 +1,24 @@
+use crdt::SyncServer;
+
+/// Runs a sync server, listening for TCP clients on the address given as the first
+/// argument and, if a second address is given, for WebSocket clients on it.
+///
+/// ```text
+/// cargo run --bin sync_server -- 127.0.0.1:7878 127.0.0.1:7879
+/// ```
+#[tokio::main]
+async fn main() -> Result<(), crdt::SyncError> {
+    let mut args = std::env::args().skip(1);
+    let addr: String = args.next().unwrap_or_else(|| "127.0.0.1:7878".to_string());
+
+    let mut server: SyncServer = SyncServer::bind(addr.as_str()).await?;
+    println!("Listening on {}", server.local_addr()?);
+    if let Some(websocket) = args.next() {
+        server = server.listen_websocket(websocket.as_str()).await?;
+        if let Some(addr) = server.websocket_addr() {
+            println!("Listening for WebSocket clients on {}", addr?);
+        }
+    }
+    server.run().await;
+    return Ok(());
+}
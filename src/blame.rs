@@ -0,0 +1,204 @@
+use crate::rga::rga::{Node, RGA};
+use crate::S4Vector;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// Who wrote a visible element, or a run of elements inserted together by one site.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Blame {
+    /// The `S4Vector` of the first element.
+    pub s4vector: S4Vector,
+    /// The values of the elements, concatenated.
+    pub value: String,
+    /// The number of elements.
+    pub len: usize,
+    /// The site that inserted the elements.
+    pub inserted_by: u64,
+    /// The sequence numbers of the inserts, one per element.
+    pub seqs: Range<u64>,
+    /// The `S4Vector` of the update that set the value, if any. Its `sid` is the
+    /// site that last updated the element and its `seq` the sequence number.
+    pub updated: Option<S4Vector>,
+}
+
+impl Blame {
+    /// Returns the site that last updated the value, if it has been updated.
+    pub fn updated_by(&self) -> Option<u64> {
+        return self.updated.map(|updated| updated.sid);
+    }
+}
+
+impl RGA {
+    /// Attributes every visible element to the site that wrote it, in document order.
+    ///
+    /// Elements stored together as a run are reported as one `Blame`, and a moved
+    /// element is reported at its current position.
+    ///
+    /// # Example
+    /// ```
+    /// use crdt::rga::rga::RGA;
+    ///
+    /// let mut rga1 = RGA::new(1, 1);
+    /// let mut rga2 = RGA::new(1, 2);
+    /// let a = rga1.local_insert("a".to_string(), None, None).unwrap();
+    /// rga2.remote_apply(a.clone());
+    /// let update = rga2.local_update(a.s4vector, "A".to_string()).unwrap();
+    /// rga1.remote_apply(update);
+    ///
+    /// let blame = rga1.blame();
+    /// assert_eq!(blame[0].value, "A");
+    /// assert_eq!(blame[0].inserted_by, 1);
+    /// assert_eq!(blame[0].updated_by(), Some(2));
+    /// ```
+    pub fn blame(&self) -> Vec<Blame> {
+        let mut blame: Vec<Blame> = Vec::new();
+
+        let mut key: Option<S4Vector> = self.head();
+        while let Some(current) = key {
+            let node: &Node = self.node(&current).unwrap();
+            if let Some(element) = self.displayed(node) {
+                let seq: u64 = element.s4vector.seq;
+                blame.push(Blame {
+                    s4vector: element.s4vector,
+                    value: element.value.clone(),
+                    len: element.len,
+                    inserted_by: element.s4vector.sid,
+                    seqs: seq..seq + element.len as u64,
+                    updated: element.updated,
+                });
+            }
+            key = node.right;
+        }
+        return blame;
+    }
+}
+
+/// `Authors` maps site IDs to metadata about the user behind each site, such as a
+/// name, to present the result of `RGA::blame`.
+///
+/// # Example
+/// ```
+/// use crdt::rga::rga::RGA;
+/// use crdt::Authors;
+///
+/// let mut authors: Authors = Authors::new();
+/// authors.insert(1, "alice".to_string());
+///
+/// let mut rga = RGA::new(1, 1);
+/// rga.local_insert("a".to_string(), None, None).unwrap();
+/// let blame = rga.blame();
+/// assert_eq!(authors.inserted_by(&blame[0]).unwrap(), "alice");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Authors<M = String> {
+    sites: HashMap<u64, M>,
+}
+
+impl<M> Default for Authors<M> {
+    fn default() -> Self {
+        return Authors {
+            sites: HashMap::new(),
+        };
+    }
+}
+
+impl<M> Authors<M> {
+    /// Creates an empty `Authors`.
+    pub fn new() -> Self {
+        return Authors::default();
+    }
+
+    /// Sets the metadata of `site_id`.
+    ///
+    /// # Returns
+    /// The previous metadata of the site, if any.
+    pub fn insert(&mut self, site_id: u64, metadata: M) -> Option<M> {
+        return self.sites.insert(site_id, metadata);
+    }
+
+    /// Removes the metadata of `site_id`.
+    pub fn remove(&mut self, site_id: u64) -> Option<M> {
+        return self.sites.remove(&site_id);
+    }
+
+    /// Returns the metadata of `site_id`, if it is known.
+    pub fn get(&self, site_id: u64) -> Option<&M> {
+        return self.sites.get(&site_id);
+    }
+
+    /// Iterates over the known sites and their metadata.
+    pub fn iter(&self) -> impl Iterator<Item = (u64, &M)> + '_ {
+        return self
+            .sites
+            .iter()
+            .map(|(site_id, metadata)| (*site_id, metadata));
+    }
+
+    /// Returns the metadata of the site that inserted the elements of `blame`.
+    pub fn inserted_by(&self, blame: &Blame) -> Option<&M> {
+        return self.get(blame.inserted_by);
+    }
+
+    /// Returns the metadata of the site that last updated the elements of `blame`.
+    pub fn updated_by(&self, blame: &Blame) -> Option<&M> {
+        return self.get(blame.updated_by()?);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Text;
+
+    #[test]
+    fn test_blame_runs_and_updates() {
+        let mut text1 = Text::new(1, 1);
+        let mut text2 = Text::new(1, 2);
+        text2
+            .apply_batch(text1.insert_str(0, "hello").unwrap())
+            .unwrap();
+        text1
+            .apply_batch(text2.insert_str(5, " world").unwrap())
+            .unwrap();
+        text1.apply(text2.delete(5..6).unwrap()).unwrap();
+
+        let blame: Vec<Blame> = text1.rga().blame();
+        assert_eq!(blame.len(), 2);
+        assert_eq!(blame[0].value, "hello");
+        assert_eq!(blame[0].inserted_by, 1);
+        assert_eq!(blame[0].seqs, 1..6);
+        assert_eq!(blame[1].value, "world");
+        assert_eq!(blame[1].inserted_by, 2);
+        assert_eq!(blame[1].seqs, 2..7);
+        assert_eq!(blame[1].updated_by(), None);
+    }
+
+    #[test]
+    fn test_blame_moved_and_updated_elements() {
+        let mut rga1 = RGA::new(1, 1);
+        let mut rga2 = RGA::new(1, 2);
+        let a = rga1.local_insert("a".to_string(), None, None).unwrap();
+        let b = rga1
+            .local_insert("b".to_string(), Some(a.s4vector), None)
+            .unwrap();
+        rga2.remote_apply(a.clone());
+        rga2.remote_apply(b.clone());
+
+        rga1.remote_apply(rga2.local_update(a.s4vector, "A".to_string()).unwrap());
+        rga1.remote_apply(rga2.local_move(a.s4vector, Some(b.s4vector), None).unwrap());
+
+        let blame: Vec<Blame> = rga1.blame();
+        let values: Vec<&str> = blame.iter().map(|blame| blame.value.as_str()).collect();
+        assert_eq!(values, ["b", "A"]);
+        assert_eq!(blame[1].s4vector, a.s4vector);
+        assert_eq!(blame[1].inserted_by, 1);
+        assert_eq!(blame[1].updated_by(), Some(2));
+        assert_eq!(blame[1].updated.unwrap().seq, 1);
+
+        let mut authors: Authors = Authors::new();
+        authors.insert(2, "bob".to_string());
+        assert_eq!(authors.inserted_by(&blame[1]), None);
+        assert_eq!(authors.updated_by(&blame[1]).unwrap(), "bob");
+    }
+}
@@ -0,0 +1,192 @@
+use crate::oplog::OpLog;
+use crate::persistence::Snapshot;
+use crate::rga::rga::{BroadcastOperation, RGA};
+
+#[derive(Debug, thiserror::Error)]
+pub enum BranchError {
+    /// A branch needs a site ID of its own so its operations never clash with those
+    /// of another replica.
+    #[error("Site {0} already has operations in the document")]
+    SiteInUse(u64),
+    #[error("The branch does not log its operations")]
+    NotLogged,
+    /// The branch was forked from operations the main line has not integrated.
+    #[error("The branch was forked from a version the main line does not have")]
+    MissingBase,
+}
+
+impl RGA {
+    /// Creates a branch of the document: an independent replica with the current
+    /// state, whose edits only reach this replica through `merge_branch`.
+    ///
+    /// The branch logs every operation it integrates from the fork on, which is what
+    /// `unmerged` and `merge_branch` read.
+    ///
+    /// # Parameters
+    /// - `new_site_id`: The site ID of the branch, which must not have made any
+    ///   operation in the document.
+    ///
+    /// # Returns
+    /// The branch, or `BranchError::SiteInUse` if the site ID has already been used.
+    ///
+    /// # Example
+    /// ```
+    /// use crdt::rga::rga::RGA;
+    ///
+    /// let mut main = RGA::new(1, 1);
+    /// let a = main.local_insert("a".to_string(), None, None).unwrap();
+    ///
+    /// let mut draft = main.fork(2).unwrap();
+    /// draft.local_insert("b".to_string(), Some(a.s4vector), None).unwrap();
+    /// assert_eq!(main.read(), vec!["a".to_string()]);
+    ///
+    /// main.merge_branch(&draft).unwrap();
+    /// assert_eq!(main.read(), vec!["a".to_string(), "b".to_string()]);
+    /// ```
+    pub fn fork(&self, new_site_id: u64) -> Result<RGA, BranchError> {
+        if new_site_id == self.site_id() || self.version().get(new_site_id) > 0 {
+            return Err(BranchError::SiteInUse(new_site_id));
+        }
+
+        let snapshot: Snapshot = Snapshot {
+            site_id: new_site_id,
+            local_sequence: 0,
+            ..self.snapshot()
+        };
+        let mut branch: RGA = RGA::from_snapshot(snapshot);
+        *branch.log_slot() = Some(OpLog::starting_at(self.version().clone()));
+        return Ok(branch);
+    }
+
+    /// Lists the operations of `branch` that this replica has not integrated, in the
+    /// order the branch integrated them.
+    ///
+    /// # Returns
+    /// The operations, or `BranchError::NotLogged` if the branch has no log.
+    pub fn unmerged<'a>(
+        &self,
+        branch: &'a RGA,
+    ) -> Result<Vec<&'a BroadcastOperation>, BranchError> {
+        let log: &OpLog = branch.log().ok_or(BranchError::NotLogged)?;
+        return Ok(log
+            .iter()
+            .filter(|op| !self.version().contains(&op.id))
+            .collect());
+    }
+
+    /// Applies the operations of `branch` that this replica has not integrated.
+    ///
+    /// Merging is repeatable: once merged, the branch can keep being edited and
+    /// merged again. The branch is not changed, so merging the main line back into
+    /// it requires a log on this replica.
+    ///
+    /// # Parameters
+    /// - `branch`: A branch created by `fork`, or any replica with a log.
+    ///
+    /// # Returns
+    /// The applied operations, to broadcast to the other replicas of this line.
+    /// `BranchError::NotLogged` if the branch has no log, or
+    /// `BranchError::MissingBase` if the branch was forked from operations this
+    /// replica has not integrated, in which case nothing is applied.
+    pub fn merge_branch(&mut self, branch: &RGA) -> Result<Vec<BroadcastOperation>, BranchError> {
+        let base_known: bool = branch
+            .log()
+            .is_none_or(|log| self.version().dominates(log.base()));
+        if !base_known {
+            return Err(BranchError::MissingBase);
+        }
+
+        let operations: Vec<BroadcastOperation> =
+            self.unmerged(branch)?.into_iter().cloned().collect();
+        for op in &operations {
+            self.remote_apply(op.clone());
+        }
+        return Ok(operations);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::S4Vector;
+
+    #[test]
+    fn test_fork_is_independent() {
+        let mut main = RGA::new(1, 1);
+        let a = main.local_insert("a".to_string(), None, None).unwrap();
+        assert!(matches!(main.fork(1), Err(BranchError::SiteInUse(1))));
+
+        let mut draft: RGA = main.fork(2).unwrap();
+        assert!(matches!(draft.fork(1), Err(BranchError::SiteInUse(1))));
+        assert_eq!(draft.site_id(), 2);
+        assert_eq!(draft.read(), main.read());
+
+        let b = draft
+            .local_insert("b".to_string(), Some(a.s4vector), None)
+            .unwrap();
+        assert_eq!(b.id.sid, 2);
+        assert_eq!(b.id.seq, 1);
+        main.local_update(a.s4vector, "A".to_string()).unwrap();
+        assert_eq!(main.read(), vec!["A"]);
+        assert_eq!(draft.read(), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn test_merge_branch_applies_only_branch_operations() {
+        let mut main = RGA::new(1, 1);
+        main.enable_log();
+        let a = main.local_insert("a".to_string(), None, None).unwrap();
+        let mut draft: RGA = main.fork(2).unwrap();
+
+        let b = draft
+            .local_insert("b".to_string(), Some(a.s4vector), None)
+            .unwrap();
+        draft.local_delete(a.s4vector).unwrap();
+        main.local_insert("c".to_string(), None, None).unwrap();
+
+        let ids: Vec<S4Vector> = main
+            .unmerged(&draft)
+            .unwrap()
+            .iter()
+            .map(|op| op.id)
+            .collect();
+        assert_eq!(ids.len(), 2);
+        assert_eq!(ids[0], b.id);
+
+        let merged: Vec<BroadcastOperation> = main.merge_branch(&draft).unwrap();
+        assert_eq!(merged.len(), 2);
+        assert_eq!(main.read(), vec!["c", "b"]);
+        assert!(main.unmerged(&draft).unwrap().is_empty());
+        assert!(main.merge_branch(&draft).unwrap().is_empty());
+
+        // Merging again after more edits, then the main line back into the draft
+        draft
+            .local_insert("d".to_string(), Some(b.s4vector), None)
+            .unwrap();
+        assert_eq!(main.merge_branch(&draft).unwrap().len(), 1);
+        draft.merge_branch(&main).unwrap();
+        assert_eq!(draft.read(), main.read());
+        assert_eq!(main.read(), vec!["c", "b", "d"]);
+    }
+
+    #[test]
+    fn test_merge_branch_errors() {
+        let mut main = RGA::new(1, 1);
+        let other = RGA::new(1, 3);
+        assert!(matches!(main.unmerged(&other), Err(BranchError::NotLogged)));
+        assert!(matches!(
+            main.merge_branch(&other),
+            Err(BranchError::NotLogged)
+        ));
+
+        // A branch of a replica that is ahead of the main line
+        let mut ahead = RGA::new(1, 1);
+        ahead.local_insert("a".to_string(), None, None).unwrap();
+        let draft: RGA = ahead.fork(2).unwrap();
+        assert!(matches!(
+            main.merge_branch(&draft),
+            Err(BranchError::MissingBase)
+        ));
+        assert!(main.read().is_empty());
+    }
+}
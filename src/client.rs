@@ -0,0 +1,334 @@
+use crate::protocol::{Channel, Connection, Message, SyncError};
+use crate::rga::rga::{BroadcastOperation, RGA};
+use crate::{OperationBatch, VersionVector};
+use std::net::SocketAddr;
+use tokio::net::{TcpStream, ToSocketAddrs};
+
+/// `SyncClient` keeps an `RGA` in sync with the other replicas of a document through
+/// a `SyncServer`.
+///
+/// Local edits are made on the RGA returned by `rga_mut` and then passed to `send`.
+/// Batches from other replicas are applied by `receive`, or by `wait_for` until a
+/// given operation arrives. The client logs every operation its replica integrates,
+/// so after `reconnect` it can send the server every operation missing from the
+/// server's version, including edits made while the connection was down.
+#[derive(Debug)]
+pub struct SyncClient {
+    addr: SocketAddr,
+    document: String,
+    rga: RGA,
+    connection: Option<Connection>,
+}
+
+impl SyncClient {
+    /// Connects to the server at `addr` to edit `document`.
+    ///
+    /// # Parameters
+    /// - `addr`: The address of the server.
+    /// - `document`: The ID of the document.
+    /// - `rga`: The local replica, whose site ID identifies the client. Its operation
+    ///   log is enabled, and only operations integrated from then on are sent on
+    ///   `reconnect`.
+    ///
+    /// # Returns
+    /// `SyncError::Rejected` if another client with the same site ID is connected.
+    pub async fn connect(
+        addr: impl ToSocketAddrs,
+        document: &str,
+        mut rga: RGA,
+    ) -> Result<Self, SyncError> {
+        let addr: SocketAddr = tokio::net::lookup_host(addr)
+            .await?
+            .next()
+            .ok_or(SyncError::Disconnected)?;
+        rga.enable_log();
+        let mut client: SyncClient = SyncClient {
+            addr,
+            document: document.to_string(),
+            rga,
+            connection: None,
+        };
+        client.reconnect().await?;
+        return Ok(client);
+    }
+
+    /// Opens a new connection to the server, sending in one batch every logged
+    /// operation not covered by the server's version. Batches the client has not seen
+    /// are received afterwards by `receive`.
+    pub async fn reconnect(&mut self) -> Result<(), SyncError> {
+        self.connection = None;
+        let mut connection: Connection = Connection::new(TcpStream::connect(self.addr).await?);
+        connection
+            .send(&Message::Hello {
+                document: self.document.clone(),
+                site_id: self.rga.site_id(),
+                version: self.rga.version().clone(),
+            })
+            .await?;
+
+        let version: VersionVector = match connection.receive().await? {
+            Some(Message::Welcome { version }) => version,
+            Some(Message::Rejected { reason }) => return Err(SyncError::Rejected(reason)),
+            Some(_) => return Err(SyncError::UnexpectedMessage),
+            None => return Err(SyncError::Disconnected),
+        };
+
+        let operations: Vec<BroadcastOperation> = match self.rga.log() {
+            Some(log) => log
+                .iter()
+                .filter(|op| !version.contains(&op.id))
+                .cloned()
+                .collect(),
+            None => Vec::new(),
+        };
+        if !operations.is_empty() {
+            connection
+                .send(&Message::Batch {
+                    batch: OperationBatch { operations },
+                })
+                .await?;
+        }
+        self.connection = Some(connection);
+        return Ok(());
+    }
+
+    /// Returns the local replica.
+    pub fn rga(&self) -> &RGA {
+        return &self.rga;
+    }
+
+    /// Returns the local replica to make local edits, which must then be passed to
+    /// `send`.
+    pub fn rga_mut(&mut self) -> &mut RGA {
+        return &mut self.rga;
+    }
+
+    /// Checks whether the client has a connection to the server.
+    pub fn is_connected(&self) -> bool {
+        return self.connection.is_some();
+    }
+
+    /// Sends the batch of a local edit to the server.
+    ///
+    /// # Returns
+    /// An error if the connection failed, in which case the batch is sent on the
+    /// next `reconnect`.
+    pub async fn send(&mut self, batch: OperationBatch) -> Result<(), SyncError> {
+        let connection: &mut Connection =
+            self.connection.as_mut().ok_or(SyncError::Disconnected)?;
+        let result: Result<(), SyncError> = connection.send(&Message::Batch { batch }).await;
+        if result.is_err() {
+            self.connection = None;
+        }
+        return result;
+    }
+
+    /// Waits for the next batch from another replica and applies it.
+    ///
+    /// # Returns
+    /// The batch, or `SyncError::Disconnected` once the server closes the connection.
+    pub async fn receive(&mut self) -> Result<OperationBatch, SyncError> {
+        let connection: &mut Connection =
+            self.connection.as_mut().ok_or(SyncError::Disconnected)?;
+        let message: Result<Option<Message>, SyncError> = connection.receive().await;
+
+        let batch: OperationBatch = match message {
+            Ok(Some(Message::Batch { batch })) => batch,
+            Ok(Some(_)) => return Err(SyncError::UnexpectedMessage),
+            Ok(None) => {
+                self.connection = None;
+                return Err(SyncError::Disconnected);
+            }
+            Err(error) => {
+                self.connection = None;
+                return Err(error);
+            }
+        };
+
+        self.rga.remote_apply_batch(batch.clone());
+        return Ok(batch);
+    }
+
+    /// Receives batches until the operation `op` has been integrated.
+    pub async fn wait_for(&mut self, op: &BroadcastOperation) -> Result<(), SyncError> {
+        while !self.rga.version().contains(&op.id) {
+            self.receive().await?;
+        }
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{S4Vector, SyncServer};
+
+    async fn start() -> (SyncServer, SocketAddr) {
+        let server: SyncServer = SyncServer::bind("127.0.0.1:0").await.unwrap();
+        let addr: SocketAddr = server.local_addr().unwrap();
+        tokio::spawn(server.clone().run());
+        return (server, addr);
+    }
+
+    async fn insert(client: &mut SyncClient, value: &str) -> BroadcastOperation {
+        let left: Option<S4Vector> = client.rga().iter_with_ids().last().map(|(id, _)| id);
+        let op: BroadcastOperation = client
+            .rga_mut()
+            .local_insert(value.to_string(), left, None)
+            .unwrap();
+        client.send(op.clone().into()).await.unwrap();
+        return op;
+    }
+
+    #[tokio::test]
+    async fn test_clients_converge_through_server() {
+        let (server, addr) = start().await;
+        let mut alice = SyncClient::connect(addr, "doc", RGA::new(1, 1))
+            .await
+            .unwrap();
+        let mut bob = SyncClient::connect(addr, "doc", RGA::new(1, 2))
+            .await
+            .unwrap();
+
+        let a = insert(&mut alice, "a").await;
+        let b = insert(&mut bob, "b").await;
+        alice.wait_for(&b).await.unwrap();
+        bob.wait_for(&a).await.unwrap();
+
+        let batch = bob
+            .rga_mut()
+            .transaction(|tx| {
+                tx.delete(a.s4vector)?;
+                tx.update(b.s4vector, "B".to_string())
+            })
+            .unwrap();
+        let last = batch.operations.last().unwrap().clone();
+        bob.send(batch).await.unwrap();
+        alice.wait_for(&last).await.unwrap();
+
+        assert_eq!(alice.rga().read(), bob.rga().read());
+        assert_eq!(server.read("doc"), Some(alice.rga().read()));
+        assert_eq!(server.log("doc").len(), 3);
+        assert_eq!(server.read("other"), None);
+    }
+
+    #[tokio::test]
+    async fn test_late_client_receives_log() {
+        let (_server, addr) = start().await;
+        let mut alice = SyncClient::connect(addr, "doc", RGA::new(1, 1))
+            .await
+            .unwrap();
+        let mut last: Option<BroadcastOperation> = None;
+        for c in ["x", "y", "z"] {
+            last = Some(insert(&mut alice, c).await);
+        }
+
+        let mut carol = SyncClient::connect(addr, "doc", RGA::new(1, 3))
+            .await
+            .unwrap();
+        carol.wait_for(&last.unwrap()).await.unwrap();
+        assert_eq!(carol.rga().read(), alice.rga().read());
+
+        // Documents are independent
+        let dave = SyncClient::connect(addr, "other", RGA::new(1, 4))
+            .await
+            .unwrap();
+        assert!(dave.rga().read().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_site_is_rejected() {
+        let (_server, addr) = start().await;
+        let _alice = SyncClient::connect(addr, "doc", RGA::new(1, 1))
+            .await
+            .unwrap();
+
+        let result = SyncClient::connect(addr, "doc", RGA::new(1, 1)).await;
+        assert!(matches!(result, Err(SyncError::Rejected(_))));
+        let result = SyncClient::connect(addr, "doc", RGA::new(1, 0)).await;
+        assert!(matches!(result, Err(SyncError::Rejected(_))));
+        assert!(SyncClient::connect(addr, "other", RGA::new(1, 1))
+            .await
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_reconnect_exchanges_missing_batches() {
+        let (server, addr) = start().await;
+        let mut alice = SyncClient::connect(addr, "doc", RGA::new(1, 1))
+            .await
+            .unwrap();
+        let mut bob = SyncClient::connect(addr, "doc", RGA::new(1, 2))
+            .await
+            .unwrap();
+        let a = insert(&mut alice, "a").await;
+        bob.wait_for(&a).await.unwrap();
+
+        // Alice edits offline while Bob keeps editing
+        alice.connection = None;
+        let offline = insert_offline(&mut alice, "offline").await;
+        let online = insert(&mut bob, "online").await;
+
+        // Wait for the server to release Alice's site ID
+        let mut reconnected = alice.reconnect().await;
+        while let Err(SyncError::Rejected(_)) = reconnected {
+            tokio::task::yield_now().await;
+            reconnected = alice.reconnect().await;
+        }
+        reconnected.unwrap();
+
+        alice.wait_for(&online).await.unwrap();
+        bob.wait_for(&offline).await.unwrap();
+        assert_eq!(alice.rga().read(), bob.rga().read());
+        assert_eq!(server.read("doc"), Some(bob.rga().read()));
+    }
+
+    #[tokio::test]
+    async fn test_reconnect_sends_operations_by_version() {
+        let (server, addr) = start().await;
+        let mut alice = SyncClient::connect(addr, "doc", RGA::new(1, 1))
+            .await
+            .unwrap();
+        let a = insert(&mut alice, "a").await;
+
+        // Edits made offline and never passed to `send`
+        alice.connection = None;
+        let batch = alice
+            .rga_mut()
+            .transaction(|tx| {
+                tx.update(a.s4vector, "A".to_string())?;
+                tx.insert("b".to_string(), Some(a.s4vector), None)?;
+                Ok(())
+            })
+            .unwrap();
+
+        let mut reconnected = alice.reconnect().await;
+        while let Err(SyncError::Rejected(_)) = reconnected {
+            tokio::task::yield_now().await;
+            reconnected = alice.reconnect().await;
+        }
+        reconnected.unwrap();
+
+        let mut bob = SyncClient::connect(addr, "doc", RGA::new(1, 2))
+            .await
+            .unwrap();
+        bob.wait_for(batch.operations.last().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(bob.rga().read(), vec!["A".to_string(), "b".to_string()]);
+        assert_eq!(server.read("doc"), Some(bob.rga().read()));
+    }
+
+    /// Makes an edit while disconnected, which fails to send but is kept.
+    async fn insert_offline(client: &mut SyncClient, value: &str) -> BroadcastOperation {
+        let op: BroadcastOperation = client
+            .rga_mut()
+            .local_insert(value.to_string(), None, None)
+            .unwrap();
+        assert!(matches!(
+            client.send(op.clone().into()).await,
+            Err(SyncError::Disconnected)
+        ));
+        return op;
+    }
+}
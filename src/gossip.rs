@@ -0,0 +1,292 @@
+use crate::network::{Rng, Transport};
+use crate::rga::rga::{BroadcastOperation, RGA};
+use crate::VersionVector;
+use serde::{Deserialize, Serialize};
+
+/// A message exchanged by `Gossip` replicas.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum GossipMessage {
+    /// Starts a round with the version of the replica that picked the peer.
+    Digest { version: VersionVector },
+    /// Answers a digest with the operations the initiator is missing and the version
+    /// of the peer, so the initiator can push back what the peer is missing.
+    Reply {
+        operations: Vec<BroadcastOperation>,
+        version: VersionVector,
+    },
+    /// Ends a round with the operations the peer is missing.
+    Push { operations: Vec<BroadcastOperation> },
+}
+
+/// `Gossip` keeps an `RGA` in sync with its peers without a central server, by
+/// periodically reconciling with a random peer.
+///
+/// Each call to `round` sends the replica's version vector to a random peer, which
+/// answers with the operations the replica is missing and its own version, and the
+/// replica then pushes the operations the peer is missing. Operations are read from
+/// the replica's `OpLog`, so every edit integrated after `new`, local or remote, is
+/// gossiped. Since every round compares full version vectors, lost or duplicated
+/// messages and partitions only delay convergence: once the network heals, every
+/// operation eventually reaches every replica.
+///
+/// # Example
+/// ```
+/// use crdt::rga::rga::RGA;
+/// use crdt::{Gossip, GossipMessage, NetworkConfig, SimulatedNetwork};
+///
+/// let network: SimulatedNetwork<GossipMessage> = SimulatedNetwork::new(1, NetworkConfig::reliable());
+/// let mut a = Gossip::new(RGA::new(1, 1), network.endpoint(1), vec![2], 1);
+/// let mut b = Gossip::new(RGA::new(1, 2), network.endpoint(2), vec![1], 2);
+///
+/// a.rga_mut().local_insert("x".to_string(), None, None).unwrap();
+///
+/// b.round();
+/// while network.in_flight() > 0 {
+///     network.advance(1);
+///     a.receive_all();
+///     b.receive_all();
+/// }
+/// assert_eq!(b.rga().read(), vec!["x".to_string()]);
+/// ```
+#[derive(Debug)]
+pub struct Gossip<T: Transport<GossipMessage>> {
+    rga: RGA,
+    transport: T,
+    peers: Vec<u64>,
+    rng: Rng,
+}
+
+impl<T: Transport<GossipMessage>> Gossip<T> {
+    /// Creates a gossip replica.
+    ///
+    /// # Parameters
+    /// - `rga`: The local replica. Its operation log is enabled, and only operations
+    ///   integrated from then on are gossiped.
+    /// - `transport`: The endpoint of the replica.
+    /// - `peers`: The site IDs of the replicas to gossip with.
+    /// - `seed`: Seeds the choice of peers, so simulations can be replayed.
+    pub fn new(mut rga: RGA, transport: T, peers: Vec<u64>, seed: u64) -> Self {
+        rga.enable_log();
+        return Gossip {
+            rga,
+            transport,
+            peers,
+            rng: Rng::new(seed),
+        };
+    }
+
+    /// Returns the local replica.
+    pub fn rga(&self) -> &RGA {
+        return &self.rga;
+    }
+
+    /// Returns the local replica to make local edits, which are sent to peers in
+    /// later rounds.
+    pub fn rga_mut(&mut self) -> &mut RGA {
+        return &mut self.rga;
+    }
+
+    /// Starts a round with a random peer.
+    ///
+    /// # Returns
+    /// The site ID of the peer, or `None` if the replica has no peers.
+    pub fn round(&mut self) -> Option<u64> {
+        if self.peers.is_empty() {
+            return None;
+        }
+
+        let peer: u64 = self.peers[self.rng.range(0, self.peers.len() as u64 - 1) as usize];
+        let version: VersionVector = self.rga.version().clone();
+        // A lost digest is made up for by later rounds
+        let _ = self.transport.send(peer, GossipMessage::Digest { version });
+        return Some(peer);
+    }
+
+    /// Handles every message delivered to the replica.
+    pub fn receive_all(&mut self) {
+        while let Some(envelope) = self.transport.receive() {
+            match envelope.payload {
+                GossipMessage::Digest { version } => {
+                    let reply: GossipMessage = GossipMessage::Reply {
+                        operations: self.missing(&version),
+                        version: self.rga.version().clone(),
+                    };
+                    let _ = self.transport.send(envelope.from, reply);
+                }
+                GossipMessage::Reply {
+                    operations,
+                    version,
+                } => {
+                    self.apply(operations);
+                    let operations: Vec<BroadcastOperation> = self.missing(&version);
+                    if !operations.is_empty() {
+                        let _ = self
+                            .transport
+                            .send(envelope.from, GossipMessage::Push { operations });
+                    }
+                }
+                GossipMessage::Push { operations } => self.apply(operations),
+            }
+        }
+    }
+
+    /// Returns the logged operations not covered by `version`, in the order they were
+    /// integrated.
+    fn missing(&self, version: &VersionVector) -> Vec<BroadcastOperation> {
+        return match self.rga.log() {
+            Some(log) => log
+                .iter()
+                .filter(|op| !version.contains(&op.id))
+                .cloned()
+                .collect(),
+            None => Vec::new(),
+        };
+    }
+
+    /// Applies operations received from a peer. Operations already integrated are
+    /// ignored by the RGA.
+    fn apply(&mut self, operations: Vec<BroadcastOperation>) {
+        for op in operations {
+            self.rga.remote_apply(op);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::{NetworkConfig, SimulatedNetwork, SimulatedTransport};
+    use crate::S4Vector;
+
+    type Replica = Gossip<SimulatedTransport<GossipMessage>>;
+
+    fn replicas(network: &SimulatedNetwork<GossipMessage>, count: u64) -> Vec<Replica> {
+        return (1..=count)
+            .map(|site_id| {
+                let peers: Vec<u64> = (1..=count).filter(|peer| *peer != site_id).collect();
+                Gossip::new(
+                    RGA::new(1, site_id),
+                    network.endpoint(site_id),
+                    peers,
+                    site_id,
+                )
+            })
+            .collect();
+    }
+
+    fn insert(replica: &mut Replica, rng: &mut Rng) {
+        let ids: Vec<S4Vector> = replica.rga().iter_with_ids().map(|(id, _)| id).collect();
+        let left: Option<S4Vector> = match rng.range(0, ids.len() as u64) {
+            0 => None,
+            i => Some(ids[i as usize - 1]),
+        };
+        let value: String = replica.rga().site_id().to_string();
+        replica.rga_mut().local_insert(value, left, None).unwrap();
+    }
+
+    /// Runs a round on every replica and delivers the messages that are due.
+    fn step(network: &SimulatedNetwork<GossipMessage>, replicas: &mut [Replica]) {
+        for replica in replicas.iter_mut() {
+            replica.round();
+        }
+        network.advance(1);
+        for replica in replicas.iter_mut() {
+            replica.receive_all();
+        }
+    }
+
+    fn converged(replicas: &[Replica]) -> bool {
+        return replicas.windows(2).all(|pair| {
+            pair[0].rga().version() == pair[1].rga().version()
+                && pair[0].rga().read() == pair[1].rga().read()
+        });
+    }
+
+    #[test]
+    fn test_round_pushes_and_pulls() {
+        let network: SimulatedNetwork<GossipMessage> =
+            SimulatedNetwork::new(1, NetworkConfig::reliable());
+        let mut replicas: Vec<Replica> = replicas(&network, 2);
+        let mut rng: Rng = Rng::new(1);
+        insert(&mut replicas[0], &mut rng);
+        insert(&mut replicas[1], &mut rng);
+
+        // A single round started by either side exchanges both edits
+        assert_eq!(replicas[0].round(), Some(2));
+        while network.in_flight() > 0 {
+            network.advance(1);
+            for replica in replicas.iter_mut() {
+                replica.receive_all();
+            }
+        }
+        assert!(converged(&replicas));
+        assert_eq!(replicas[0].rga().read().len(), 2);
+    }
+
+    #[test]
+    fn test_messages_round_trip_through_json() {
+        let mut rga: RGA = RGA::new(1, 1);
+        let op: BroadcastOperation = rga.local_insert("a".to_string(), None, None).unwrap();
+        let message: GossipMessage = GossipMessage::Reply {
+            operations: vec![op],
+            version: rga.version().clone(),
+        };
+
+        let json: String = serde_json::to_string(&message).unwrap();
+        let restored: GossipMessage = serde_json::from_str(&json).unwrap();
+        let GossipMessage::Reply {
+            operations,
+            version,
+        } = restored
+        else {
+            panic!("expected a reply, got {restored:?}");
+        };
+        let mut peer: RGA = RGA::new(1, 2);
+        for op in operations {
+            peer.remote_apply(op);
+        }
+        assert_eq!(peer.read(), rga.read());
+        assert_eq!(&version, rga.version());
+    }
+
+    #[test]
+    fn test_fifty_replicas_converge_after_partitions() {
+        let config: NetworkConfig = NetworkConfig {
+            min_latency: 1,
+            max_latency: 5,
+            duplicate_probability: 0.1,
+            loss_probability: 0.1,
+        };
+        let network: SimulatedNetwork<GossipMessage> = SimulatedNetwork::new(50, config);
+        let mut replicas: Vec<Replica> = replicas(&network, 50);
+        let mut rng: Rng = Rng::new(50);
+
+        let left: Vec<u64> = (1..=25).collect();
+        let right: Vec<u64> = (26..=50).collect();
+        let odd: Vec<u64> = (1..=50).filter(|site| site % 2 == 1).collect();
+        let even: Vec<u64> = (1..=50).filter(|site| site % 2 == 0).collect();
+
+        for round in 0..60 {
+            match round {
+                0 => network.partition(&[&left, &right]),
+                30 => network.partition(&[&odd, &even]),
+                _ => {}
+            }
+            for _ in 0..5 {
+                let replica: usize = rng.range(0, 49) as usize;
+                insert(&mut replicas[replica], &mut rng);
+            }
+            step(&network, &mut replicas);
+        }
+        assert!(!converged(&replicas));
+
+        network.heal();
+        let mut rounds: usize = 0;
+        while !converged(&replicas) {
+            assert!(rounds < 100, "replicas did not converge");
+            step(&network, &mut replicas);
+            rounds += 1;
+        }
+        assert_eq!(replicas[0].rga().read().len(), 300);
+    }
+}
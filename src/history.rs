@@ -0,0 +1,471 @@
+use crate::persistence::Snapshot;
+use crate::rga::rga::{Node, RGA};
+use crate::{BranchError, NodeState, S4Vector, VersionVector};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// The deletes and values an element has had, kept in a `Snapshot` so a restored
+/// replica can still read past versions.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ElementHistory {
+    pub s4vector: S4Vector,
+    /// The `S4Vector`s of the operations that deleted the element.
+    pub deletes: BTreeSet<S4Vector>,
+    /// The values of the element with the `S4Vector` of the update that set them, in
+    /// order. A list, as JSON only has string keys.
+    pub values: Vec<(S4Vector, String)>,
+}
+
+impl Node {
+    /// Checks whether the node had been deleted as of `version`.
+    ///
+    /// A tombstone merged by `Reconciliation` is recorded as deleted by the element's
+    /// own id, so it reads as deleted at every version that contains the element.
+    fn deleted_at(&self, version: &VersionVector) -> bool {
+        if self.deletes.is_empty() {
+            return self.tombstone;
+        }
+        return self.deletes.iter().any(|id| version.contains(id));
+    }
+
+    /// Returns the latest update of the element covered by `version`, if any.
+    fn updated_at(&self, version: &VersionVector) -> Option<S4Vector> {
+        return self
+            .values
+            .keys()
+            .rev()
+            .find(|id| **id != self.s4vector && version.contains(id))
+            .copied();
+    }
+
+    /// Returns the value of the element at `offset` in the node as of `version`.
+    fn value_at(&self, offset: usize, version: &VersionVector) -> String {
+        if self.values.is_empty() {
+            return match self.len {
+                1 => self.value.clone(),
+                _ => self
+                    .value
+                    .chars()
+                    .nth(offset)
+                    .map(String::from)
+                    .unwrap_or_default(),
+            };
+        }
+        return self
+            .values
+            .iter()
+            .rev()
+            .find(|(id, _)| version.contains(id))
+            .map_or_else(|| self.value.clone(), |(_, value)| value.clone());
+    }
+}
+
+impl RGA {
+    /// Reads the document as it was at `version`, the visible values in order.
+    ///
+    /// Only the effects of operations covered by `version` are shown: elements
+    /// inserted later are hidden, elements deleted later are shown, and every element
+    /// has the value of its latest update covered by `version` at the position of its
+    /// latest move covered by `version`. Operations the replica has not integrated
+    /// yet are ignored.
+    ///
+    /// # Parameters
+    /// - `version`: The version to read, typically the version of the replica at some
+    ///   earlier point.
+    ///
+    /// # Returns
+    /// The values visible at `version`, as `read()` would have returned them.
+    ///
+    /// # Example
+    /// ```
+    /// use crdt::rga::rga::RGA;
+    ///
+    /// let mut rga = RGA::new(1, 1);
+    /// let a = rga.local_insert("a".to_string(), None, None).unwrap();
+    /// let version = rga.version().clone();
+    /// rga.local_update(a.s4vector, "A".to_string()).unwrap();
+    /// rga.local_insert("b".to_string(), Some(a.s4vector), None).unwrap();
+    ///
+    /// assert_eq!(rga.read_at(&version), vec!["a".to_string()]);
+    /// assert_eq!(rga.read(), vec!["A".to_string(), "b".to_string()]);
+    /// ```
+    pub fn read_at(&self, version: &VersionVector) -> Vec<String> {
+        let version: VersionVector = self.clamp(version);
+        let mut values: Vec<String> = Vec::new();
+
+        let mut key: Option<S4Vector> = self.head();
+        while let Some(current) = key {
+            let node: &Node = self.node(&current).unwrap();
+            for i in 0..node.len {
+                let position: S4Vector = node.s4vector.offset(i as u64);
+                let element: S4Vector = match node.target {
+                    Some(target) => target.offset(i as u64),
+                    None => position,
+                };
+                if !version.contains(&position) || self.position_at(&element, &version) != position
+                {
+                    continue;
+                }
+                if let Some(value) = self.element_value_at(&element, &version) {
+                    values.push(value);
+                }
+            }
+            key = node.right;
+        }
+        return values;
+    }
+
+    /// Creates a replica holding the document as it was at `version`.
+    ///
+    /// The replica has the same session id and integrates the operations after
+    /// `version` like any other replica, so it can be used to inspect or branch from
+    /// an old state. Deletes and updates after `version` are not carried over. Like a
+    /// branch created by `fork`, it edits under a site ID of its own, so its
+    /// operations and this replica's can be exchanged without clashing.
+    ///
+    /// # Parameters
+    /// - `version`: The version to check out. Operations the replica has not
+    ///   integrated yet are ignored.
+    /// - `new_site_id`: The site ID of the new replica, which must not have made any
+    ///   operation in the document.
+    ///
+    /// # Returns
+    /// A new `RGA` whose `read()` equals `read_at(version)`, or
+    /// `BranchError::SiteInUse` if the site ID has already been used.
+    ///
+    /// # Example
+    /// ```
+    /// use crdt::rga::rga::RGA;
+    ///
+    /// let mut rga = RGA::new(1, 1);
+    /// let a = rga.local_insert("a".to_string(), None, None).unwrap();
+    /// let version = rga.version().clone();
+    /// let delete = rga.local_delete(a.s4vector).unwrap();
+    ///
+    /// let mut old = rga.checkout(&version, 2).unwrap();
+    /// assert_eq!(old.read(), vec!["a".to_string()]);
+    /// old.remote_apply(delete);
+    /// assert_eq!(old.read(), rga.read());
+    /// ```
+    pub fn checkout(&self, version: &VersionVector, new_site_id: u64) -> Result<RGA, BranchError> {
+        if new_site_id == self.site_id() || self.version().get(new_site_id) > 0 {
+            return Err(BranchError::SiteInUse(new_site_id));
+        }
+
+        let version: VersionVector = self.clamp(version);
+        let nodes: Vec<NodeState> = self
+            .node_states()
+            .into_iter()
+            .filter(|state| version.contains(&state.s4vector))
+            .map(|mut state| {
+                if state.target.is_none() {
+                    let node: &Node = self.node_containing(&state.s4vector).unwrap();
+                    state.tombstone = node.deleted_at(&version);
+                    state.updated = node.updated_at(&version);
+                    state.value =
+                        node.value_at((state.s4vector.seq - node.s4vector.seq) as usize, &version);
+                }
+                state
+            })
+            .collect();
+        let history: Vec<ElementHistory> = self
+            .history()
+            .into_iter()
+            .filter(|element| version.contains(&element.s4vector))
+            .map(|mut element| {
+                element.deletes.retain(|id| version.contains(id));
+                element.values.retain(|(id, _)| version.contains(id));
+                element
+            })
+            .collect();
+
+        return Ok(RGA::from_snapshot(Snapshot {
+            session_id: self.session_id(),
+            site_id: new_site_id,
+            local_sequence: 0,
+            version,
+            out_of_order: BTreeMap::new(),
+            nodes,
+            buffer: Vec::new(),
+            history,
+        }));
+    }
+
+    /// Returns the history of every element that has been deleted or updated.
+    pub(crate) fn history(&self) -> Vec<ElementHistory> {
+        return self
+            .nodes()
+            .filter(|node| node.target.is_none())
+            .filter(|node| !node.deletes.is_empty() || !node.values.is_empty())
+            .flat_map(|node| {
+                (0..node.len).map(move |i| ElementHistory {
+                    s4vector: node.s4vector.offset(i as u64),
+                    deletes: node.deletes.clone(),
+                    values: node
+                        .values
+                        .iter()
+                        .map(|(id, value)| (*id, value.clone()))
+                        .collect(),
+                })
+            })
+            .collect();
+    }
+
+    /// Replaces the history of the elements in `history`, which have been integrated.
+    pub(crate) fn restore_history(&mut self, history: Vec<ElementHistory>) {
+        for element in history {
+            if let Some(node) = self.isolate(&element.s4vector) {
+                node.deletes = element.deletes;
+                node.values = element.values.into_iter().collect();
+            }
+        }
+    }
+
+    /// Returns the value of an element as of `version`, or `None` if it had not been
+    /// inserted or had been deleted.
+    fn element_value_at(&self, s4vector: &S4Vector, version: &VersionVector) -> Option<String> {
+        let node: &Node = self.node_containing(s4vector)?;
+        if !version.contains(s4vector) || node.deleted_at(version) {
+            return None;
+        }
+        return Some(node.value_at((s4vector.seq - node.s4vector.seq) as usize, version));
+    }
+
+    /// Limits `version` to the operations the replica has integrated in order.
+    fn clamp(&self, version: &VersionVector) -> VersionVector {
+        let mut clamped: VersionVector = VersionVector::new();
+        for (sid, seq) in version.iter() {
+            clamped.set(sid, seq.min(self.version().get(sid)));
+        }
+        return clamped;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::Rng;
+    use crate::rga::rga::BroadcastOperation;
+    use std::collections::VecDeque;
+
+    #[test]
+    fn test_read_at_past_versions() {
+        let mut rga = RGA::new(1, 1);
+        let mut versions: Vec<(VersionVector, Vec<String>)> = vec![(VersionVector::new(), vec![])];
+        let mut record = |rga: &RGA| versions.push((rga.version().clone(), rga.read()));
+
+        let a = rga.local_insert("a".to_string(), None, None).unwrap();
+        let b = rga
+            .local_insert("b".to_string(), Some(a.s4vector), None)
+            .unwrap();
+        let c = rga
+            .local_insert("c".to_string(), Some(b.s4vector), None)
+            .unwrap();
+        record(&rga);
+        rga.local_update(b.s4vector, "B".to_string()).unwrap();
+        record(&rga);
+        rga.local_move(c.s4vector, None, Some(a.s4vector)).unwrap();
+        record(&rga);
+        rga.local_update(b.s4vector, "β".to_string()).unwrap();
+        rga.local_delete(a.s4vector).unwrap();
+        record(&rga);
+        rga.local_delete_range(b.s4vector, c.s4vector).unwrap();
+        record(&rga);
+
+        assert_eq!(versions[3].1, vec!["c", "a", "B"]);
+        for (version, read) in &versions {
+            assert_eq!(&rga.read_at(version), read);
+            assert_eq!(&rga.checkout(version, 9).unwrap().read(), read);
+        }
+    }
+
+    #[test]
+    fn test_update_concurrent_with_delete() {
+        let mut rga1 = RGA::new(1, 1);
+        let mut rga2 = RGA::new(1, 2);
+        let a = rga1.local_insert("a".to_string(), None, None).unwrap();
+        rga2.remote_apply(a.clone());
+
+        let update = rga1.local_update(a.s4vector, "A".to_string()).unwrap();
+        let delete = rga2.local_delete(a.s4vector).unwrap();
+        rga1.remote_apply(delete);
+        rga2.remote_apply(update);
+        assert!(rga1.read().is_empty());
+
+        let mut version: VersionVector = VersionVector::new();
+        version.set(1, 2);
+        assert_eq!(rga1.read_at(&version), vec!["A"]);
+        assert_eq!(rga2.read_at(&version), vec!["A"]);
+        version.set(1, 1);
+        version.set(2, 1);
+        assert!(rga1.read_at(&version).is_empty());
+        assert_eq!(
+            rga2.checkout(&VersionVector::new(), 9)
+                .unwrap()
+                .read()
+                .len(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_update_after_delete_reads_the_same_everywhere() {
+        let mut rga1 = RGA::new(1, 1);
+        let mut rga2 = RGA::new(1, 2);
+        let a = rga1.local_insert("a".to_string(), None, None).unwrap();
+        rga2.remote_apply(a.clone());
+
+        // The update is made on a replica that has already seen the delete
+        let delete = rga2.local_delete(a.s4vector).unwrap();
+        rga1.remote_apply(delete);
+        let update = rga1.local_update(a.s4vector, "NEW".to_string()).unwrap();
+        assert_eq!(update.value.as_deref(), Some("NEW"));
+        rga2.remote_apply(update);
+        assert!(rga1.read().is_empty() && rga2.read().is_empty());
+
+        let mut version: VersionVector = VersionVector::new();
+        version.set(1, 2);
+        assert_eq!(rga1.read_at(&version), vec!["NEW"]);
+        assert_eq!(rga2.read_at(&version), rga1.read_at(&version));
+        assert_eq!(
+            rga2.checkout(&version, 9).unwrap().read(),
+            rga1.checkout(&version, 9).unwrap().read()
+        );
+    }
+
+    #[test]
+    fn test_checkout_integrates_later_operations() {
+        let mut rga = RGA::new(1, 1);
+        let a = rga.local_insert("a".to_string(), None, None).unwrap();
+        let b = rga
+            .local_insert("b".to_string(), Some(a.s4vector), None)
+            .unwrap();
+        let version: VersionVector = rga.version().clone();
+
+        let later: Vec<BroadcastOperation> = vec![
+            rga.local_update(a.s4vector, "A".to_string()).unwrap(),
+            rga.local_move(a.s4vector, Some(b.s4vector), None).unwrap(),
+            rga.local_delete(b.s4vector).unwrap(),
+        ];
+
+        let mut old: RGA = rga.checkout(&version, 2).unwrap();
+        assert_eq!(old.version(), &version);
+        assert_eq!(old.read(), vec!["a", "b"]);
+        for op in later {
+            old.remote_apply(op);
+        }
+        assert_eq!(old.read(), rga.read());
+        assert_eq!(old.read_at(&version), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn test_checkout_exchanges_edits_with_replica() {
+        let mut rga = RGA::new(1, 1);
+        let a = rga.local_insert("a".to_string(), None, None).unwrap();
+        let version: VersionVector = rga.version().clone();
+        let b = rga
+            .local_insert("b".to_string(), Some(a.s4vector), None)
+            .unwrap();
+
+        assert!(matches!(
+            rga.checkout(&version, 1),
+            Err(BranchError::SiteInUse(1))
+        ));
+        let mut old: RGA = rga.checkout(&version, 2).unwrap();
+
+        // Both replicas edit after "a", which used to give both the same S4Vector
+        let x = old
+            .local_insert("x".to_string(), Some(a.s4vector), None)
+            .unwrap();
+        let y = rga
+            .local_insert("y".to_string(), Some(a.s4vector), None)
+            .unwrap();
+        assert_ne!(x.s4vector, y.s4vector);
+
+        old.remote_apply(b);
+        old.remote_apply(y);
+        rga.remote_apply(x);
+        assert_eq!(old.read(), rga.read());
+        assert_eq!(old.read().len(), 4);
+        assert_eq!(old.version(), rga.version());
+    }
+
+    #[test]
+    fn test_snapshot_keeps_history() {
+        let mut rga = RGA::new(1, 1);
+        let a = rga.local_insert("a".to_string(), None, None).unwrap();
+        let version: VersionVector = rga.version().clone();
+        rga.local_update(a.s4vector, "A".to_string()).unwrap();
+        let updated: VersionVector = rga.version().clone();
+        rga.local_delete(a.s4vector).unwrap();
+
+        let snapshot: Snapshot = rga.snapshot();
+        assert_eq!(snapshot.history.len(), 1);
+        let json: String = serde_json::to_string(&snapshot).unwrap();
+        let restored: RGA = RGA::from_snapshot(serde_json::from_str(&json).unwrap());
+        assert!(restored.read().is_empty());
+        assert_eq!(restored.read_at(&version), vec!["a"]);
+        assert_eq!(restored.read_at(&updated), vec!["A"]);
+    }
+
+    #[test]
+    fn test_random_histories() {
+        for seed in 0..50 {
+            let mut rng: Rng = Rng::new(seed);
+            let mut replicas: Vec<RGA> = (1..=2).map(|site_id| RGA::new(1, site_id)).collect();
+            // The operations each replica has yet to receive, in the order they were made
+            let mut pending: Vec<VecDeque<BroadcastOperation>> = vec![VecDeque::new(); 2];
+            let mut history: Vec<Vec<(VersionVector, Vec<String>)>> = vec![Vec::new(); 2];
+
+            for _ in 0..60 {
+                let site: usize = rng.range(0, 1) as usize;
+                if rng.chance(0.3) {
+                    if let Some(op) = pending[site].pop_front() {
+                        replicas[site].remote_apply(op);
+                    }
+                } else {
+                    let rga: &mut RGA = &mut replicas[site];
+                    let ids: Vec<S4Vector> = rga.iter_with_ids().map(|(id, _)| id).collect();
+                    let len: u64 = ids.len() as u64;
+                    let op: BroadcastOperation = match rng.range(0, 5) {
+                        3 if len > 0 => rga.local_delete(ids[rng.range(0, len - 1) as usize]),
+                        4 if len > 0 => rga.local_update(
+                            ids[rng.range(0, len - 1) as usize],
+                            rng.range(10, 19).to_string(),
+                        ),
+                        5 if len > 1 => {
+                            let from: usize = rng.range(0, len - 1) as usize;
+                            rga.local_move_at(from, rng.range(0, len - 1) as usize)
+                        }
+                        _ => {
+                            let left: Option<S4Vector> = match rng.range(0, len) {
+                                0 => None,
+                                i => Some(ids[i as usize - 1]),
+                            };
+                            rga.local_insert(rng.range(0, 9).to_string(), left, None)
+                        }
+                    }
+                    .unwrap();
+                    pending[1 - site].push_back(op);
+                }
+                let rga: &RGA = &replicas[site];
+                history[site].push((rga.version().clone(), rga.read()));
+            }
+
+            for site in 0..2 {
+                while let Some(op) = pending[site].pop_front() {
+                    replicas[site].remote_apply(op);
+                }
+            }
+            for (rga, history) in replicas.iter().zip(&history) {
+                let restored: RGA = RGA::from_snapshot(rga.snapshot());
+                for (version, read) in history {
+                    assert_eq!(&rga.read_at(version), read, "seed {seed}");
+                    let old: RGA = rga.checkout(version, 99).unwrap();
+                    assert_eq!(&old.read(), read, "seed {seed}");
+                    assert_eq!(&restored.read_at(version), read, "seed {seed}");
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,410 @@
+use crate::rga::rga::{Node, OperationError, RGA};
+use crate::S4Vector;
+use std::iter::Flatten;
+use std::str::CharIndices;
+
+/// The elements of a single node, one per character for a run.
+struct Elements<'a> {
+    s4vector: S4Vector,
+    target: Option<S4Vector>,
+    value: &'a str,
+    visible: bool,
+    chars: Option<CharIndices<'a>>,
+    front: usize,
+    back: usize,
+}
+
+impl<'a> Elements<'a> {
+    fn new(rga: &'a RGA, node: &'a Node) -> Self {
+        let displayed: Option<&Node> = rga.displayed(node);
+        let element: &Node = match node.target {
+            Some(target) => rga.node(&target).unwrap_or(node),
+            None => node,
+        };
+
+        return Elements {
+            s4vector: node.s4vector,
+            target: node.target,
+            value: &element.value,
+            visible: displayed.is_some(),
+            chars: (node.len > 1).then(|| node.value.char_indices()),
+            front: 0,
+            back: node.len,
+        };
+    }
+
+    fn element(&self, index: usize, value: &'a str) -> (S4Vector, &'a str, bool) {
+        let s4vector: S4Vector = self.target.unwrap_or(self.s4vector.offset(index as u64));
+        return (s4vector, value, self.visible);
+    }
+
+    fn slice(&self, (byte, c): (usize, char)) -> &'a str {
+        return &self.value[byte..byte + c.len_utf8()];
+    }
+}
+
+impl<'a> Iterator for Elements<'a> {
+    type Item = (S4Vector, &'a str, bool);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.front >= self.back {
+            return None;
+        }
+        let index: usize = self.front;
+        self.front += 1;
+
+        let value: &'a str = match self.chars.as_mut() {
+            Some(chars) => {
+                let c: (usize, char) = chars.next()?;
+                self.slice(c)
+            }
+            None => self.value,
+        };
+        return Some(self.element(index, value));
+    }
+}
+
+impl DoubleEndedIterator for Elements<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.front >= self.back {
+            return None;
+        }
+        self.back -= 1;
+        let index: usize = self.back;
+
+        let value: &str = match self.chars.as_mut() {
+            Some(chars) => {
+                let c: (usize, char) = chars.next_back()?;
+                self.slice(c)
+            }
+            None => self.value,
+        };
+        return Some(self.element(index, value));
+    }
+}
+
+/// The nodes of the list, from either end.
+struct Nodes<'a> {
+    rga: &'a RGA,
+    front: Option<S4Vector>,
+    back: Option<S4Vector>,
+}
+
+impl<'a> Iterator for Nodes<'a> {
+    type Item = Elements<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let key: S4Vector = self.front?;
+        let node: &Node = self.rga.node(&key)?;
+
+        if self.front == self.back {
+            self.front = None;
+            self.back = None;
+        } else {
+            self.front = node.right;
+        }
+        return Some(Elements::new(self.rga, node));
+    }
+}
+
+impl DoubleEndedIterator for Nodes<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let key: S4Vector = self.back?;
+        let node: &Node = self.rga.node(&key)?;
+
+        if self.front == self.back {
+            self.front = None;
+            self.back = None;
+        } else {
+            self.back = node.previous;
+        }
+        return Some(Elements::new(self.rga, node));
+    }
+}
+
+/// `Entries` iterates over every element of an `RGA` in order, including deleted ones,
+/// without cloning any values.
+///
+/// Each item is the `S4Vector` of the element, its value and whether it is visible.
+/// A moved element appears once at each position it has been moved to, and is only
+/// visible at its current position. Iterate in reverse with `rev()`.
+pub struct Entries<'a> {
+    inner: Flatten<Nodes<'a>>,
+}
+
+impl<'a> Iterator for Entries<'a> {
+    type Item = (S4Vector, &'a str, bool);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        return self.inner.next();
+    }
+}
+
+impl DoubleEndedIterator for Entries<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        return self.inner.next_back();
+    }
+}
+
+impl RGA {
+    /// Iterates over every element, including deleted ones.
+    ///
+    /// # Example
+    /// ```
+    /// use crdt::rga::rga::RGA;
+    ///
+    /// let mut rga = RGA::new(1, 1);
+    /// let a = rga.local_insert("a".to_string(), None, None).unwrap().s4vector;
+    /// rga.local_insert("b".to_string(), Some(a), None).unwrap();
+    /// rga.local_delete(a).unwrap();
+    ///
+    /// let entries: Vec<(&str, bool)> = rga
+    ///     .iter_including_tombstones()
+    ///     .map(|(_, value, visible)| (value, visible))
+    ///     .collect();
+    /// assert_eq!(entries, vec![("a", false), ("b", true)]);
+    /// ```
+    pub fn iter_including_tombstones(&self) -> Entries<'_> {
+        let nodes: Nodes<'_> = Nodes {
+            rga: self,
+            front: self.head(),
+            back: self.tail(),
+        };
+        return Entries {
+            inner: nodes.flatten(),
+        };
+    }
+
+    /// Iterates over the visible elements together with their `S4Vector`s.
+    pub fn iter_with_ids(&self) -> impl DoubleEndedIterator<Item = (S4Vector, &str)> + '_ {
+        return self
+            .iter_including_tombstones()
+            .filter(|(_, _, visible)| *visible)
+            .map(|(s4vector, value, _)| (s4vector, value));
+    }
+
+    /// Iterates over the values of the visible elements, the borrowing equivalent of
+    /// `read()`.
+    ///
+    /// # Example
+    /// ```
+    /// use crdt::rga::rga::RGA;
+    ///
+    /// let mut rga = RGA::new(1, 1);
+    /// let a = rga.local_insert("a".to_string(), None, None).unwrap().s4vector;
+    /// rga.local_insert("b".to_string(), Some(a), None).unwrap();
+    ///
+    /// assert_eq!(rga.iter().collect::<String>(), "ab");
+    /// assert_eq!(rga.iter().rev().collect::<String>(), "ba");
+    /// ```
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &str> + '_ {
+        return self.iter_with_ids().map(|(_, value)| value);
+    }
+
+    /// Returns a cursor placed before the visible element at `index`, or at the end
+    /// when `index` is the number of visible elements.
+    pub fn cursor(&self, index: usize) -> Result<Cursor, OperationError> {
+        if index == 0 {
+            return Ok(Cursor::start());
+        }
+
+        return self
+            .iter_with_ids()
+            .nth(index - 1)
+            .map(|(s4vector, _)| Cursor::after(s4vector))
+            .ok_or(OperationError::IndexError);
+    }
+
+    /// Checks whether the element at the list position `s4vector` is visible.
+    pub(crate) fn is_visible(&self, s4vector: &S4Vector) -> bool {
+        return self
+            .node_containing(s4vector)
+            .is_some_and(|node| self.displayed(node).is_some());
+    }
+}
+
+/// `Cursor` is a position between two elements of an `RGA`.
+///
+/// The cursor is anchored to the element to its left rather than to an index, so it
+/// keeps its place when other replicas insert or delete elements around it. If the
+/// anchor is deleted the cursor stays where the anchor was, and if the anchor is moved
+/// the cursor moves with it.
+///
+/// # Example
+/// ```
+/// use crdt::rga::rga::RGA;
+///
+/// let mut rga = RGA::new(1, 1);
+/// let a = rga.local_insert("a".to_string(), None, None).unwrap().s4vector;
+/// rga.local_insert("b".to_string(), Some(a), None).unwrap();
+///
+/// let mut cursor = rga.cursor(1).unwrap();
+/// rga.local_insert("x".to_string(), None, None).unwrap();
+/// assert_eq!(cursor.index(&rga), 2);
+///
+/// assert!(cursor.move_right(&rga));
+/// assert!(!cursor.move_right(&rga));
+/// assert_eq!(cursor.index(&rga), 3);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Cursor {
+    anchor: Option<S4Vector>,
+}
+
+impl Cursor {
+    /// Creates a cursor at the start of the sequence.
+    pub fn start() -> Self {
+        return Cursor { anchor: None };
+    }
+
+    /// Creates a cursor directly after the element `s4vector`.
+    pub fn after(s4vector: S4Vector) -> Self {
+        return Cursor {
+            anchor: Some(s4vector),
+        };
+    }
+
+    /// Returns the `S4Vector` of the element the cursor is anchored to, which is the
+    /// left neighbour to use when inserting at the cursor.
+    pub fn anchor(&self) -> Option<S4Vector> {
+        return self.anchor;
+    }
+
+    /// Returns the number of visible elements before the cursor.
+    pub fn index(&self, rga: &RGA) -> usize {
+        let mut index: usize = 0;
+        let mut current: Option<S4Vector> = self.anchor.map(|anchor| rga.position_of(&anchor));
+
+        while let Some(s4vector) = current {
+            if rga.is_visible(&s4vector) {
+                index += 1;
+            }
+            current = rga.predecessor(Some(s4vector));
+        }
+        return index;
+    }
+
+    /// Moves the cursor over the next visible element.
+    ///
+    /// # Returns
+    /// `false` if the cursor is already at the end.
+    pub fn move_right(&mut self, rga: &RGA) -> bool {
+        let position: Option<S4Vector> = self.anchor.map(|anchor| rga.position_of(&anchor));
+        let mut current: Option<S4Vector> = rga.successor(position);
+
+        while let Some(s4vector) = current {
+            if rga.is_visible(&s4vector) {
+                self.anchor = Some(rga.identity_of(&s4vector));
+                return true;
+            }
+            current = rga.successor(Some(s4vector));
+        }
+        return false;
+    }
+
+    /// Moves the cursor over the previous visible element.
+    ///
+    /// # Returns
+    /// `false` if the cursor is already at the start.
+    pub fn move_left(&mut self, rga: &RGA) -> bool {
+        let mut current: Option<S4Vector> = self.anchor.map(|anchor| rga.position_of(&anchor));
+
+        // Skip over deleted elements to the element left of the cursor
+        while let Some(s4vector) = current.filter(|s4vector| !rga.is_visible(s4vector)) {
+            current = rga.predecessor(Some(s4vector));
+        }
+        let Some(s4vector) = current else {
+            return false;
+        };
+
+        let mut previous: Option<S4Vector> = rga.predecessor(Some(s4vector));
+        while let Some(s4vector) = previous.filter(|s4vector| !rga.is_visible(s4vector)) {
+            previous = rga.predecessor(Some(s4vector));
+        }
+        self.anchor = previous.map(|previous| rga.identity_of(&previous));
+        return true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{deliver, type_text};
+
+    #[test]
+    fn test_iter_matches_read() {
+        let mut rga = RGA::new(1, 1);
+        let operations = type_text(&mut rga, None, "héllo");
+        rga.local_insert("x".to_string(), Some(operations[1].s4vector), None)
+            .unwrap();
+        rga.local_delete(operations[3].s4vector).unwrap();
+
+        assert_eq!(rga.iter().map(String::from).collect::<Vec<_>>(), rga.read());
+        assert_eq!(rga.iter().rev().collect::<String>(), "olxéh");
+        assert_eq!(rga.iter_including_tombstones().count(), 6);
+    }
+
+    #[test]
+    fn test_iter_with_ids() {
+        let mut rga = RGA::new(1, 1);
+        let operations = type_text(&mut rga, None, "abc");
+        rga.local_delete(operations[1].s4vector).unwrap();
+
+        let entries: Vec<(S4Vector, &str)> = rga.iter_with_ids().collect();
+        assert_eq!(
+            entries,
+            vec![(operations[0].s4vector, "a"), (operations[2].s4vector, "c")]
+        );
+    }
+
+    #[test]
+    fn test_iter_from_both_ends() {
+        let mut rga = RGA::new(1, 1);
+        type_text(&mut rga, None, "abcd");
+
+        let mut iter = rga.iter();
+        assert_eq!(iter.next(), Some("a"));
+        assert_eq!(iter.next_back(), Some("d"));
+        assert_eq!(iter.next_back(), Some("c"));
+        assert_eq!(iter.next(), Some("b"));
+        assert_eq!(iter.next(), None);
+        assert_eq!(iter.next_back(), None);
+    }
+
+    #[test]
+    fn test_iter_shows_moved_element_once() {
+        let mut rga = RGA::new(1, 1);
+        type_text(&mut rga, None, "abc");
+        rga.local_move_at(0, 2).unwrap();
+
+        assert_eq!(rga.iter().collect::<String>(), "bca");
+        assert_eq!(rga.iter().rev().collect::<String>(), "acb");
+    }
+
+    #[test]
+    fn test_cursor_survives_concurrent_edits() {
+        let mut rga1 = RGA::new(1, 1);
+        let mut rga2 = RGA::new(1, 2);
+        let operations = type_text(&mut rga1, None, "abcd");
+        deliver(&mut rga2, &operations);
+
+        // The cursor sits between "b" and "c"
+        let mut cursor = rga2.cursor(2).unwrap();
+        let x = rga1.local_insert("x".to_string(), None, None).unwrap();
+        let delete = rga1.local_delete(operations[1].s4vector).unwrap();
+        rga2.remote_apply(x.clone());
+        rga2.remote_apply(delete);
+
+        assert_eq!(rga2.iter().collect::<String>(), "xacd");
+        assert_eq!(cursor.index(&rga2), 2);
+
+        assert!(cursor.move_left(&rga2));
+        assert_eq!(cursor.index(&rga2), 1);
+        assert!(cursor.move_left(&rga2));
+        assert!(!cursor.move_left(&rga2));
+        assert_eq!(cursor, Cursor::start());
+
+        assert!(cursor.move_right(&rga2));
+        assert_eq!(cursor.anchor(), Some(x.s4vector));
+    }
+}
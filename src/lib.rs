@@ -1,5 +1,74 @@
 pub mod rga;
-pub use crate::rga::*;
+pub use crate::rga::rga::*;
 
 pub mod s4vector;
 pub use crate::s4vector::*;
+
+pub mod transaction;
+pub use crate::transaction::*;
+
+pub mod version_vector;
+pub use crate::version_vector::*;
+
+pub mod iter;
+pub use crate::iter::*;
+
+pub mod text;
+pub use crate::text::*;
+
+pub mod rich_text;
+pub use crate::rich_text::*;
+
+pub mod observer;
+pub use crate::observer::*;
+
+pub mod replica;
+pub use crate::replica::*;
+
+pub mod network;
+pub use crate::network::*;
+
+#[cfg(feature = "network")]
+pub mod protocol;
+#[cfg(feature = "network")]
+pub use crate::protocol::*;
+
+#[cfg(feature = "network")]
+pub mod server;
+#[cfg(feature = "network")]
+pub use crate::server::*;
+
+#[cfg(feature = "network")]
+pub mod client;
+#[cfg(feature = "network")]
+pub use crate::client::*;
+
+pub mod gossip;
+pub use crate::gossip::*;
+
+pub mod reconcile;
+pub use crate::reconcile::*;
+
+pub mod oplog;
+pub use crate::oplog::*;
+
+pub mod persistence;
+pub use crate::persistence::*;
+
+pub mod storage;
+pub use crate::storage::*;
+
+pub mod repo;
+pub use crate::repo::*;
+
+pub mod history;
+pub use crate::history::*;
+
+pub mod blame;
+pub use crate::blame::*;
+
+pub mod branch;
+pub use crate::branch::*;
+
+#[cfg(test)]
+mod test_support;
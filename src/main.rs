@@ -1,30 +1,518 @@
-use crdt::rga::rga::RGA;
+use crdt::rga::rga::{BroadcastOperation, OperationError, OperationType, RGA};
+use crdt::S4Vector;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
 
-fn main() {
-    let mut rga = RGA::new(1, 1);
+/// The session every replica of the REPL belongs to.
+const SESSION_ID: u64 = 1;
 
-    // Insert elements
-    let s4_a = rga
-        .local_insert("A".to_string(), None, None)
-        .unwrap()
-        .s4vector;
+/// The usage and description of every command.
+const COMMANDS: [(&str, &str); 17] = [
+    (
+        "new <name> [site]",
+        "create a replica, with the next free site ID by default",
+    ),
+    ("replicas", "list the replicas"),
+    (
+        "insert <name> <index> <value>",
+        "insert a value before the visible element at index",
+    ),
+    (
+        "delete <name> <index>",
+        "delete the visible element at index",
+    ),
+    (
+        "update <name> <index> <value>",
+        "update the visible element at index",
+    ),
+    (
+        "move <name> <from> <to>",
+        "move the visible element at from to index to",
+    ),
+    (
+        "inbox <name>",
+        "list the operations not delivered to a replica yet",
+    ),
+    (
+        "deliver <name> [n|all]",
+        "deliver the n-th undelivered operation, the first by default",
+    ),
+    ("drop <name> <n>", "drop the n-th undelivered operation"),
+    ("swap <name> <n> <m>", "swap two undelivered operations"),
+    ("sync", "deliver every operation to every replica"),
+    (
+        "read [name]",
+        "print the visible values of one or every replica",
+    ),
+    (
+        "tombstones <name>",
+        "print the hidden elements of a replica",
+    ),
+    (
+        "buffer <name>",
+        "print the operations waiting for their dependencies",
+    ),
+    ("version <name>", "print the version vector of a replica"),
+    ("help", "print this list"),
+    ("quit", "exit"),
+];
 
-    // Read the state
-    println!("Current RGA State: {:?}", rga.read());
+#[derive(Debug, thiserror::Error)]
+enum CommandError {
+    #[error("Unknown command {0}, type help for the list of commands")]
+    UnknownCommand(String),
+    #[error("Usage: {0}")]
+    Usage(&'static str),
+    #[error("No replica named {0}")]
+    UnknownReplica(String),
+    #[error("Replica {0} already exists")]
+    ReplicaExists(String),
+    #[error("Site {0} is already used by another replica")]
+    SiteInUse(u64),
+    #[error("Replica {0} has no undelivered operation {1}")]
+    NoOperation(String, usize),
+    #[error(transparent)]
+    Operation(#[from] OperationError),
+}
+
+/// A replica and the operations of the other replicas it has not received.
+struct Replica {
+    rga: RGA,
+    inbox: Vec<BroadcastOperation>,
+}
+
+/// The replicas of a document, whose operations are only delivered on command, so
+/// any interleaving of delivery, loss and reordering can be tried.
+struct Repl {
+    replicas: BTreeMap<String, Replica>,
+    /// Every operation made so far, delivered to replicas created later.
+    sent: Vec<BroadcastOperation>,
+}
+
+impl Repl {
+    fn new() -> Self {
+        return Repl {
+            replicas: BTreeMap::new(),
+            sent: Vec::new(),
+        };
+    }
+
+    /// Runs one command.
+    ///
+    /// # Returns
+    /// The text to print, empty if there is nothing to print.
+    fn execute(&mut self, line: &str) -> Result<String, CommandError> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let number = |i: usize, usage: &'static str| -> Result<usize, CommandError> {
+            return words
+                .get(i)
+                .and_then(|word| word.parse().ok())
+                .ok_or(CommandError::Usage(usage));
+        };
+
+        return match words.as_slice() {
+            [] => Ok(String::new()),
+            ["help"] => Ok(help()),
+            ["new", name, rest @ ..] if rest.len() <= 1 => {
+                let site: Option<u64> = match rest.first() {
+                    Some(site) => Some(
+                        site.parse()
+                            .map_err(|_| CommandError::Usage("new <name> [site]"))?,
+                    ),
+                    None => None,
+                };
+                self.create(name, site)
+            }
+            ["replicas"] => Ok(self
+                .replicas
+                .iter()
+                .map(|(name, replica)| {
+                    format!(
+                        "{name}: site {}, {} undelivered",
+                        replica.rga.site_id(),
+                        replica.inbox.len()
+                    )
+                })
+                .collect::<Vec<String>>()
+                .join("\n")),
+            ["insert", name, _, _, ..] => {
+                let index: usize = number(2, "insert <name> <index> <value>")?;
+                let value: String = words[3..].join(" ");
+                let rga: &mut RGA = &mut self.replica(name)?.rga;
+                let left: Option<S4Vector> = rga.cursor(index)?.anchor();
+                let op: BroadcastOperation = rga.local_insert(value, left, None)?;
+                Ok(self.broadcast(name, op))
+            }
+            ["delete", name, _] => {
+                let index: usize = number(2, "delete <name> <index>")?;
+                let rga: &mut RGA = &mut self.replica(name)?.rga;
+                let op: BroadcastOperation = rga.local_delete(element(rga, index)?)?;
+                Ok(self.broadcast(name, op))
+            }
+            ["update", name, _, _, ..] => {
+                let index: usize = number(2, "update <name> <index> <value>")?;
+                let value: String = words[3..].join(" ");
+                let rga: &mut RGA = &mut self.replica(name)?.rga;
+                let op: BroadcastOperation = rga.local_update(element(rga, index)?, value)?;
+                Ok(self.broadcast(name, op))
+            }
+            ["move", name, _, _] => {
+                let from: usize = number(2, "move <name> <from> <to>")?;
+                let to: usize = number(3, "move <name> <from> <to>")?;
+                let op: BroadcastOperation = self.replica(name)?.rga.local_move_at(from, to)?;
+                Ok(self.broadcast(name, op))
+            }
+            ["inbox", name] => Ok(self
+                .replica(name)?
+                .inbox
+                .iter()
+                .enumerate()
+                .map(|(i, op)| format!("{i}: {}", describe(op)))
+                .collect::<Vec<String>>()
+                .join("\n")),
+            ["deliver", name] => self.deliver(name, 0),
+            ["deliver", name, "all"] => {
+                let replica: &mut Replica = self.replica(name)?;
+                let delivered: usize = replica.inbox.len();
+                for op in replica.inbox.drain(..) {
+                    replica.rga.remote_apply(op);
+                }
+                Ok(format!("Delivered {delivered} operations"))
+            }
+            ["deliver", name, _] => {
+                let n: usize = number(2, "deliver <name> [n|all]")?;
+                self.deliver(name, n)
+            }
+            ["drop", name, _] => {
+                let n: usize = number(2, "drop <name> <n>")?;
+                let op: BroadcastOperation = self.take(name, n)?;
+                Ok(format!("Dropped {}", describe(&op)))
+            }
+            ["swap", name, _, _] => {
+                let n: usize = number(2, "swap <name> <n> <m>")?;
+                let m: usize = number(3, "swap <name> <n> <m>")?;
+                let inbox: &mut Vec<BroadcastOperation> = &mut self.replica(name)?.inbox;
+                for i in [n, m] {
+                    if i >= inbox.len() {
+                        return Err(CommandError::NoOperation(name.to_string(), i));
+                    }
+                }
+                inbox.swap(n, m);
+                Ok(String::new())
+            }
+            ["sync"] => {
+                for replica in self.replicas.values_mut() {
+                    for op in replica.inbox.drain(..) {
+                        replica.rga.remote_apply(op);
+                    }
+                }
+                Ok(self.read_all())
+            }
+            ["read"] => Ok(self.read_all()),
+            ["read", name] => Ok(format!("{:?}", self.replica(name)?.rga.read())),
+            ["tombstones", name] => Ok(self
+                .replica(name)?
+                .rga
+                .iter_including_tombstones()
+                .filter(|(_, _, visible)| !visible)
+                .map(|(s4vector, value, _)| format!("{} {value:?}", id(&s4vector)))
+                .collect::<Vec<String>>()
+                .join("\n")),
+            ["buffer", name] => Ok(self
+                .replica(name)?
+                .rga
+                .snapshot()
+                .buffer
+                .iter()
+                .enumerate()
+                .flat_map(|(i, group)| {
+                    group
+                        .iter()
+                        .map(move |op| format!("group {i}: {}", describe(op)))
+                })
+                .collect::<Vec<String>>()
+                .join("\n")),
+            ["version", name] => Ok(self
+                .replica(name)?
+                .rga
+                .version()
+                .iter()
+                .map(|(sid, seq)| format!("site {sid}: {seq}"))
+                .collect::<Vec<String>>()
+                .join("\n")),
+            ["new" | "insert" | "delete" | "update" | "move" | "inbox" | "deliver" | "drop"
+            | "swap" | "read" | "tombstones" | "buffer" | "version", ..] => {
+                Err(CommandError::Usage(usage(words[0])))
+            }
+            [command, ..] => Err(CommandError::UnknownCommand(command.to_string())),
+        };
+    }
+
+    /// Creates a replica that has every operation made so far in its inbox.
+    fn create(&mut self, name: &str, site: Option<u64>) -> Result<String, CommandError> {
+        if self.replicas.contains_key(name) {
+            return Err(CommandError::ReplicaExists(name.to_string()));
+        }
+        let used = |site: u64| {
+            self.replicas
+                .values()
+                .any(|replica| replica.rga.site_id() == site)
+        };
+        let site: u64 = match site {
+            Some(site) if used(site) => return Err(CommandError::SiteInUse(site)),
+            Some(site) => site,
+            None => (1..).find(|site| !used(*site)).unwrap(),
+        };
+
+        let replica: Replica = Replica {
+            rga: RGA::new(SESSION_ID, site),
+            inbox: self.sent.clone(),
+        };
+        self.replicas.insert(name.to_string(), replica);
+        return Ok(format!("Created {name} with site {site}"));
+    }
+
+    fn replica(&mut self, name: &str) -> Result<&mut Replica, CommandError> {
+        return self
+            .replicas
+            .get_mut(name)
+            .ok_or_else(|| CommandError::UnknownReplica(name.to_string()));
+    }
+
+    /// Puts a local operation of `from` in the inbox of every other replica.
+    fn broadcast(&mut self, from: &str, op: BroadcastOperation) -> String {
+        for (name, replica) in &mut self.replicas {
+            if name != from {
+                replica.inbox.push(op.clone());
+            }
+        }
+        let description: String = describe(&op);
+        self.sent.push(op);
+        return description;
+    }
+
+    /// Removes the `n`-th operation from the inbox of a replica.
+    fn take(&mut self, name: &str, n: usize) -> Result<BroadcastOperation, CommandError> {
+        let inbox: &mut Vec<BroadcastOperation> = &mut self.replica(name)?.inbox;
+        if n >= inbox.len() {
+            return Err(CommandError::NoOperation(name.to_string(), n));
+        }
+        return Ok(inbox.remove(n));
+    }
+
+    fn deliver(&mut self, name: &str, n: usize) -> Result<String, CommandError> {
+        let op: BroadcastOperation = self.take(name, n)?;
+        let description: String = describe(&op);
+        let rga: &mut RGA = &mut self.replica(name)?.rga;
+        rga.remote_apply(op);
+        return Ok(format!("Delivered {description}\n{:?}", rga.read()));
+    }
+
+    /// Prints the values of every replica and whether they have converged.
+    fn read_all(&self) -> String {
+        let mut lines: Vec<String> = self
+            .replicas
+            .iter()
+            .map(|(name, replica)| format!("{name}: {:?}", replica.rga.read()))
+            .collect();
+        let mut reads = self.replicas.values().map(|replica| replica.rga.read());
+        if let Some(first) = reads.next() {
+            let converged: bool = reads.all(|read| read == first);
+            lines.push(if converged { "converged" } else { "diverged" }.to_string());
+        }
+        return lines.join("\n");
+    }
+}
+
+/// Returns the list of commands.
+fn help() -> String {
+    return COMMANDS
+        .iter()
+        .map(|(usage, description)| format!("{usage:<31}{description}"))
+        .collect::<Vec<String>>()
+        .join("\n");
+}
+
+/// Returns the usage of a command.
+fn usage(command: &str) -> &'static str {
+    return COMMANDS
+        .iter()
+        .find(|(usage, _)| usage.split_whitespace().next() == Some(command))
+        .map_or("help", |(usage, _)| usage);
+}
+
+/// Returns the `S4Vector` of the visible element at `index`.
+fn element(rga: &RGA, index: usize) -> Result<S4Vector, OperationError> {
+    return rga
+        .iter_with_ids()
+        .nth(index)
+        .map(|(s4vector, _)| s4vector)
+        .ok_or(OperationError::IndexError);
+}
+
+/// Formats an `S4Vector` as its site ID and sequence number, which identify it within
+/// a session.
+fn id(s4vector: &S4Vector) -> String {
+    return format!("{}.{}", s4vector.sid, s4vector.seq);
+}
+
+fn describe(op: &BroadcastOperation) -> String {
+    let after = |left: Option<S4Vector>| left.map_or("the start".to_string(), |left| id(&left));
+    let value: &str = op.value.as_deref().unwrap_or_default();
+    let description: String = match &op.operation {
+        OperationType::Insert | OperationType::InsertRun => {
+            format!("insert {value:?} after {}", after(op.left))
+        }
+        OperationType::Update => format!("update {} to {value:?}", id(&op.s4vector)),
+        OperationType::Delete => format!("delete {}", id(&op.s4vector)),
+        OperationType::DeleteRange { to, .. } => {
+            format!("delete {} to {}", id(&op.s4vector), id(to))
+        }
+        OperationType::UpdateRange { to, .. } => {
+            format!("update {} to {} to {value:?}", id(&op.s4vector), id(to))
+        }
+        OperationType::Move => format!("move {} after {}", id(&op.s4vector), after(op.left)),
+    };
+    return format!("[{}] {description}", id(&op.id));
+}
+
+/// Runs a REPL over replicas of one document, reading commands from the file given as
+/// the first argument, or interactively from standard input. Type `help` for the list
+/// of commands.
+///
+/// ```text
+/// cargo run --bin crdt
+/// > new a
+/// > new b
+/// > insert a 0 x
+/// > read
+/// ```
+fn main() -> io::Result<()> {
+    let script: Option<String> = std::env::args().nth(1);
+    let interactive: bool = script.is_none();
+    let input: Box<dyn BufRead> = match script {
+        Some(path) => Box::new(BufReader::new(File::open(path)?)),
+        None => Box::new(io::stdin().lock()),
+    };
+
+    let mut repl: Repl = Repl::new();
+    if interactive {
+        print!("> ");
+        io::stdout().flush()?;
+    }
+    for line in input.lines() {
+        let line: String = line?;
+        let command: &str = line.trim();
+        if command == "quit" || command == "exit" {
+            break;
+        }
+
+        if !interactive && !command.is_empty() && !command.starts_with('#') {
+            println!("> {command}");
+        }
+        if !command.starts_with('#') {
+            match repl.execute(command) {
+                Ok(output) if output.is_empty() => {}
+                Ok(output) => println!("{output}"),
+                Err(error) => eprintln!("error: {error}"),
+            }
+        }
+        if interactive {
+            print!("> ");
+            io::stdout().flush()?;
+        }
+    }
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(repl: &mut Repl, commands: &[&str]) -> String {
+        let mut output: String = String::new();
+        for command in commands {
+            output = repl.execute(command).unwrap();
+        }
+        return output;
+    }
 
-    let s4_b = rga
-        .local_insert("B".to_string(), Some(s4_a), None)
-        .unwrap()
-        .s4vector;
-    // Read the state
-    println!("Current RGA State: {:?}", rga.read());
+    #[test]
+    fn test_concurrent_edits_converge() {
+        let mut repl: Repl = Repl::new();
+        let output: String = run(
+            &mut repl,
+            &[
+                "new a",
+                "new b",
+                "insert a 0 x",
+                "insert b 0 y",
+                "swap a 0 0",
+                "deliver b",
+                "update b 1 hello world",
+                "sync",
+            ],
+        );
+        assert!(output.ends_with("converged"), "{output}");
+        assert!(output.contains("hello world"), "{output}");
 
-    // Delete an element
-    rga.local_delete(s4_a).unwrap();
+        // Replicas created later receive everything made so far
+        let output: String = run(&mut repl, &["new c 7", "deliver c all", "read"]);
+        assert!(output.ends_with("converged"), "{output}");
+    }
 
-    // Update an element
-    rga.local_update(s4_b, "Updated B".to_string()).unwrap();
+    #[test]
+    fn test_dropped_operation_buffers_dependents() {
+        let mut repl: Repl = Repl::new();
+        run(
+            &mut repl,
+            &["new a", "new b", "insert a 0 x", "delete a 0", "drop b 0"],
+        );
+        assert!(repl.execute("inbox b").unwrap().contains("delete 1.1"));
+        assert_eq!(
+            repl.execute("deliver b").unwrap(),
+            "Delivered [1.2] delete 1.1\n[]"
+        );
+        assert!(repl
+            .execute("buffer b")
+            .unwrap()
+            .starts_with("group 0: [1.2] delete 1.1"));
+        assert_eq!(repl.execute("tombstones a").unwrap(), "1.1 \"x\"");
+        assert!(repl.execute("read").unwrap().ends_with("converged"));
+    }
 
-    // Read the state
-    println!("Current RGA State: {:?}", rga.read());
+    #[test]
+    fn test_errors() {
+        let mut repl: Repl = Repl::new();
+        run(&mut repl, &["new a 1"]);
+        assert!(matches!(
+            repl.execute("new a"),
+            Err(CommandError::ReplicaExists(_))
+        ));
+        assert!(matches!(
+            repl.execute("new b 1"),
+            Err(CommandError::SiteInUse(1))
+        ));
+        assert!(matches!(
+            repl.execute("read b"),
+            Err(CommandError::UnknownReplica(_))
+        ));
+        assert!(matches!(
+            repl.execute("deliver a"),
+            Err(CommandError::NoOperation(_, 0))
+        ));
+        assert!(matches!(
+            repl.execute("delete a 0"),
+            Err(CommandError::Operation(OperationError::IndexError))
+        ));
+        assert!(matches!(
+            repl.execute("frobnicate"),
+            Err(CommandError::UnknownCommand(_))
+        ));
+        match repl.execute("insert a") {
+            Err(CommandError::Usage(usage)) => assert_eq!(usage, "insert <name> <index> <value>"),
+            result => panic!("{result:?}"),
+        }
+    }
 }
@@ -0,0 +1,597 @@
+use crate::OperationBatch;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap, VecDeque};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// A message sent from one site to another, by default a batch of operations.
+#[derive(Debug, Clone)]
+pub struct Envelope<M = OperationBatch> {
+    pub from: u64,
+    pub to: u64,
+    pub payload: M,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum TransportError {
+    #[error("Failed to send, site {0} is not connected")]
+    UnknownSite(u64),
+    #[error("Failed to send, the transport has been closed")]
+    Closed,
+}
+
+/// `Transport` moves batches of operations between the replicas of a document.
+///
+/// Each replica owns one endpoint, identified by its site ID. A transport does not
+/// have to deliver batches in order, exactly once or at all: the `RGA` buffers
+/// operations until their dependencies arrive and ignores operations it has already
+/// integrated, so replicas converge as long as every batch eventually arrives.
+///
+/// Transports carry `OperationBatch`es unless another message type `M` is given,
+/// as used by protocols such as `Gossip` that also exchange version vectors.
+pub trait Transport<M = OperationBatch> {
+    /// Returns the site ID of the replica owning this endpoint.
+    fn site_id(&self) -> u64;
+
+    /// Sends `payload` to the replica with site ID `to`.
+    ///
+    /// # Returns
+    /// `Ok` once the payload has been handed to the network, which does not mean it
+    /// will be delivered.
+    fn send(&mut self, to: u64, payload: M) -> Result<(), TransportError>;
+
+    /// Returns the next message delivered to this endpoint, if any.
+    fn receive(&mut self) -> Option<Envelope<M>>;
+}
+
+/// The faults injected by a `SimulatedNetwork`.
+///
+/// Latency is measured in ticks of the network's virtual clock. Each message is
+/// delayed by a latency drawn between `min_latency` and `max_latency`, so messages
+/// are reordered whenever the two differ.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NetworkConfig {
+    pub min_latency: u64,
+    pub max_latency: u64,
+    /// The probability that a message is delivered twice.
+    pub duplicate_probability: f64,
+    /// The probability that a message is never delivered.
+    pub loss_probability: f64,
+}
+
+impl NetworkConfig {
+    /// A network that delivers every message once, in order, after one tick.
+    pub fn reliable() -> Self {
+        return NetworkConfig {
+            min_latency: 1,
+            max_latency: 1,
+            duplicate_probability: 0.0,
+            loss_probability: 0.0,
+        };
+    }
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        return NetworkConfig::reliable();
+    }
+}
+
+/// A small deterministic random number generator (SplitMix64), so a simulation can
+/// be replayed exactly from its seed.
+#[derive(Debug, Clone)]
+pub(crate) struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub(crate) fn new(seed: u64) -> Self {
+        return Rng { state: seed };
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z: u64 = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        return z ^ (z >> 31);
+    }
+
+    /// Returns a number in `low..=high`.
+    pub(crate) fn range(&mut self, low: u64, high: u64) -> u64 {
+        if high <= low {
+            return low;
+        }
+        return low + self.next_u64() % (high - low + 1);
+    }
+
+    /// Returns `true` with the given probability.
+    pub(crate) fn chance(&mut self, probability: f64) -> bool {
+        let unit: f64 = (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64;
+        return unit < probability;
+    }
+}
+
+/// The state shared by a `SimulatedNetwork` and its endpoints.
+#[derive(Debug)]
+struct NetworkState<M> {
+    config: NetworkConfig,
+    rng: Rng,
+    now: u64,
+    /// Messages by the tick they are due and the order they were sent in.
+    in_flight: BinaryHeap<Reverse<(u64, usize)>>,
+    messages: BTreeMap<usize, Envelope<M>>,
+    next_message: usize,
+    inboxes: BTreeMap<u64, VecDeque<Envelope<M>>>,
+    /// The group each site belongs to while the network is partitioned.
+    partitions: Option<BTreeMap<u64, usize>>,
+}
+
+impl<M> NetworkState<M> {
+    fn connected(&self, from: u64, to: u64) -> bool {
+        return match &self.partitions {
+            None => true,
+            Some(groups) => groups.get(&from) == groups.get(&to),
+        };
+    }
+
+    fn schedule(&mut self, envelope: Envelope<M>) {
+        let latency: u64 = self
+            .rng
+            .range(self.config.min_latency, self.config.max_latency);
+        let id: usize = self.next_message;
+        self.next_message += 1;
+        self.in_flight.push(Reverse((self.now + latency, id)));
+        self.messages.insert(id, envelope);
+    }
+}
+
+/// `SimulatedNetwork` is an in-process network for testing replicas under
+/// latency, reordering, duplication, partitions and message loss.
+///
+/// The network has a virtual clock that only moves when `advance` or `run` is
+/// called, and every fault is drawn from a generator seeded by `seed`, so the same
+/// seed and the same sequence of calls always deliver the same messages in the same
+/// order. A failing test can therefore be reproduced by rerunning its seed.
+///
+/// # Example
+/// ```
+/// use crdt::rga::rga::RGA;
+/// use crdt::{NetworkConfig, SimulatedNetwork, Transport};
+///
+/// let network: SimulatedNetwork = SimulatedNetwork::new(7, NetworkConfig::reliable());
+/// let mut a = network.endpoint(1);
+/// let mut b = network.endpoint(2);
+/// let mut rga1 = RGA::new(1, 1);
+/// let mut rga2 = RGA::new(1, 2);
+///
+/// let op = rga1.local_insert("x".to_string(), None, None).unwrap();
+/// a.send(2, op.into()).unwrap();
+/// network.run();
+///
+/// while let Some(envelope) = b.receive() {
+///     rga2.remote_apply_batch(envelope.payload);
+/// }
+/// assert_eq!(rga2.read(), vec!["x".to_string()]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct SimulatedNetwork<M = OperationBatch> {
+    state: Arc<Mutex<NetworkState<M>>>,
+}
+
+impl<M: Clone> SimulatedNetwork<M> {
+    /// Creates a network without any endpoints.
+    ///
+    /// # Parameters
+    /// - `seed`: Seeds the faults injected by the network.
+    /// - `config`: The latency and fault probabilities of the network.
+    pub fn new(seed: u64, config: NetworkConfig) -> Self {
+        let state: NetworkState<M> = NetworkState {
+            config,
+            rng: Rng::new(seed),
+            now: 0,
+            in_flight: BinaryHeap::new(),
+            messages: BTreeMap::new(),
+            next_message: 0,
+            inboxes: BTreeMap::new(),
+            partitions: None,
+        };
+        return SimulatedNetwork {
+            state: Arc::new(Mutex::new(state)),
+        };
+    }
+
+    fn state(&self) -> MutexGuard<'_, NetworkState<M>> {
+        return self.state.lock().expect("network state poisoned");
+    }
+
+    /// Connects a replica to the network.
+    ///
+    /// # Returns
+    /// The endpoint the replica with site ID `site_id` sends and receives through.
+    pub fn endpoint(&self, site_id: u64) -> SimulatedTransport<M> {
+        self.state().inboxes.entry(site_id).or_default();
+        return SimulatedTransport {
+            site_id,
+            state: self.state.clone(),
+        };
+    }
+
+    /// Returns the site IDs of every connected endpoint.
+    pub fn sites(&self) -> Vec<u64> {
+        return self.state().inboxes.keys().copied().collect();
+    }
+
+    /// Replaces the fault configuration, for example to stop losing messages once a
+    /// test has finished injecting faults.
+    pub fn set_config(&self, config: NetworkConfig) {
+        self.state().config = config;
+    }
+
+    /// Splits the network so that sites can only reach sites in the same group.
+    ///
+    /// Messages sent between groups while partitioned are dropped, as are messages
+    /// already in flight between them when they would be delivered. Sites that are
+    /// not listed form a group of their own.
+    pub fn partition(&self, groups: &[&[u64]]) {
+        let mut assignment: BTreeMap<u64, usize> = BTreeMap::new();
+        for (group, sites) in groups.iter().enumerate() {
+            for site in sites.iter() {
+                assignment.insert(*site, group);
+            }
+        }
+        self.state().partitions = Some(assignment);
+    }
+
+    /// Removes every partition.
+    pub fn heal(&self) {
+        self.state().partitions = None;
+    }
+
+    /// Returns the current time of the virtual clock.
+    pub fn now(&self) -> u64 {
+        return self.state().now;
+    }
+
+    /// Returns the number of messages sent but not yet delivered to an inbox.
+    pub fn in_flight(&self) -> usize {
+        return self.state().in_flight.len();
+    }
+
+    /// Moves the virtual clock forward, delivering every message that is due.
+    ///
+    /// # Returns
+    /// The number of messages delivered to an inbox.
+    pub fn advance(&self, ticks: u64) -> usize {
+        let mut state: MutexGuard<'_, NetworkState<M>> = self.state();
+        state.now += ticks;
+
+        let mut delivered: usize = 0;
+        while let Some(Reverse((due, id))) = state.in_flight.peek().copied() {
+            if due > state.now {
+                break;
+            }
+            state.in_flight.pop();
+
+            let envelope: Envelope<M> = state.messages.remove(&id).expect("message in flight");
+            if !state.connected(envelope.from, envelope.to) {
+                continue;
+            }
+            if let Some(inbox) = state.inboxes.get_mut(&envelope.to) {
+                inbox.push_back(envelope);
+                delivered += 1;
+            }
+        }
+        return delivered;
+    }
+
+    /// Advances the clock until no message is in flight.
+    ///
+    /// # Returns
+    /// The number of messages delivered to an inbox.
+    pub fn run(&self) -> usize {
+        let mut delivered: usize = 0;
+        while self.in_flight() > 0 {
+            delivered += self.advance(1);
+        }
+        return delivered;
+    }
+}
+
+/// The endpoint of a replica connected to a `SimulatedNetwork`.
+#[derive(Debug, Clone)]
+pub struct SimulatedTransport<M = OperationBatch> {
+    site_id: u64,
+    state: Arc<Mutex<NetworkState<M>>>,
+}
+
+impl<M: Clone> SimulatedTransport<M> {
+    /// Sends `payload` to every other site connected to the network.
+    pub fn broadcast(&mut self, payload: M) {
+        let sites: Vec<u64> = {
+            let state: MutexGuard<'_, NetworkState<M>> =
+                self.state.lock().expect("network state poisoned");
+            state.inboxes.keys().copied().collect()
+        };
+        for site in sites {
+            if site != self.site_id {
+                let _ = self.send(site, payload.clone());
+            }
+        }
+    }
+}
+
+impl<M: Clone> Transport<M> for SimulatedTransport<M> {
+    fn site_id(&self) -> u64 {
+        return self.site_id;
+    }
+
+    fn send(&mut self, to: u64, payload: M) -> Result<(), TransportError> {
+        let mut state: MutexGuard<'_, NetworkState<M>> =
+            self.state.lock().expect("network state poisoned");
+        if !state.inboxes.contains_key(&to) {
+            return Err(TransportError::UnknownSite(to));
+        }
+        if !state.connected(self.site_id, to) {
+            return Ok(());
+        }
+
+        let loss: f64 = state.config.loss_probability;
+        if state.rng.chance(loss) {
+            return Ok(());
+        }
+
+        let envelope: Envelope<M> = Envelope {
+            from: self.site_id,
+            to,
+            payload,
+        };
+        let duplicate: f64 = state.config.duplicate_probability;
+        if state.rng.chance(duplicate) {
+            state.schedule(envelope.clone());
+        }
+        state.schedule(envelope);
+        return Ok(());
+    }
+
+    fn receive(&mut self) -> Option<Envelope<M>> {
+        let mut state: MutexGuard<'_, NetworkState<M>> =
+            self.state.lock().expect("network state poisoned");
+        return state.inboxes.get_mut(&self.site_id)?.pop_front();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rga::rga::{BroadcastOperation, OperationError, RGA};
+    use crate::S4Vector;
+    use std::collections::HashSet;
+
+    /// A replica together with its endpoint and every batch it has produced.
+    struct Peer {
+        rga: RGA,
+        transport: SimulatedTransport,
+        history: Vec<OperationBatch>,
+    }
+
+    impl Peer {
+        fn publish(&mut self, batch: OperationBatch) {
+            self.history.push(batch.clone());
+            self.transport.broadcast(batch);
+        }
+
+        fn receive_all(&mut self) {
+            while let Some(envelope) = self.transport.receive() {
+                self.rga.remote_apply_batch(envelope.payload);
+            }
+        }
+
+        /// Makes a random edit, skipping edits that are not possible right now.
+        fn edit(&mut self, rng: &mut Rng) {
+            let ids: Vec<S4Vector> = self.rga.iter_with_ids().map(|(id, _)| id).collect();
+            let len: usize = ids.len();
+            let value: String = format!("{}{}", self.rga.site_id(), rng.next_u64() % 100);
+
+            let result: Result<OperationBatch, OperationError> = match rng.range(0, 9) {
+                0..=3 => {
+                    let left: Option<S4Vector> = match rng.range(0, len as u64) {
+                        0 => None,
+                        i => Some(ids[i as usize - 1]),
+                    };
+                    self.rga
+                        .local_insert(value, left, None)
+                        .map(OperationBatch::from)
+                }
+                4 if len > 0 => {
+                    let id: S4Vector = ids[rng.range(0, len as u64 - 1) as usize];
+                    self.rga.local_delete(id).map(OperationBatch::from)
+                }
+                5 if len > 0 => {
+                    let id: S4Vector = ids[rng.range(0, len as u64 - 1) as usize];
+                    self.rga.local_update(id, value).map(OperationBatch::from)
+                }
+                6 if len > 1 => {
+                    let from: usize = rng.range(0, len as u64 - 1) as usize;
+                    let to: usize = rng.range(0, len as u64) as usize;
+                    self.rga.local_move_at(from, to).map(OperationBatch::from)
+                }
+                7 if len > 1 => {
+                    let start: usize = rng.range(0, len as u64 - 1) as usize;
+                    let end: usize = rng.range(start as u64 + 1, len as u64) as usize;
+                    self.rga
+                        .local_delete_range_at(start..end)
+                        .map(OperationBatch::from)
+                }
+                8 => self.rga.transaction(|tx| {
+                    let mut left: Option<S4Vector> = ids.last().copied();
+                    for c in ["t", "x"] {
+                        left = Some(tx.insert(c.to_string(), left, None)?);
+                    }
+                    Ok(())
+                }),
+                _ => return,
+            };
+
+            if let Ok(batch) = result {
+                self.publish(batch);
+            }
+        }
+    }
+
+    fn peers(network: &SimulatedNetwork, count: u64) -> Vec<Peer> {
+        return (1..=count)
+            .map(|site_id| Peer {
+                rga: RGA::new(1, site_id),
+                transport: network.endpoint(site_id),
+                history: Vec::new(),
+            })
+            .collect();
+    }
+
+    fn deliveries(seed: u64) -> Vec<(u64, u64, S4Vector)> {
+        let config: NetworkConfig = NetworkConfig {
+            min_latency: 1,
+            max_latency: 10,
+            duplicate_probability: 0.3,
+            loss_probability: 0.2,
+        };
+        let network: SimulatedNetwork = SimulatedNetwork::new(seed, config);
+        let mut a: SimulatedTransport = network.endpoint(1);
+        let mut b: SimulatedTransport = network.endpoint(2);
+        let mut rga: RGA = RGA::new(1, 1);
+
+        for i in 0..20 {
+            let op: BroadcastOperation = rga.local_insert(i.to_string(), None, None).unwrap();
+            a.send(2, op.into()).unwrap();
+            network.advance(1);
+        }
+        network.run();
+
+        let mut received: Vec<(u64, u64, S4Vector)> = Vec::new();
+        while let Some(envelope) = b.receive() {
+            received.push((
+                envelope.from,
+                envelope.to,
+                envelope.payload.operations[0].id,
+            ));
+        }
+        return received;
+    }
+
+    #[test]
+    fn test_same_seed_same_deliveries() {
+        let first = deliveries(42);
+        assert_eq!(first, deliveries(42));
+        assert_ne!(first, deliveries(43));
+
+        // Some messages were lost, duplicated and reordered
+        let ids: Vec<S4Vector> = first.iter().map(|(_, _, id)| *id).collect();
+        let unique: HashSet<S4Vector> = ids.iter().copied().collect();
+        assert!(unique.len() < 20);
+        assert!(unique.len() < ids.len());
+        assert!(ids.windows(2).any(|pair| pair[0] > pair[1]));
+    }
+
+    #[test]
+    fn test_latency() {
+        let config: NetworkConfig = NetworkConfig {
+            min_latency: 5,
+            max_latency: 5,
+            ..NetworkConfig::reliable()
+        };
+        let network: SimulatedNetwork = SimulatedNetwork::new(1, config);
+        let mut a: SimulatedTransport = network.endpoint(1);
+        let mut b: SimulatedTransport = network.endpoint(2);
+
+        a.send(2, OperationBatch::default()).unwrap();
+        assert_eq!(network.advance(4), 0);
+        assert!(b.receive().is_none());
+        assert_eq!(network.advance(1), 1);
+        assert_eq!(b.receive().unwrap().from, 1);
+        assert!(matches!(
+            a.send(3, OperationBatch::default()),
+            Err(TransportError::UnknownSite(3))
+        ));
+    }
+
+    #[test]
+    fn test_partition_drops_messages() {
+        let config: NetworkConfig = NetworkConfig {
+            min_latency: 3,
+            max_latency: 3,
+            ..NetworkConfig::reliable()
+        };
+        let network: SimulatedNetwork = SimulatedNetwork::new(1, config);
+        let mut a: SimulatedTransport = network.endpoint(1);
+        let mut b: SimulatedTransport = network.endpoint(2);
+        let mut c: SimulatedTransport = network.endpoint(3);
+
+        // In flight when the partition starts
+        a.send(3, OperationBatch::default()).unwrap();
+        network.partition(&[&[1, 2], &[3]]);
+        a.send(2, OperationBatch::default()).unwrap();
+        a.send(3, OperationBatch::default()).unwrap();
+        assert_eq!(network.run(), 1);
+        assert!(b.receive().is_some());
+        assert!(c.receive().is_none());
+
+        network.heal();
+        a.send(3, OperationBatch::default()).unwrap();
+        assert_eq!(network.run(), 1);
+        assert!(c.receive().is_some());
+    }
+
+    /// Edits on every replica while the network misbehaves, then retransmits every
+    /// batch over a reliable network, as anti-entropy would, and checks convergence.
+    fn simulate(seed: u64) {
+        let config: NetworkConfig = NetworkConfig {
+            min_latency: 1,
+            max_latency: 20,
+            duplicate_probability: 0.2,
+            loss_probability: 0.1,
+        };
+        let network: SimulatedNetwork = SimulatedNetwork::new(seed, config);
+        let mut rng: Rng = Rng::new(seed);
+        let mut peers: Vec<Peer> = peers(&network, 4);
+
+        for round in 0..200 {
+            match round {
+                50 => network.partition(&[&[1, 2], &[3, 4]]),
+                100 => network.partition(&[&[1], &[2, 3, 4]]),
+                150 => network.heal(),
+                _ => {}
+            }
+
+            let peer: &mut Peer = &mut peers[rng.range(0, 3) as usize];
+            peer.edit(&mut rng);
+            network.advance(rng.range(0, 3));
+            for peer in peers.iter_mut() {
+                peer.receive_all();
+            }
+        }
+
+        network.set_config(NetworkConfig::reliable());
+        for peer in peers.iter_mut() {
+            for batch in peer.history.clone() {
+                peer.transport.broadcast(batch);
+            }
+        }
+        network.run();
+        for peer in peers.iter_mut() {
+            peer.receive_all();
+        }
+
+        let state: Vec<String> = peers[0].rga.read();
+        for peer in &peers[1..] {
+            assert_eq!(peer.rga.read(), state, "replicas diverged with seed {seed}");
+            assert_eq!(peer.rga.version(), peers[0].rga.version());
+        }
+    }
+
+    #[test]
+    fn test_replicas_converge_under_faults() {
+        for seed in 0..20 {
+            simulate(seed);
+        }
+    }
+}
@@ -0,0 +1,304 @@
+use crate::rga::rga::RGA;
+use crate::{S4Vector, VersionVector};
+use std::fmt;
+use tokio::sync::{broadcast, watch};
+
+/// A change to the visible sequence of an `RGA`.
+///
+/// Indices refer to the visible sequence, as returned by `read()`. Changes produced by
+/// a single call are delivered in an order that can be applied one after another to a
+/// copy of the sequence: deletions first, then insertions, then updates.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    /// `value` was inserted at `index`.
+    Insert {
+        index: usize,
+        s4vector: S4Vector,
+        value: String,
+    },
+    /// The element at `index` was deleted.
+    Delete { index: usize, s4vector: S4Vector },
+    /// The value of the element at `index` changed to `value`.
+    Update {
+        index: usize,
+        s4vector: S4Vector,
+        value: String,
+    },
+}
+
+/// Identifies a callback registered with `RGA::subscribe`.
+pub type SubscriptionId = u64;
+
+type Callback = Box<dyn FnMut(&Change) + Send>;
+
+/// The subscribers of an `RGA` and the changes waiting to be delivered to them.
+#[derive(Default)]
+pub(crate) struct Observers {
+    next_id: SubscriptionId,
+    callbacks: Vec<(SubscriptionId, Callback)>,
+    watchers: Vec<watch::Sender<VersionVector>>,
+    pending: Vec<Change>,
+}
+
+impl Observers {
+    /// Checks whether anyone is listening for changes.
+    pub(crate) fn is_active(&self) -> bool {
+        return !self.callbacks.is_empty() || !self.watchers.is_empty();
+    }
+
+    /// Queues changes until the operation that produced them completes.
+    pub(crate) fn record(&mut self, changes: impl IntoIterator<Item = Change>) {
+        self.pending.extend(changes);
+    }
+
+    /// Drops the queued changes, used when a transaction is rolled back.
+    pub(crate) fn discard(&mut self) {
+        self.pending.clear();
+    }
+
+    /// Delivers the queued changes to every subscriber.
+    pub(crate) fn flush(&mut self, version: &VersionVector) {
+        if self.pending.is_empty() {
+            return;
+        }
+
+        for change in std::mem::take(&mut self.pending) {
+            for (_, callback) in self.callbacks.iter_mut() {
+                callback(&change);
+            }
+        }
+
+        self.watchers.retain(|watcher| !watcher.is_closed());
+        for watcher in &self.watchers {
+            watcher.send_replace(version.clone());
+        }
+    }
+}
+
+impl fmt::Debug for Observers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return f
+            .debug_struct("Observers")
+            .field("callbacks", &self.callbacks.len())
+            .field("watchers", &self.watchers.len())
+            .field("pending", &self.pending)
+            .finish();
+    }
+}
+
+impl RGA {
+    /// Registers a callback called with every change to the visible sequence, whether
+    /// it was made locally, received from another replica or released from the buffer.
+    ///
+    /// # Returns
+    /// An identifier that can be passed to `unsubscribe`.
+    ///
+    /// # Example
+    /// ```
+    /// use crdt::rga::rga::RGA;
+    /// use crdt::Change;
+    /// use std::sync::{Arc, Mutex};
+    ///
+    /// let mut rga = RGA::new(1, 1);
+    /// let changes = Arc::new(Mutex::new(Vec::new()));
+    /// let seen = changes.clone();
+    /// rga.subscribe(move |change| seen.lock().unwrap().push(change.clone()));
+    ///
+    /// let a = rga.local_insert("a".to_string(), None, None).unwrap().s4vector;
+    /// rga.local_delete(a).unwrap();
+    ///
+    /// assert!(matches!(changes.lock().unwrap()[..], [
+    ///     Change::Insert { index: 0, .. },
+    ///     Change::Delete { index: 0, .. },
+    /// ]));
+    /// ```
+    pub fn subscribe<F>(&mut self, callback: F) -> SubscriptionId
+    where
+        F: FnMut(&Change) + Send + 'static,
+    {
+        let observers: &mut Observers = self.observers();
+        let id: SubscriptionId = observers.next_id;
+        observers.next_id += 1;
+        observers.callbacks.push((id, Box::new(callback)));
+        return id;
+    }
+
+    /// Removes a callback registered with `subscribe`.
+    ///
+    /// # Returns
+    /// `false` if no callback was registered with `id`.
+    pub fn unsubscribe(&mut self, id: SubscriptionId) -> bool {
+        let observers: &mut Observers = self.observers();
+        let len: usize = observers.callbacks.len();
+        observers.callbacks.retain(|(other, _)| *other != id);
+        return observers.callbacks.len() != len;
+    }
+
+    /// Returns a tokio `broadcast` receiver of every change to the visible sequence.
+    ///
+    /// # Parameters
+    /// - `capacity`: The number of changes kept for slow receivers, which miss the
+    ///   oldest changes once it is exceeded.
+    pub fn subscribe_channel(&mut self, capacity: usize) -> broadcast::Receiver<Change> {
+        let (sender, receiver) = broadcast::channel(capacity);
+        self.subscribe(move |change| {
+            let _ = sender.send(change.clone());
+        });
+        return receiver;
+    }
+
+    /// Returns a tokio `watch` receiver holding the version of the RGA as of its latest
+    /// change, for consumers that only need to know when to read again.
+    pub fn watch(&mut self) -> watch::Receiver<VersionVector> {
+        let (sender, receiver) = watch::channel(self.version().clone());
+        self.observers().watchers.push(sender);
+        return receiver;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{deliver, type_text};
+    use std::sync::{Arc, Mutex};
+
+    fn record(rga: &mut RGA) -> Arc<Mutex<Vec<Change>>> {
+        let changes: Arc<Mutex<Vec<Change>>> = Arc::new(Mutex::new(Vec::new()));
+        let seen: Arc<Mutex<Vec<Change>>> = changes.clone();
+        rga.subscribe(move |change| seen.lock().unwrap().push(change.clone()));
+        return changes;
+    }
+
+    /// Applies changes to a copy of the sequence, as a UI would.
+    fn replay(changes: &[Change], sequence: &mut Vec<String>) {
+        for change in changes {
+            match change {
+                Change::Insert { index, value, .. } => sequence.insert(*index, value.clone()),
+                Change::Delete { index, .. } => {
+                    sequence.remove(*index);
+                }
+                Change::Update { index, value, .. } => sequence[*index] = value.clone(),
+            }
+        }
+    }
+
+    #[test]
+    fn test_local_changes() {
+        let mut rga = RGA::new(1, 1);
+        let changes = record(&mut rga);
+
+        let operations = type_text(&mut rga, None, "abc");
+        rga.local_update(operations[1].s4vector, "B".to_string())
+            .unwrap();
+        rga.local_delete(operations[0].s4vector).unwrap();
+
+        let changes = changes.lock().unwrap();
+        assert_eq!(changes.len(), 5);
+        assert_eq!(
+            changes[3],
+            Change::Update {
+                index: 1,
+                s4vector: operations[1].s4vector,
+                value: "B".to_string()
+            }
+        );
+
+        let mut sequence: Vec<String> = Vec::new();
+        replay(&changes, &mut sequence);
+        assert_eq!(sequence, rga.read());
+    }
+
+    #[test]
+    fn test_remote_and_buffered_changes() {
+        let mut rga1 = RGA::new(1, 1);
+        let mut rga2 = RGA::new(1, 2);
+        let changes = record(&mut rga2);
+
+        let operations = type_text(&mut rga1, None, "abcd");
+        let delete = rga1.local_delete_range_at(1..3).unwrap();
+        let moved = rga1.local_move_at(1, 0).unwrap();
+
+        // The range delete and move are buffered until the inserts arrive
+        rga2.remote_apply(delete);
+        rga2.remote_apply(moved);
+        assert!(changes.lock().unwrap().is_empty());
+        for op in operations {
+            rga2.remote_apply(op);
+        }
+
+        let mut sequence: Vec<String> = Vec::new();
+        replay(&changes.lock().unwrap(), &mut sequence);
+        assert_eq!(sequence, vec!["d".to_string(), "a".to_string()]);
+        assert_eq!(sequence, rga2.read());
+    }
+
+    #[test]
+    fn test_range_changes_include_moved_elements() {
+        let mut rga1 = RGA::new(1, 1);
+        let mut rga2 = RGA::new(1, 2);
+        let operations = type_text(&mut rga1, None, "abcd");
+        deliver(&mut rga2, &operations);
+        let changes = record(&mut rga1);
+
+        // "b" is moved out of the range concurrently with the range being deleted
+        let moved = rga1.local_move_at(1, 3).unwrap();
+        let delete = rga2.local_delete_range_at(0..2).unwrap();
+        rga1.remote_apply(delete);
+        rga2.remote_apply(moved);
+
+        let mut sequence: Vec<String> = operations
+            .iter()
+            .map(|op| op.value.clone().unwrap())
+            .collect();
+        replay(&changes.lock().unwrap(), &mut sequence);
+        assert_eq!(sequence, vec!["c".to_string(), "d".to_string()]);
+        assert_eq!(sequence, rga1.read());
+        assert_eq!(rga1.read(), rga2.read());
+    }
+
+    #[test]
+    fn test_rolled_back_transaction_has_no_changes() {
+        let mut rga = RGA::new(1, 1);
+        let changes = record(&mut rga);
+
+        let result = rga.transaction(|tx| {
+            tx.insert("x".to_string(), None, None)?;
+            Err(crate::OperationError::IndexError)
+        });
+
+        assert!(result.is_err());
+        assert!(changes.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_unsubscribe() {
+        let mut rga = RGA::new(1, 1);
+        let changes = record(&mut rga);
+        let id = rga.subscribe(|_| panic!("unsubscribed callback was called"));
+
+        assert!(rga.unsubscribe(id));
+        assert!(!rga.unsubscribe(id));
+        type_text(&mut rga, None, "a");
+        assert_eq!(changes.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_channels() {
+        let mut rga = RGA::new(1, 1);
+        let mut receiver = rga.subscribe_channel(16);
+        let mut watcher = rga.watch();
+
+        type_text(&mut rga, None, "ab");
+
+        assert!(matches!(
+            receiver.try_recv(),
+            Ok(Change::Insert { index: 0, .. })
+        ));
+        assert!(matches!(
+            receiver.try_recv(),
+            Ok(Change::Insert { index: 1, .. })
+        ));
+        assert!(watcher.has_changed().unwrap());
+        assert_eq!(watcher.borrow_and_update().get(1), 2);
+    }
+}
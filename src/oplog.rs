@@ -0,0 +1,267 @@
+use crate::rga::rga::{BroadcastOperation, RGA};
+use crate::{S4Vector, VersionVector};
+use std::collections::HashSet;
+use std::ops::RangeBounds;
+
+/// `OpLog` is an append-only log of the operations integrated by an `RGA`, local and
+/// remote, in the order they were integrated.
+///
+/// An operation is only integrated once its dependencies are, so the log is in
+/// causal order and replaying it into an empty `RGA` rebuilds the same state. Once
+/// the state has been saved in a snapshot, the operations it covers can be truncated
+/// and the rest replayed into the RGA restored from the snapshot.
+///
+/// State merged by `Reconciliation` does not come from operations, so it is not
+/// recorded.
+///
+/// # Example
+/// ```
+/// use crdt::rga::rga::RGA;
+///
+/// let mut rga = RGA::new(1, 1);
+/// rga.enable_log();
+/// let a = rga.local_insert("a".to_string(), None, None).unwrap();
+/// rga.local_insert("b".to_string(), Some(a.s4vector), None).unwrap();
+/// rga.local_delete(a.s4vector).unwrap();
+///
+/// let mut copy = RGA::new(1, 2);
+/// rga.log().unwrap().replay(&mut copy);
+/// assert_eq!(copy.read(), vec!["b".to_string()]);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct OpLog {
+    /// The version of the snapshot the log starts from, empty if it has never been
+    /// truncated.
+    base: VersionVector,
+    operations: Vec<BroadcastOperation>,
+    /// The ids of the operations in `operations`, used to drop operations recorded twice.
+    ids: HashSet<S4Vector>,
+}
+
+impl OpLog {
+    /// Creates an empty `OpLog`.
+    pub fn new() -> Self {
+        return OpLog::default();
+    }
+
+    /// Creates an empty log starting from `base`, for a replica that already has the
+    /// operations it covers.
+    pub(crate) fn starting_at(base: VersionVector) -> Self {
+        return OpLog {
+            base,
+            ..OpLog::default()
+        };
+    }
+
+    /// Appends an operation to the log.
+    ///
+    /// # Returns
+    /// `false` if the operation is already in the log or was truncated.
+    pub fn record(&mut self, operation: BroadcastOperation) -> bool {
+        if self.base.contains(&operation.id) || !self.ids.insert(operation.id) {
+            return false;
+        }
+        self.operations.push(operation);
+        return true;
+    }
+
+    /// Returns the number of operations in the log.
+    pub fn len(&self) -> usize {
+        return self.operations.len();
+    }
+
+    /// Checks whether the log holds no operations.
+    pub fn is_empty(&self) -> bool {
+        return self.operations.is_empty();
+    }
+
+    /// Returns the version of the snapshot the log starts from.
+    pub fn base(&self) -> &VersionVector {
+        return &self.base;
+    }
+
+    /// Iterates over the operations in the order they were integrated.
+    pub fn iter(&self) -> impl Iterator<Item = &BroadcastOperation> + '_ {
+        return self.operations.iter();
+    }
+
+    /// Iterates over the operations generated by `site_id`.
+    pub fn by_site(&self, site_id: u64) -> impl Iterator<Item = &BroadcastOperation> + '_ {
+        return self
+            .operations
+            .iter()
+            .filter(move |op| op.id.sid == site_id);
+    }
+
+    /// Iterates over the operations generated by `site_id` whose sequence numbers are
+    /// in `seqs`, in order of sequence number.
+    ///
+    /// # Example
+    /// ```
+    /// use crdt::rga::rga::RGA;
+    ///
+    /// let mut rga = RGA::new(1, 1);
+    /// rga.enable_log();
+    /// for value in ["a", "b", "c"] {
+    ///     rga.local_insert(value.to_string(), None, None).unwrap();
+    /// }
+    ///
+    /// let values: Vec<_> = rga.log().unwrap().range(1, 2..).map(|op| op.value.clone().unwrap()).collect();
+    /// assert_eq!(values, vec!["b", "c"]);
+    /// ```
+    pub fn range<R: RangeBounds<u64>>(
+        &self,
+        site_id: u64,
+        seqs: R,
+    ) -> impl Iterator<Item = &BroadcastOperation> + '_ {
+        let mut operations: Vec<&BroadcastOperation> = self
+            .by_site(site_id)
+            .filter(|op| seqs.contains(&op.id.seq))
+            .collect();
+        // Operations of one site can be integrated out of order, for instance when a
+        // replica restores its own edits from another replica
+        operations.sort_by_key(|op| op.id.seq);
+        return operations.into_iter();
+    }
+
+    /// Drops the operations covered by `version`, typically the version of a snapshot
+    /// that has just been saved.
+    ///
+    /// # Returns
+    /// The number of operations dropped.
+    pub fn truncate(&mut self, version: &VersionVector) -> usize {
+        let len: usize = self.operations.len();
+        self.operations.retain(|op| !version.contains(&op.id));
+        self.ids = self.operations.iter().map(|op| op.id).collect();
+        self.base.merge(version);
+        return len - self.operations.len();
+    }
+
+    /// Applies every operation in the log to `into`, in order.
+    ///
+    /// `into` must be empty, or restored from a snapshot covering `base`. Operations it
+    /// has already integrated are applied again, which does not change its state.
+    pub fn replay(&self, into: &mut RGA) {
+        for op in &self.operations {
+            into.remote_apply(op.clone());
+        }
+        into.resume_local_sequence();
+    }
+
+    /// Drops the operations recorded after the first `len`, used when a transaction
+    /// is rolled back.
+    pub(crate) fn rollback(&mut self, len: usize) {
+        for op in self.operations.drain(len..) {
+            self.ids.remove(&op.id);
+        }
+    }
+}
+
+impl RGA {
+    /// Starts recording every operation integrated from now on in an `OpLog`,
+    /// returned by `log`. Does nothing if the log is already enabled.
+    pub fn enable_log(&mut self) {
+        self.log_slot().get_or_insert_with(OpLog::new);
+    }
+
+    /// Stops recording operations.
+    ///
+    /// # Returns
+    /// The log, if it was enabled.
+    pub fn take_log(&mut self) -> Option<OpLog> {
+        return self.log_slot().take();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::OperationType;
+
+    #[test]
+    fn test_log_records_local_and_remote_operations() {
+        let mut rga1 = RGA::new(1, 1);
+        let mut rga2 = RGA::new(1, 2);
+        rga2.enable_log();
+
+        let a = rga1.local_insert("a".to_string(), None, None).unwrap();
+        let b = rga1
+            .local_insert("b".to_string(), Some(a.s4vector), None)
+            .unwrap();
+        let c = rga2.local_insert("c".to_string(), None, None).unwrap();
+
+        // Out of order and duplicated delivery is recorded once, in causal order
+        rga2.remote_apply(b.clone());
+        rga2.remote_apply(a.clone());
+        rga2.remote_apply(a.clone());
+        rga2.local_delete(a.s4vector).unwrap();
+
+        let log: &OpLog = rga2.log().unwrap();
+        let ids: Vec<S4Vector> = log.iter().map(|op| op.id).collect();
+        assert_eq!(ids[..3], [c.id, a.id, b.id]);
+        assert!(matches!(
+            log.iter().last().unwrap().operation,
+            OperationType::Delete
+        ));
+        assert_eq!(log.by_site(1).count(), 2);
+        assert_eq!(log.by_site(2).count(), 2);
+        assert_eq!(
+            log.range(1, ..2).map(|op| op.id).collect::<Vec<_>>(),
+            [a.id]
+        );
+
+        let mut copy = RGA::new(1, 3);
+        log.replay(&mut copy);
+        assert_eq!(copy.read(), rga2.read());
+    }
+
+    #[test]
+    fn test_replay_after_truncation() {
+        let mut rga = RGA::new(1, 1);
+        rga.enable_log();
+        let a = rga.local_insert("a".to_string(), None, None).unwrap();
+        let mut snapshot = RGA::new(1, 1);
+        snapshot.remote_apply(a.clone());
+
+        let version: VersionVector = rga.version().clone();
+        rga.local_insert("b".to_string(), Some(a.s4vector), None)
+            .unwrap();
+        rga.local_update(a.s4vector, "A".to_string()).unwrap();
+
+        let mut log: OpLog = rga.take_log().unwrap();
+        assert_eq!(log.truncate(&version), 1);
+        assert_eq!(log.len(), 2);
+        assert!(!log.record(a));
+
+        // The restored replica continues the sequence of the site
+        log.replay(&mut snapshot);
+        assert_eq!(snapshot.read(), vec!["A", "b"]);
+        let next = snapshot.local_insert("c".to_string(), None, None).unwrap();
+        assert_eq!(next.id.seq, rga.version().get(1) + 1);
+    }
+
+    #[test]
+    fn test_rolled_back_transaction_is_not_logged() {
+        let mut rga = RGA::new(1, 1);
+        rga.enable_log();
+        let result = rga.transaction(|tx| {
+            tx.insert("a".to_string(), None, None)?;
+            tx.delete(S4Vector {
+                ssn: 1,
+                sum: 1,
+                sid: 9,
+                seq: 9,
+            })
+        });
+        assert!(result.is_err());
+        assert!(rga.log().unwrap().is_empty());
+
+        rga.transaction(|tx| {
+            let a = tx.insert("a".to_string(), None, None)?;
+            tx.insert("b".to_string(), Some(a), None)?;
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(rga.log().unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,643 @@
+use crate::history::ElementHistory;
+use crate::rga::rga::{BroadcastOperation, Operation, OperationError, RGA};
+use crate::storage::{FileStorage, Storage};
+use crate::{NodeState, OperationBatch, S4Vector, Transaction, VersionVector};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// The number of batches after which a `DurableReplica` compacts its storage by default.
+const DEFAULT_SNAPSHOT_INTERVAL: usize = 1000;
+
+/// The size of the length and checksum preceding every record of a `Wal`.
+const HEADER_LEN: usize = 8;
+
+#[derive(Debug, thiserror::Error)]
+pub enum PersistenceError {
+    #[error("Storage failed: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("The snapshot is corrupt: {0}")]
+    CorruptSnapshot(#[from] serde_json::Error),
+    /// An error of a `Storage` implemented on top of another system, such as a database.
+    #[error("Storage backend failed: {0}")]
+    Backend(Box<dyn std::error::Error + Send + Sync>),
+    /// A local edit could not be made, so nothing was stored.
+    #[error("The edit failed: {0}")]
+    Edit(#[from] OperationError),
+}
+
+/// The complete state of an `RGA`, including the operations still waiting for their
+/// dependencies, from which `RGA::from_snapshot` rebuilds an identical replica.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub session_id: u64,
+    pub site_id: u64,
+    /// The local logical clock.
+    pub local_sequence: u64,
+    pub version: VersionVector,
+    /// Sequence numbers integrated ahead of `version`, for each site.
+    pub out_of_order: BTreeMap<u64, BTreeSet<u64>>,
+    pub nodes: Vec<NodeState>,
+    /// The groups of operations waiting for their dependencies.
+    pub buffer: Vec<Vec<BroadcastOperation>>,
+    /// The deletes and values of the elements that have been deleted or updated, used
+    /// by `read_at` and `checkout`.
+    #[serde(default)]
+    pub history: Vec<ElementHistory>,
+}
+
+impl RGA {
+    /// Captures the state of the RGA.
+    pub fn snapshot(&self) -> Snapshot {
+        return Snapshot {
+            session_id: self.session_id(),
+            site_id: self.site_id(),
+            local_sequence: self.local_sequence(),
+            version: self.version().clone(),
+            out_of_order: self
+                .out_of_order()
+                .iter()
+                .map(|(sid, seqs)| (*sid, seqs.clone()))
+                .collect(),
+            nodes: self.node_states(),
+            buffer: self
+                .buffered()
+                .map(|group| {
+                    group
+                        .iter()
+                        .cloned()
+                        .map(BroadcastOperation::from)
+                        .collect()
+                })
+                .collect(),
+            history: self.history(),
+        };
+    }
+
+    /// Rebuilds an RGA from a snapshot.
+    ///
+    /// # Example
+    /// ```
+    /// use crdt::rga::rga::RGA;
+    ///
+    /// let mut rga = RGA::new(1, 1);
+    /// rga.local_insert("a".to_string(), None, None).unwrap();
+    ///
+    /// let restored = RGA::from_snapshot(rga.snapshot());
+    /// assert_eq!(restored.read(), rga.read());
+    /// assert_eq!(restored.version(), rga.version());
+    /// ```
+    pub fn from_snapshot(snapshot: Snapshot) -> Self {
+        let mut rga: RGA = RGA::new(snapshot.session_id, snapshot.site_id);
+        rga.merge_node_states(snapshot.nodes);
+        rga.restore_history(snapshot.history);
+
+        let out_of_order: HashMap<u64, BTreeSet<u64>> = snapshot.out_of_order.into_iter().collect();
+        let buffer: VecDeque<Vec<Operation>> = snapshot
+            .buffer
+            .into_iter()
+            .map(|group| group.into_iter().map(Operation::from).collect())
+            .collect();
+        rga.restore(
+            snapshot.local_sequence,
+            snapshot.version,
+            out_of_order,
+            buffer,
+        );
+        return rga;
+    }
+}
+
+/// `Wal` is an append-only file of `OperationBatch`es, written before the batches are
+/// applied so they survive a crash.
+///
+/// Every record is the length of the batch encoded as JSON and its CRC-32, both as
+/// little-endian `u32`s, followed by the JSON. A crash in the middle of an append
+/// leaves a record that is cut short or fails its checksum; opening the log keeps the
+/// records before the first such record and discards the rest.
+#[derive(Debug)]
+pub struct Wal {
+    file: File,
+    records: usize,
+}
+
+impl Wal {
+    /// Opens the log at `path`, creating it if needed.
+    ///
+    /// # Returns
+    /// The log and the batches recorded in it, oldest first.
+    pub fn open(path: impl AsRef<Path>) -> Result<(Self, Vec<OperationBatch>), PersistenceError> {
+        let mut file: File = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)?;
+        let mut bytes: Vec<u8> = Vec::new();
+        file.read_to_end(&mut bytes)?;
+
+        let (batches, valid) = decode(&bytes);
+        if valid < bytes.len() {
+            // Drop the torn tail so new records are not appended after it
+            file.set_len(valid as u64)?;
+            file.sync_all()?;
+        }
+
+        let wal: Wal = Wal {
+            file,
+            records: batches.len(),
+        };
+        return Ok((wal, batches));
+    }
+
+    /// Appends a batch and waits for it to reach the disk.
+    pub fn append(&mut self, batch: &OperationBatch) -> Result<(), PersistenceError> {
+        let payload: Vec<u8> = serde_json::to_vec(batch)?;
+        let mut record: Vec<u8> = Vec::with_capacity(HEADER_LEN + payload.len());
+        record.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        record.extend_from_slice(&crc32(&payload).to_le_bytes());
+        record.extend_from_slice(&payload);
+
+        self.file.write_all(&record)?;
+        self.file.sync_data()?;
+        self.records += 1;
+        return Ok(());
+    }
+
+    /// Reads every record of the log.
+    pub fn read(&mut self) -> Result<Vec<OperationBatch>, PersistenceError> {
+        let mut bytes: Vec<u8> = Vec::new();
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.read_to_end(&mut bytes)?;
+        return Ok(decode(&bytes).0);
+    }
+
+    /// Returns the number of records in the log.
+    pub fn len(&self) -> usize {
+        return self.records;
+    }
+
+    /// Checks whether the log holds no records.
+    pub fn is_empty(&self) -> bool {
+        return self.records == 0;
+    }
+
+    /// Removes every record, once they are covered by a snapshot.
+    pub fn clear(&mut self) -> Result<(), PersistenceError> {
+        self.file.set_len(0)?;
+        self.file.sync_all()?;
+        self.records = 0;
+        return Ok(());
+    }
+}
+
+/// Decodes the records of a log.
+///
+/// # Returns
+/// The batches of the valid records and the number of bytes they take up.
+fn decode(bytes: &[u8]) -> (Vec<OperationBatch>, usize) {
+    let mut batches: Vec<OperationBatch> = Vec::new();
+    let mut offset: usize = 0;
+
+    while bytes.len() - offset >= HEADER_LEN {
+        let header: &[u8] = &bytes[offset..offset + HEADER_LEN];
+        let len: usize = u32::from_le_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        let checksum: u32 = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+
+        let start: usize = offset + HEADER_LEN;
+        let Some(payload) = bytes.get(start..start + len) else {
+            break;
+        };
+        if crc32(payload) != checksum {
+            break;
+        }
+        let Ok(batch) = serde_json::from_slice::<OperationBatch>(payload) else {
+            break;
+        };
+
+        batches.push(batch);
+        offset = start + len;
+    }
+    return (batches, offset);
+}
+
+/// Computes the CRC-32 (IEEE) checksum of `bytes`.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc: u32 = !0;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = match crc & 1 {
+                1 => (crc >> 1) ^ 0xedb8_8320,
+                _ => crc >> 1,
+            };
+        }
+    }
+    return !crc;
+}
+
+/// `DurableReplica` keeps an `RGA` in a `Storage` so it survives restarts and crashes.
+///
+/// Every batch, local or remote, is appended to the storage before it takes effect.
+/// Local edits made through `insert`, `delete`, `update` or `transaction` are only
+/// returned once stored, so an operation that reaches another replica is never
+/// generated again after a crash. Once enough batches have been appended the storage
+/// is compacted: the state of the RGA is saved as a snapshot, replacing the previous
+/// one, and the batches it covers are dropped. Opening a replica loads the snapshot
+/// and replays the batches appended since.
+///
+/// # Example
+/// ```no_run
+/// use crdt::DurableReplica;
+///
+/// let mut replica = DurableReplica::open("notes", 1, 1).unwrap();
+/// replica.insert("a".to_string(), None, None).unwrap();
+///
+/// drop(replica);
+/// let replica = DurableReplica::open("notes", 1, 1).unwrap();
+/// assert_eq!(replica.rga().read(), vec!["a".to_string()]);
+/// ```
+#[derive(Debug)]
+pub struct DurableReplica<S: Storage = FileStorage> {
+    storage: S,
+    rga: RGA,
+    snapshot_interval: usize,
+}
+
+impl DurableReplica<FileStorage> {
+    /// Opens the replica stored in `directory`, creating an empty one if the
+    /// directory holds none.
+    ///
+    /// # Parameters
+    /// - `directory`: The directory holding the snapshot and the log.
+    /// - `session_id`: The session ID of a new replica.
+    /// - `site_id`: The site ID of a new replica.
+    ///
+    /// # Returns
+    /// `PersistenceError::CorruptSnapshot` if the snapshot cannot be read.
+    pub fn open(
+        directory: impl AsRef<Path>,
+        session_id: u64,
+        site_id: u64,
+    ) -> Result<Self, PersistenceError> {
+        return DurableReplica::with_storage(FileStorage::open(directory)?, session_id, site_id);
+    }
+}
+
+impl<S: Storage> DurableReplica<S> {
+    /// Opens the replica kept in `storage`, creating an empty one if the storage
+    /// holds none.
+    ///
+    /// # Parameters
+    /// - `storage`: The storage holding the replica.
+    /// - `session_id`: The session ID of a new replica.
+    /// - `site_id`: The site ID of a new replica.
+    pub fn with_storage(
+        mut storage: S,
+        session_id: u64,
+        site_id: u64,
+    ) -> Result<Self, PersistenceError> {
+        let mut rga: RGA = match storage.load_snapshot()? {
+            Some(snapshot) => RGA::from_snapshot(snapshot),
+            None => RGA::new(session_id, site_id),
+        };
+
+        // A crash while compacting can leave batches the snapshot already covers
+        let version: VersionVector = rga.version().clone();
+        for batch in storage.read_since(&version)? {
+            rga.remote_apply_batch(batch);
+        }
+        rga.resume_local_sequence();
+
+        return Ok(DurableReplica {
+            storage,
+            rga,
+            snapshot_interval: DEFAULT_SNAPSHOT_INTERVAL,
+        });
+    }
+
+    /// Returns the local replica.
+    pub fn rga(&self) -> &RGA {
+        return &self.rga;
+    }
+
+    /// Returns the local replica to make local edits that have no method on
+    /// `DurableReplica`, such as moves, which must then be passed to `record`.
+    pub fn rga_mut(&mut self) -> &mut RGA {
+        return &mut self.rga;
+    }
+
+    /// Returns the storage of the replica.
+    pub fn storage(&self) -> &S {
+        return &self.storage;
+    }
+
+    /// Closes the replica, returning its storage so it can be opened again.
+    pub fn into_storage(self) -> S {
+        return self.storage;
+    }
+
+    /// Sets the number of batches after which the storage is compacted.
+    pub fn set_snapshot_interval(&mut self, batches: usize) {
+        self.snapshot_interval = batches.max(1);
+    }
+
+    /// Inserts a new value, see `RGA::local_insert`, storing the operation before it
+    /// takes effect.
+    ///
+    /// # Returns
+    /// The operation to broadcast. If the edit fails or cannot be stored, nothing is
+    /// applied.
+    pub fn insert(
+        &mut self,
+        value: String,
+        left: Option<S4Vector>,
+        right: Option<S4Vector>,
+    ) -> Result<BroadcastOperation, PersistenceError> {
+        let mut batch: OperationBatch = self.transaction(|tx| {
+            tx.insert(value, left, right)?;
+            Ok(())
+        })?;
+        return Ok(batch.operations.remove(0));
+    }
+
+    /// Marks a node as logically deleted, see `RGA::local_delete`, storing the
+    /// operation before it takes effect.
+    pub fn delete(&mut self, s4vector: S4Vector) -> Result<BroadcastOperation, PersistenceError> {
+        let mut batch: OperationBatch = self.transaction(|tx| tx.delete(s4vector))?;
+        return Ok(batch.operations.remove(0));
+    }
+
+    /// Updates the value of a node, see `RGA::local_update`, storing the operation
+    /// before it takes effect.
+    pub fn update(
+        &mut self,
+        s4vector: S4Vector,
+        value: String,
+    ) -> Result<BroadcastOperation, PersistenceError> {
+        let mut batch: OperationBatch = self.transaction(|tx| tx.update(s4vector, value))?;
+        return Ok(batch.operations.remove(0));
+    }
+
+    /// Applies several local edits, see `RGA::transaction`, storing their batch
+    /// before any of them takes effect.
+    ///
+    /// # Returns
+    /// The batch to broadcast. If an edit fails or the batch cannot be stored, none
+    /// of the edits are kept.
+    pub fn transaction<F>(&mut self, f: F) -> Result<OperationBatch, PersistenceError>
+    where
+        F: FnOnce(&mut Transaction<'_>) -> Result<(), OperationError>,
+    {
+        // Compacting first means a failure never hides an edit that has been stored
+        self.compact_if_needed()?;
+
+        let storage: &mut S = &mut self.storage;
+        return self.rga.transaction_with(f, |batch| {
+            if batch.operations.is_empty() {
+                return Ok(());
+            }
+            return storage.append(batch);
+        });
+    }
+
+    /// Persists the batch of a local edit made through `rga_mut`.
+    ///
+    /// The edit has already been applied, so it is lost if the process stops before
+    /// this returns, and a later edit may be given the same `S4Vector`. The batch must
+    /// only be broadcast once this returns.
+    pub fn record(&mut self, batch: &OperationBatch) -> Result<(), PersistenceError> {
+        self.storage.append(batch)?;
+        return self.compact_if_needed();
+    }
+
+    /// Persists a batch received from another replica and applies it.
+    pub fn apply(&mut self, batch: OperationBatch) -> Result<(), PersistenceError> {
+        self.storage.append(&batch)?;
+        self.rga.remote_apply_batch(batch);
+        return self.compact_if_needed();
+    }
+
+    /// Saves a snapshot of the RGA, dropping the batches it covers.
+    pub fn compact(&mut self) -> Result<(), PersistenceError> {
+        return self.storage.compact(&self.rga.snapshot());
+    }
+
+    fn compact_if_needed(&mut self) -> Result<(), PersistenceError> {
+        if self.storage.appended() >= self.snapshot_interval {
+            return self.compact();
+        }
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{test_directory, SNAPSHOT_FILE, WAL_FILE};
+    use crate::test_support::append;
+    use crate::{Change, MemoryStorage};
+    use std::fs;
+    use std::path::PathBuf;
+    use tokio::sync::broadcast;
+
+    /// A `MemoryStorage` whose appends fail while `full` is set, as on a full disk.
+    #[derive(Debug, Default)]
+    struct FullStorage {
+        inner: MemoryStorage,
+        full: bool,
+    }
+
+    impl Storage for FullStorage {
+        fn load_snapshot(&mut self) -> Result<Option<Snapshot>, PersistenceError> {
+            return self.inner.load_snapshot();
+        }
+
+        fn append(&mut self, batch: &OperationBatch) -> Result<(), PersistenceError> {
+            if self.full {
+                return Err(std::io::Error::other("disk full").into());
+            }
+            return self.inner.append(batch);
+        }
+
+        fn read_since(
+            &mut self,
+            version: &VersionVector,
+        ) -> Result<Vec<OperationBatch>, PersistenceError> {
+            return self.inner.read_since(version);
+        }
+
+        fn appended(&self) -> usize {
+            return self.inner.appended();
+        }
+
+        fn compact(&mut self, snapshot: &Snapshot) -> Result<(), PersistenceError> {
+            return self.inner.compact(snapshot);
+        }
+    }
+
+    #[test]
+    fn test_snapshot_restores_identical_replica() {
+        let mut rga1 = RGA::new(1, 1);
+        let mut rga2 = RGA::new(1, 2);
+        let a = rga1.local_insert("a".to_string(), None, None).unwrap();
+        let b = rga1
+            .local_insert("b".to_string(), Some(a.s4vector), None)
+            .unwrap();
+        let c = rga1
+            .local_insert("c".to_string(), Some(b.s4vector), None)
+            .unwrap();
+        rga1.local_update(a.s4vector, "A".to_string()).unwrap();
+        rga1.local_move_at(2, 0).unwrap();
+        let delete = rga1.local_delete(b.s4vector).unwrap();
+
+        // `c` arrives ahead of `b`, so it waits in the buffer
+        rga2.remote_apply(a);
+        rga2.remote_apply(c);
+        let snapshot: Snapshot = rga2.snapshot();
+        let json: String = serde_json::to_string(&snapshot).unwrap();
+        let mut restored = RGA::from_snapshot(serde_json::from_str(&json).unwrap());
+        assert_eq!(restored.node_states(), rga2.node_states());
+        assert_eq!(restored.version(), rga2.version());
+
+        for op in [b, delete] {
+            restored.remote_apply(op.clone());
+            rga2.remote_apply(op);
+        }
+        assert_eq!(restored.read(), rga2.read());
+        assert_eq!(restored.read(), vec!["a", "c"]);
+        assert_eq!(RGA::from_snapshot(rga1.snapshot()).read(), rga1.read());
+    }
+
+    #[test]
+    fn test_replica_survives_restart() {
+        let path: PathBuf = test_directory("restart");
+        let mut replica = DurableReplica::open(&path, 1, 1).unwrap();
+        replica.set_snapshot_interval(3);
+        for value in ["a", "b", "c", "d"] {
+            append(&mut replica, value);
+        }
+
+        let mut peer = RGA::new(1, 2);
+        let op = peer.local_insert("x".to_string(), None, None).unwrap();
+        replica.apply(op.into()).unwrap();
+        let read: Vec<String> = replica.rga().read();
+        drop(replica);
+
+        // The snapshot holds the first three edits and the log the last two
+        let mut replica = DurableReplica::open(&path, 1, 1).unwrap();
+        assert_eq!(replica.storage().appended(), 2);
+        assert_eq!(replica.rga().read(), read);
+
+        // Sequence numbers continue where the replica left off
+        let op = append(&mut replica, "e");
+        assert_eq!(op.id.seq, 5);
+        fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn test_local_edits_are_stored_before_they_take_effect() {
+        let mut replica = DurableReplica::with_storage(FullStorage::default(), 1, 1).unwrap();
+        let a = append(&mut replica, "a");
+        let mut changes: broadcast::Receiver<Change> = replica.rga_mut().subscribe_channel(16);
+
+        // Edits that cannot be stored are neither applied nor given an id
+        replica.storage.full = true;
+        assert!(matches!(
+            replica.insert("b".to_string(), Some(a.s4vector), None),
+            Err(PersistenceError::Io(_))
+        ));
+        assert!(matches!(
+            replica.update(a.s4vector, "A".to_string()),
+            Err(PersistenceError::Io(_))
+        ));
+        assert_eq!(replica.rga().read(), vec!["a".to_string()]);
+        assert!(changes.try_recv().is_err());
+
+        replica.storage.full = false;
+        let b = replica
+            .insert("b".to_string(), Some(a.s4vector), None)
+            .unwrap();
+        assert_eq!(b.id.seq, 2);
+        assert!(matches!(
+            replica.delete(b.s4vector.offset(5)),
+            Err(PersistenceError::Edit(OperationError::DependancyError))
+        ));
+
+        // Every edit that was returned survives a restart
+        let read: Vec<String> = replica.rga().read();
+        let replica = DurableReplica::with_storage(replica.into_storage(), 1, 1).unwrap();
+        assert_eq!(replica.rga().read(), read);
+        assert_eq!(read, vec!["a".to_string(), "b".to_string()]);
+    }
+
+    #[test]
+    fn test_torn_writes_keep_complete_records() {
+        let path: PathBuf = test_directory("torn");
+        let mut replica = DurableReplica::open(&path, 1, 1).unwrap();
+        for value in ["a", "b", "c"] {
+            append(&mut replica, value);
+        }
+        drop(replica);
+
+        let wal: PathBuf = path.join(WAL_FILE);
+        let bytes: Vec<u8> = fs::read(&wal).unwrap();
+        let (_, valid) = decode(&bytes);
+        assert_eq!(valid, bytes.len());
+
+        // Cut the log at every byte, as if the machine crashed mid-write
+        let mut records: Vec<usize> = Vec::new();
+        for cut in 0..=bytes.len() {
+            fs::write(&wal, &bytes[..cut]).unwrap();
+            let mut replica = DurableReplica::open(&path, 1, 1).unwrap();
+            let kept: usize = replica.rga().read().len();
+            records.push(kept);
+
+            // New records follow the last complete one
+            append(&mut replica, "z");
+            drop(replica);
+            let replica = DurableReplica::open(&path, 1, 1).unwrap();
+            assert_eq!(replica.rga().read().len(), kept + 1);
+        }
+        assert_eq!(records.first(), Some(&0));
+        assert_eq!(records.last(), Some(&3));
+        assert!(records.windows(2).all(|pair| pair[0] <= pair[1]));
+
+        // A corrupted record and everything after it are dropped
+        let mut corrupted: Vec<u8> = bytes.clone();
+        corrupted[HEADER_LEN + 2] ^= 0xff;
+        fs::write(&wal, &corrupted).unwrap();
+        let replica = DurableReplica::open(&path, 1, 1).unwrap();
+        assert!(replica.rga().read().is_empty());
+        fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn test_crash_during_compaction() {
+        let path: PathBuf = test_directory("compaction");
+        let mut replica = DurableReplica::open(&path, 1, 1).unwrap();
+        for value in ["a", "b"] {
+            append(&mut replica, value);
+        }
+        let read: Vec<String> = replica.rga().read();
+
+        // A crash after the snapshot was written but before the log was cleared
+        let bytes: Vec<u8> = serde_json::to_vec(&replica.rga().snapshot()).unwrap();
+        fs::write(path.join(SNAPSHOT_FILE), bytes).unwrap();
+        fs::write(path.join(format!("{SNAPSHOT_FILE}.tmp")), b"{\"torn").unwrap();
+        drop(replica);
+
+        let replica = DurableReplica::open(&path, 1, 1).unwrap();
+        assert_eq!(replica.rga().read(), read);
+
+        // A corrupt snapshot is reported instead of silently losing the replica
+        drop(replica);
+        fs::write(path.join(SNAPSHOT_FILE), b"{\"torn").unwrap();
+        assert!(matches!(
+            DurableReplica::open(&path, 1, 1),
+            Err(PersistenceError::CorruptSnapshot(_))
+        ));
+        fs::remove_dir_all(&path).unwrap();
+    }
+}
@@ -0,0 +1,150 @@
+use crate::{OperationBatch, VersionVector};
+use futures_util::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite;
+use tokio_tungstenite::WebSocketStream;
+
+/// A message exchanged between a sync client and a `SyncServer`.
+///
+/// Every message is a JSON object tagged by `type`, sent as a single line over TCP
+/// or as a single text frame over WebSocket. A session goes through the following
+/// steps:
+///
+/// 1. The client sends `hello` with the document it wants to edit, its site ID and
+///    the version of its replica.
+/// 2. The server answers `welcome` with the version of its copy of the document, or
+///    `rejected` and closes the connection.
+/// 3. The server sends a `batch` for every batch in its log the client has not seen,
+///    and the client sends a `batch` for every batch it has that the server has not.
+/// 4. Both sides then send a `batch` for every new edit, the server relaying the
+///    edits of every other client of the document. At any point after `welcome` the
+///    client may send `request_snapshot`, which the server answers with `snapshot`.
+///
+/// Version vectors are objects mapping site IDs, as strings, to the highest sequence
+/// number seen from that site. Batches are objects with an `operations` array of
+/// `BroadcastOperation`s.
+///
+/// # Example
+/// ```
+/// use crdt::{Message, VersionVector};
+///
+/// let mut version = VersionVector::new();
+/// version.set(1, 3);
+/// let hello = Message::Hello {
+///     document: "notes".to_string(),
+///     site_id: 2,
+///     version,
+/// };
+/// let line = serde_json::to_string(&hello).unwrap();
+/// assert_eq!(line, r#"{"type":"hello","document":"notes","site_id":2,"version":{"1":3}}"#);
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Message {
+    Hello {
+        document: String,
+        site_id: u64,
+        version: VersionVector,
+    },
+    Welcome {
+        version: VersionVector,
+    },
+    Rejected {
+        reason: String,
+    },
+    Batch {
+        batch: OperationBatch,
+    },
+    RequestSnapshot,
+    /// The visible values of the server's copy of the document, so a client can
+    /// display it before, or instead of, keeping a replica.
+    Snapshot {
+        version: VersionVector,
+        values: Vec<String>,
+    },
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SyncError {
+    #[error("Connection failed: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Received a malformed message: {0}")]
+    Malformed(#[from] serde_json::Error),
+    #[error("Received an unexpected message")]
+    UnexpectedMessage,
+    #[error("The server rejected the connection: {0}")]
+    Rejected(String),
+    #[error("The connection has been closed")]
+    Disconnected,
+    #[error("WebSocket connection failed: {0}")]
+    WebSocket(#[from] tungstenite::Error),
+}
+
+/// A connection carrying `Message`s, over which the server runs a session.
+pub(crate) trait Channel: Send {
+    async fn send(&mut self, message: &Message) -> Result<(), SyncError>;
+
+    /// Waits for the next message.
+    ///
+    /// # Returns
+    /// `None` once the other side has closed the connection. No message is lost if
+    /// the future is dropped, so it can be used in `tokio::select!`.
+    async fn receive(&mut self) -> Result<Option<Message>, SyncError>;
+}
+
+/// A TCP connection sending one JSON `Message` per line.
+#[derive(Debug)]
+pub(crate) struct Connection {
+    reader: Lines<BufReader<OwnedReadHalf>>,
+    writer: OwnedWriteHalf,
+}
+
+impl Connection {
+    pub(crate) fn new(stream: TcpStream) -> Self {
+        let (reader, writer) = stream.into_split();
+        return Connection {
+            reader: BufReader::new(reader).lines(),
+            writer,
+        };
+    }
+}
+
+impl Channel for Connection {
+    async fn send(&mut self, message: &Message) -> Result<(), SyncError> {
+        let mut line: String = serde_json::to_string(message)?;
+        line.push('\n');
+        self.writer.write_all(line.as_bytes()).await?;
+        return Ok(());
+    }
+
+    async fn receive(&mut self) -> Result<Option<Message>, SyncError> {
+        return match self.reader.next_line().await? {
+            Some(line) => Ok(Some(serde_json::from_str(&line)?)),
+            None => Ok(None),
+        };
+    }
+}
+
+impl Channel for WebSocketStream<TcpStream> {
+    async fn send(&mut self, message: &Message) -> Result<(), SyncError> {
+        let text: String = serde_json::to_string(message)?;
+        SinkExt::send(self, tungstenite::Message::text(text)).await?;
+        return Ok(());
+    }
+
+    async fn receive(&mut self) -> Result<Option<Message>, SyncError> {
+        while let Some(frame) = self.next().await {
+            match frame? {
+                tungstenite::Message::Text(text) => return Ok(Some(serde_json::from_str(&text)?)),
+                tungstenite::Message::Binary(_) => return Err(SyncError::UnexpectedMessage),
+                tungstenite::Message::Close(_) => return Ok(None),
+                // Pings are answered by tungstenite
+                _ => continue,
+            }
+        }
+        return Ok(None);
+    }
+}
@@ -0,0 +1,647 @@
+use crate::rga::rga::{Node, Operation, OperationType, RGA};
+use crate::{S4Vector, VersionVector};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::ops::Bound;
+
+/// Ranges holding at most this many nodes are sent as nodes instead of fingerprints.
+const NODE_THRESHOLD: usize = 8;
+
+/// The number of subranges a range whose fingerprints differ is split into.
+const SPLIT: usize = 16;
+
+/// The states of the nodes of a replica in order of `S4Vector`, with their digests.
+type NodeIndex = BTreeMap<S4Vector, (NodeState, u64)>;
+
+/// The state of a single element of an `RGA`, or of a position an element was moved
+/// to, independent of how the element is stored in runs.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NodeState {
+    pub s4vector: S4Vector,
+    /// The value of the element, empty for a position.
+    pub value: String,
+    /// The left origin of the element.
+    pub left: Option<S4Vector>,
+    /// The right origin of the element.
+    pub right: Option<S4Vector>,
+    pub tombstone: bool,
+    /// The `S4Vector` of the update that set the value, if any.
+    pub updated: Option<S4Vector>,
+    /// For a position, the `S4Vector` of the element moved there.
+    pub target: Option<S4Vector>,
+}
+
+impl NodeState {
+    /// Returns the states of the elements held by `node`.
+    fn of(node: &Node) -> impl Iterator<Item = NodeState> + '_ {
+        let mut chars = node.value.chars();
+        return (0..node.len).map(move |i| {
+            let s4vector: S4Vector = node.s4vector.offset(i as u64);
+            let value: String = match (node.len, node.target) {
+                (_, Some(_)) => String::new(),
+                (1, None) => node.value.clone(),
+                _ => chars.next().map(String::from).unwrap_or_default(),
+            };
+            NodeState {
+                s4vector,
+                value,
+                left: match i {
+                    0 => node.left,
+                    _ => Some(node.s4vector.offset(i as u64 - 1)),
+                },
+                right: node.origin_right,
+                tombstone: node.tombstone,
+                updated: node.updated,
+                target: node.target.map(|target| target.offset(i as u64)),
+            }
+        });
+    }
+
+    /// Hashes the state with 64-bit FNV-1a, which gives the same result on every
+    /// platform and compiler version.
+    ///
+    /// FNV-1a alone changes by nearly the same amount for the same edit to different
+    /// nodes, so differences could cancel out in a sum of digests. The hash is mixed
+    /// with the MurmurHash3 finalizer to prevent that.
+    fn digest(&self) -> u64 {
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        let mut write = |bytes: &[u8]| {
+            for byte in bytes {
+                hash ^= *byte as u64;
+                hash = hash.wrapping_mul(0x0100_0000_01b3);
+            }
+        };
+        let mut write_s4 = |s4vector: Option<S4Vector>| match s4vector {
+            Some(s4) => {
+                write(&[1]);
+                for part in [s4.ssn, s4.sum, s4.sid, s4.seq] {
+                    write(&part.to_le_bytes());
+                }
+            }
+            None => write(&[0]),
+        };
+
+        write_s4(Some(self.s4vector));
+        write_s4(self.left);
+        write_s4(self.right);
+        write_s4(self.updated);
+        write_s4(self.target);
+        write(&[self.tombstone as u8]);
+        write(&(self.value.len() as u64).to_le_bytes());
+        write(self.value.as_bytes());
+
+        hash ^= hash >> 33;
+        hash = hash.wrapping_mul(0xff51_afd7_ed55_8ccd);
+        hash ^= hash >> 33;
+        hash = hash.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+        hash ^= hash >> 33;
+        return hash;
+    }
+}
+
+/// What one replica holds in a range of `S4Vector`s.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Summary {
+    /// The sum of the digests of the nodes in the range.
+    Fingerprint(u64),
+    /// The nodes in the range. If `reply` is set the receiver answers with the nodes
+    /// it holds in the range that differ.
+    Nodes { nodes: Vec<NodeState>, reply: bool },
+}
+
+/// The summary of the nodes from `lower` up to, but excluding, `upper`. A missing
+/// bound leaves that side of the range open.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RangeSummary {
+    pub lower: Option<S4Vector>,
+    pub upper: Option<S4Vector>,
+    pub summary: Summary,
+}
+
+/// A message of a reconciliation, carrying the ranges still to be compared and the
+/// version of the sender when the reconciliation started. A message without ranges
+/// ends the reconciliation.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReconcileMessage {
+    pub ranges: Vec<RangeSummary>,
+    pub version: VersionVector,
+}
+
+/// `Reconciliation` brings two replicas to the same state by comparing hashes of
+/// their nodes instead of their version vectors, so it works even when the
+/// operations one replica is missing are no longer kept anywhere.
+///
+/// The nodes of both replicas are ordered by `S4Vector`. The initiator sends a
+/// fingerprint of all of its nodes; a replica receiving a fingerprint that differs
+/// from its own splits the range into subranges and sends their fingerprints back,
+/// until the ranges are small enough to send their nodes. Ranges with equal
+/// fingerprints are never looked at again, so `d` differing nodes among `n` are found
+/// in `O(log n)` round trips, transferring `O(d log n)` fingerprints and only the
+/// nodes that differ and their neighbours in small ranges.
+///
+/// Received nodes are merged into the RGA: missing elements are inserted at their
+/// origins, deletes win over inserts and updates are last-writer-wins. Once every
+/// range matches, each replica merges the other's version vector.
+///
+/// # Example
+/// ```
+/// use crdt::rga::rga::RGA;
+/// use crdt::Reconciliation;
+///
+/// let mut rga1 = RGA::new(1, 1);
+/// let mut rga2 = RGA::new(1, 2);
+/// rga1.local_insert("a".to_string(), None, None).unwrap();
+/// rga2.local_insert("b".to_string(), None, None).unwrap();
+///
+/// let (mut initiator, mut message) = Reconciliation::start(&rga1);
+/// let mut responder = Reconciliation::accept(&rga2);
+/// loop {
+///     message = match responder.handle(&mut rga2, message) {
+///         Some(reply) => reply,
+///         None => break,
+///     };
+///     message = match initiator.handle(&mut rga1, message) {
+///         Some(reply) => reply,
+///         None => break,
+///     };
+/// }
+///
+/// assert_eq!(rga1.read(), rga2.read());
+/// assert_eq!(rga1.version(), rga2.version());
+/// ```
+#[derive(Debug, Clone)]
+pub struct Reconciliation {
+    /// The version of the local replica when the reconciliation started. Edits made
+    /// since are not guaranteed to reach the other replica.
+    version: VersionVector,
+    /// The nodes of the local replica, kept across rounds and only rebuilt once nodes
+    /// received from the other replica have been merged.
+    nodes: NodeIndex,
+    done: bool,
+}
+
+impl Reconciliation {
+    /// Starts a reconciliation.
+    ///
+    /// # Returns
+    /// The reconciliation and the first message to send to the other replica.
+    pub fn start(rga: &RGA) -> (Self, ReconcileMessage) {
+        let reconciliation: Reconciliation = Reconciliation::accept(rga);
+        let message: ReconcileMessage = ReconcileMessage {
+            ranges: vec![RangeSummary {
+                lower: None,
+                upper: None,
+                summary: Summary::Fingerprint(fingerprint(&reconciliation.nodes, None, None)),
+            }],
+            version: rga.version().clone(),
+        };
+        return (reconciliation, message);
+    }
+
+    /// Accepts a reconciliation started by another replica.
+    pub fn accept(rga: &RGA) -> Self {
+        return Reconciliation {
+            version: rga.version().clone(),
+            nodes: rga.node_index(),
+            done: false,
+        };
+    }
+
+    /// Checks whether both replicas are known to hold the same nodes.
+    pub fn is_done(&self) -> bool {
+        return self.done;
+    }
+
+    /// Handles a message from the other replica, merging any nodes it carries.
+    ///
+    /// # Returns
+    /// The message to send back, or `None` once the reconciliation is over.
+    pub fn handle(&mut self, rga: &mut RGA, message: ReconcileMessage) -> Option<ReconcileMessage> {
+        if self.done {
+            return None;
+        }
+        if message.ranges.is_empty() {
+            self.finish(rga, &message.version);
+            return None;
+        }
+
+        let mut received: Vec<NodeState> = Vec::new();
+        for range in &message.ranges {
+            if let Summary::Nodes { nodes, .. } = &range.summary {
+                received.extend(nodes.iter().cloned());
+            }
+        }
+        if !received.is_empty() {
+            rga.integrate_node_states(received);
+            self.nodes = rga.node_index();
+        }
+        let nodes: &NodeIndex = &self.nodes;
+
+        let mut ranges: Vec<RangeSummary> = Vec::new();
+        for range in message.ranges {
+            let (lower, upper) = (range.lower, range.upper);
+            match range.summary {
+                Summary::Fingerprint(theirs) => {
+                    if fingerprint(nodes, lower, upper) != theirs {
+                        ranges.extend(split(nodes, lower, upper));
+                    }
+                }
+                Summary::Nodes {
+                    nodes: theirs,
+                    reply,
+                } => {
+                    let theirs: BTreeMap<S4Vector, u64> = theirs
+                        .iter()
+                        .map(|node| (node.s4vector, node.digest()))
+                        .collect();
+                    let differing: Vec<NodeState> = in_range(nodes, lower, upper)
+                        .filter(|(node, digest)| theirs.get(&node.s4vector) != Some(digest))
+                        .map(|(node, _)| node.clone())
+                        .collect();
+                    if reply && !differing.is_empty() {
+                        ranges.push(RangeSummary {
+                            lower,
+                            upper,
+                            summary: Summary::Nodes {
+                                nodes: differing,
+                                reply: false,
+                            },
+                        });
+                    }
+                }
+            }
+        }
+
+        if ranges.is_empty() {
+            self.finish(rga, &message.version);
+        }
+        return Some(ReconcileMessage {
+            ranges,
+            version: self.version.clone(),
+        });
+    }
+}
+
+impl Reconciliation {
+    /// Ends the reconciliation once every range matches, so this replica holds
+    /// everything the other replica held when it started.
+    ///
+    /// Operations buffered for missing dependencies are only released now. Their
+    /// effects may not have reached the other replica, which learns of them through
+    /// the usual delivery of operations or the next reconciliation.
+    fn finish(&mut self, rga: &mut RGA, version: &VersionVector) {
+        rga.merge_version(version);
+        rga.apply_buffered_operations();
+        self.done = true;
+    }
+}
+
+/// Iterates over the nodes from `lower` up to, but excluding, `upper`.
+fn in_range(
+    nodes: &NodeIndex,
+    lower: Option<S4Vector>,
+    upper: Option<S4Vector>,
+) -> impl Iterator<Item = &(NodeState, u64)> + '_ {
+    let start: Bound<S4Vector> = match lower {
+        Some(lower) => Bound::Included(lower),
+        None => Bound::Unbounded,
+    };
+    let end: Bound<S4Vector> = match (lower, upper) {
+        // A range received from another replica may be reversed, which is empty
+        (Some(lower), Some(upper)) if upper < lower => Bound::Excluded(lower),
+        (_, Some(upper)) => Bound::Excluded(upper),
+        (_, None) => Bound::Unbounded,
+    };
+    return nodes.range((start, end)).map(|(_, node)| node);
+}
+
+fn fingerprint(nodes: &NodeIndex, lower: Option<S4Vector>, upper: Option<S4Vector>) -> u64 {
+    return in_range(nodes, lower, upper).fold(0, |sum, (_, digest)| sum.wrapping_add(*digest));
+}
+
+/// Summarises a range whose fingerprints differ, either by its nodes or by the
+/// fingerprints of subranges holding equal numbers of nodes.
+fn split(nodes: &NodeIndex, lower: Option<S4Vector>, upper: Option<S4Vector>) -> Vec<RangeSummary> {
+    let own: Vec<&(NodeState, u64)> = in_range(nodes, lower, upper).collect();
+    if own.len() <= NODE_THRESHOLD {
+        return vec![RangeSummary {
+            lower,
+            upper,
+            summary: Summary::Nodes {
+                nodes: own.into_iter().map(|(node, _)| node.clone()).collect(),
+                reply: true,
+            },
+        }];
+    }
+
+    let size: usize = own.len().div_ceil(SPLIT);
+    let chunks: Vec<&[&(NodeState, u64)]> = own.chunks(size).collect();
+    let mut ranges: Vec<RangeSummary> = Vec::new();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let sum: u64 = chunk
+            .iter()
+            .fold(0, |sum: u64, (_, digest)| sum.wrapping_add(*digest));
+        ranges.push(RangeSummary {
+            lower: match i {
+                0 => lower,
+                _ => Some(chunk[0].0.s4vector),
+            },
+            upper: match chunks.get(i + 1) {
+                Some(next) => Some(next[0].0.s4vector),
+                None => upper,
+            },
+            summary: Summary::Fingerprint(sum),
+        });
+    }
+    return ranges;
+}
+
+impl RGA {
+    /// Returns the state of every element and position in order of `S4Vector`.
+    pub fn node_states(&self) -> Vec<NodeState> {
+        return self.nodes().flat_map(NodeState::of).collect();
+    }
+
+    fn node_index(&self) -> NodeIndex {
+        return self
+            .nodes()
+            .flat_map(NodeState::of)
+            .map(|node| {
+                let digest: u64 = node.digest();
+                (node.s4vector, (node, digest))
+            })
+            .collect();
+    }
+
+    /// Merges node states received from another replica.
+    ///
+    /// Missing elements and positions are inserted once their origins are known,
+    /// deletes are applied, and values are updated unless a later update has been
+    /// applied. Nodes whose origins are never received are ignored. Merged nodes are
+    /// not operations, so they are not added to the log.
+    pub fn merge_node_states(&mut self, nodes: Vec<NodeState>) {
+        self.integrate_node_states(nodes);
+        self.apply_buffered_operations();
+    }
+
+    /// Merges node states without releasing buffered operations, which would change
+    /// ranges a reconciliation has already compared.
+    fn integrate_node_states(&mut self, nodes: Vec<NodeState>) {
+        let mut pending: Vec<NodeState> = nodes;
+        loop {
+            let before: usize = pending.len();
+            let mut waiting: Vec<NodeState> = Vec::new();
+
+            for node in pending {
+                let known = |s4: &Option<S4Vector>| s4.is_none_or(|s4| self.contains(&s4));
+                let ready: bool = known(&node.left) && known(&node.right) && known(&node.target);
+                if ready {
+                    self.merge_node_state(node);
+                } else {
+                    waiting.push(node);
+                }
+            }
+
+            pending = waiting;
+            if pending.is_empty() || pending.len() == before {
+                break;
+            }
+        }
+        self.flush_changes();
+    }
+
+    fn merge_node_state(&mut self, node: NodeState) {
+        let operation = |operation: OperationType, value: Option<String>, id: S4Vector| Operation {
+            operation,
+            s4vector: node.target.unwrap_or(node.s4vector),
+            value,
+            left: node.left,
+            right: node.right,
+            id,
+        };
+
+        if !self.contains(&node.s4vector) {
+            match node.target {
+                Some(_) => {
+                    self.integrate_unlogged(&operation(OperationType::Move, None, node.s4vector))
+                }
+                None => {
+                    let value: Option<String> = Some(node.value.clone());
+                    self.integrate_unlogged(&operation(
+                        OperationType::Insert,
+                        value,
+                        node.s4vector,
+                    ));
+                }
+            }
+        }
+        if node.target.is_some() {
+            return;
+        }
+
+        if let Some(updated) = node.updated {
+            match self.isolate(&node.s4vector) {
+                // Updates are not applied to deleted elements, but the value must
+                // still converge for the states to match
+                Some(element) if element.tombstone => {
+                    if updated > element.updated.unwrap_or(element.s4vector) {
+                        element.value = node.value.clone();
+                        element.updated = Some(updated);
+                    }
+                }
+                _ => {
+                    let value: Option<String> = Some(node.value.clone());
+                    self.integrate_unlogged(&operation(OperationType::Update, value, updated));
+                }
+            }
+        }
+        if node.tombstone {
+            // The element's own id has already been observed
+            self.integrate_unlogged(&operation(OperationType::Delete, None, node.s4vector));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::Rng;
+    use crate::rga::rga::BroadcastOperation;
+    use crate::test_support::{deliver, type_text};
+
+    /// Runs a reconciliation to the end.
+    ///
+    /// # Returns
+    /// The number of messages exchanged and the number of nodes transferred.
+    fn reconcile(rga1: &mut RGA, rga2: &mut RGA) -> (usize, usize) {
+        let (mut initiator, mut message) = Reconciliation::start(rga1);
+        let mut responder = Reconciliation::accept(rga2);
+        let (mut messages, mut transferred): (usize, usize) = (1, 0);
+
+        let count = |message: &ReconcileMessage| -> usize {
+            return message
+                .ranges
+                .iter()
+                .map(|range| match &range.summary {
+                    Summary::Nodes { nodes, .. } => nodes.len(),
+                    Summary::Fingerprint(_) => 0,
+                })
+                .sum();
+        };
+
+        loop {
+            message = match responder.handle(rga2, message) {
+                Some(reply) => reply,
+                None => break,
+            };
+            messages += 1;
+            transferred += count(&message);
+            message = match initiator.handle(rga1, message) {
+                Some(reply) => reply,
+                None => break,
+            };
+            messages += 1;
+            transferred += count(&message);
+        }
+        assert!(initiator.is_done() && responder.is_done());
+        return (messages, transferred);
+    }
+
+    #[test]
+    fn test_node_states_ignore_runs() {
+        let mut rga1 = RGA::new(1, 1);
+        let mut rga2 = RGA::new(1, 2);
+        let mut rga3 = RGA::new(1, 3);
+        let operations = type_text(&mut rga1, None, "hello");
+
+        // One replica keeps a single run, the other receives it out of order
+        deliver(&mut rga2, &operations);
+        for op in operations.iter().rev() {
+            rga3.remote_apply(op.clone());
+        }
+
+        assert_eq!(rga1.node_states().len(), 5);
+        assert_eq!(rga1.node_states(), rga2.node_states());
+        assert_eq!(rga1.node_states(), rga3.node_states());
+    }
+
+    #[test]
+    fn test_reconcile_without_operations() {
+        let mut rga1 = RGA::new(1, 1);
+        let mut rga2 = RGA::new(1, 2);
+        let operations = type_text(&mut rga1, None, "abcdef");
+        deliver(&mut rga2, &operations[..3]);
+
+        // Edits on both sides, some of which the other side never receives
+        rga1.local_delete(operations[1].s4vector).unwrap();
+        rga1.local_move_at(3, 0).unwrap();
+        rga2.local_update(operations[2].s4vector, "C".to_string())
+            .unwrap();
+        rga2.local_insert("x".to_string(), Some(operations[0].s4vector), None)
+            .unwrap();
+
+        reconcile(&mut rga1, &mut rga2);
+        assert_eq!(rga1.read(), rga2.read());
+        assert_eq!(rga1.read(), vec!["e", "a", "x", "C", "d", "f"]);
+        assert_eq!(rga1.version(), rga2.version());
+
+        // Operations keep flowing once the versions have been merged
+        let op = rga2.local_delete_range_at(0..2).unwrap();
+        rga1.remote_apply(op);
+        assert_eq!(rga1.read(), rga2.read());
+        assert_eq!(reconcile(&mut rga1, &mut rga2), (2, 0));
+    }
+
+    #[test]
+    fn test_few_differences_in_large_document() {
+        let mut rga1 = RGA::new(1, 1);
+        let mut rga2 = RGA::new(1, 2);
+        let text: String = "lorem ipsum ".repeat(500);
+        let operations = type_text(&mut rga1, None, &text);
+        deliver(&mut rga2, &operations);
+
+        for i in [10, 2000, 4000] {
+            rga1.local_delete(operations[i].s4vector).unwrap();
+        }
+        rga2.local_update(operations[3000].s4vector, "!".to_string())
+            .unwrap();
+
+        let (messages, transferred) = reconcile(&mut rga1, &mut rga2);
+        assert_eq!(rga1.read(), rga2.read());
+        assert_eq!(rga1.version(), rga2.version());
+
+        // log16(6000) rounds of splitting, then the nodes and the final message
+        assert!(messages <= 8, "{messages} messages");
+        assert!(transferred <= 4 * 2 * NODE_THRESHOLD, "{transferred} nodes");
+    }
+
+    #[test]
+    fn test_reversed_range_is_empty() {
+        let mut rga = RGA::new(1, 1);
+        let operations = type_text(&mut rga, None, "abc");
+        let mut reconciliation = Reconciliation::accept(&rga);
+
+        let message: ReconcileMessage = ReconcileMessage {
+            ranges: vec![RangeSummary {
+                lower: Some(operations[2].s4vector),
+                upper: Some(operations[0].s4vector),
+                summary: Summary::Fingerprint(0),
+            }],
+            version: VersionVector::new(),
+        };
+        let reply: ReconcileMessage = reconciliation.handle(&mut rga, message).unwrap();
+        assert!(reply.ranges.is_empty());
+        assert!(reconciliation.is_done());
+    }
+
+    #[test]
+    fn test_random_replicas_converge() {
+        for seed in 0..100 {
+            let mut rng: Rng = Rng::new(seed);
+            let mut replicas: Vec<RGA> = (1..=3).map(|site_id| RGA::new(1, site_id)).collect();
+            let mut reference: RGA = RGA::new(1, 9);
+
+            for _ in 0..40 {
+                let site: usize = rng.range(0, 2) as usize;
+                let rga: &mut RGA = &mut replicas[site];
+                let ids: Vec<S4Vector> = rga.iter_with_ids().map(|(id, _)| id).collect();
+                let len: u64 = ids.len() as u64;
+                let result = match rng.range(0, 4) {
+                    3 if len > 0 => rga.local_delete(ids[rng.range(0, len - 1) as usize]),
+                    4 if len > 1 => {
+                        let from: usize = rng.range(0, len - 1) as usize;
+                        rga.local_move_at(from, rng.range(0, len - 1) as usize)
+                    }
+                    _ => {
+                        let left: Option<S4Vector> = match rng.range(0, len) {
+                            0 => None,
+                            i => Some(ids[i as usize - 1]),
+                        };
+                        rga.local_insert(rng.range(0, 9).to_string(), left, None)
+                    }
+                };
+
+                // Every operation reaches a random subset of the other replicas
+                let op: BroadcastOperation = result.unwrap();
+                reference.remote_apply(op.clone());
+                for (i, other) in replicas.iter_mut().enumerate() {
+                    if i != site && rng.chance(0.5) {
+                        other.remote_apply(op.clone());
+                    }
+                }
+            }
+
+            // Released buffered operations may need another pass
+            for _ in 0..2 {
+                for (i, j) in [(0, 1), (1, 2), (0, 2)] {
+                    let (left, right) = replicas.split_at_mut(j);
+                    reconcile(&mut left[i], &mut right[0]);
+                }
+            }
+            for rga in &replicas {
+                assert_eq!(rga.node_states(), reference.node_states(), "seed {seed}");
+                assert_eq!(rga.read(), reference.read(), "seed {seed}");
+            }
+        }
+    }
+}
@@ -0,0 +1,463 @@
+use crate::rga::rga::{BroadcastOperation, OperationError, RGA};
+use crate::{S4Vector, VersionVector};
+use tokio::sync::{broadcast, mpsc, oneshot, watch};
+
+/// A local edit sent to the task owning the `RGA`.
+enum Edit {
+    Insert {
+        value: String,
+        left: Option<S4Vector>,
+        right: Option<S4Vector>,
+    },
+    Delete(S4Vector),
+    Update(S4Vector, String),
+}
+
+/// A request sent to the task owning the `RGA`.
+enum Request {
+    Edit(
+        Edit,
+        oneshot::Sender<Result<BroadcastOperation, OperationError>>,
+    ),
+    Read(oneshot::Sender<Vec<String>>),
+    Version(oneshot::Sender<VersionVector>),
+    Missing(VersionVector, oneshot::Sender<Vec<BroadcastOperation>>),
+}
+
+/// `AsyncReplica` is a handle to an `RGA` owned by a tokio task.
+///
+/// Local edits and reads are sent to the task over an `mpsc` channel and answered
+/// once applied. Operations received from other replicas are sent over a second
+/// `mpsc` channel, and every local edit is published on a `broadcast` channel for
+/// the other replicas. The version of the RGA is published on a `watch` channel
+/// after every change, and the OpLog of the RGA is enabled so a replica that fell
+/// behind can be sent what it is missing. The task stops once every handle has been
+/// dropped.
+///
+/// # Example
+/// ```
+/// use crdt::rga::rga::RGA;
+/// use crdt::AsyncReplica;
+///
+/// # tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap().block_on(async {
+/// let replica = AsyncReplica::spawn(RGA::new(1, 1));
+/// let peer = AsyncReplica::spawn(RGA::new(1, 2));
+/// replica.forward_to(&peer);
+///
+/// let op = replica.insert("a".to_string(), None, None).await.unwrap();
+/// assert_eq!(replica.read().await, vec!["a".to_string()]);
+///
+/// peer.wait_for(&op).await;
+/// assert_eq!(peer.read().await, vec!["a".to_string()]);
+/// # });
+/// ```
+#[derive(Debug, Clone)]
+pub struct AsyncReplica {
+    requests: mpsc::Sender<Request>,
+    remote: mpsc::Sender<BroadcastOperation>,
+    outgoing: broadcast::Sender<BroadcastOperation>,
+    version: watch::Receiver<VersionVector>,
+}
+
+impl AsyncReplica {
+    /// The number of requests or remote operations that can be queued before senders wait.
+    const CHANNEL_CAPACITY: usize = 1024;
+
+    /// Spawns a task owning `rga` on the current tokio runtime.
+    ///
+    /// # Panics
+    /// If called outside of a tokio runtime.
+    pub fn spawn(rga: RGA) -> Self {
+        return Self::spawn_with_outgoing_capacity(rga, Self::CHANNEL_CAPACITY);
+    }
+
+    /// Spawns a task owning `rga`, keeping at most `capacity` local edits for
+    /// subscribers that fall behind.
+    fn spawn_with_outgoing_capacity(mut rga: RGA, capacity: usize) -> Self {
+        rga.enable_log();
+        let (requests, request_receiver) = mpsc::channel(Self::CHANNEL_CAPACITY);
+        let (remote, remote_receiver) = mpsc::channel(Self::CHANNEL_CAPACITY);
+        let (outgoing, _) = broadcast::channel(capacity);
+        let (version_sender, version) = watch::channel(rga.version().clone());
+
+        tokio::spawn(Self::run(
+            rga,
+            request_receiver,
+            remote_receiver,
+            outgoing.clone(),
+            version_sender,
+        ));
+
+        return AsyncReplica {
+            requests,
+            remote,
+            outgoing,
+            version,
+        };
+    }
+
+    /// Owns the `RGA`, applying requests and remote operations as they arrive.
+    async fn run(
+        mut rga: RGA,
+        mut requests: mpsc::Receiver<Request>,
+        mut remote: mpsc::Receiver<BroadcastOperation>,
+        outgoing: broadcast::Sender<BroadcastOperation>,
+        version: watch::Sender<VersionVector>,
+    ) {
+        loop {
+            tokio::select! {
+                request = requests.recv() => match request {
+                    Some(request) => Self::handle(&mut rga, request, &outgoing),
+                    None => return,
+                },
+                Some(operation) = remote.recv() => rga.remote_apply(operation),
+            }
+            Self::publish(&rga, &version);
+        }
+    }
+
+    /// Publishes the version of `rga` if it changed since it was last published.
+    fn publish(rga: &RGA, version: &watch::Sender<VersionVector>) {
+        version.send_if_modified(|published| {
+            if published == rga.version() {
+                return false;
+            }
+            *published = rga.version().clone();
+            return true;
+        });
+    }
+
+    fn handle(rga: &mut RGA, request: Request, outgoing: &broadcast::Sender<BroadcastOperation>) {
+        match request {
+            Request::Edit(edit, reply) => {
+                let result: Result<BroadcastOperation, OperationError> = match edit {
+                    Edit::Insert { value, left, right } => rga.local_insert(value, left, right),
+                    Edit::Delete(s4vector) => rga.local_delete(s4vector),
+                    Edit::Update(s4vector, value) => rga.local_update(s4vector, value),
+                };
+
+                if let Ok(operation) = &result {
+                    // No replica may be listening yet
+                    let _ = outgoing.send(operation.clone());
+                }
+                let _ = reply.send(result);
+            }
+            Request::Read(reply) => {
+                let _ = reply.send(rga.read());
+            }
+            Request::Version(reply) => {
+                let _ = reply.send(rga.version().clone());
+            }
+            Request::Missing(version, reply) => {
+                let missing: Vec<BroadcastOperation> = match rga.log() {
+                    Some(log) => log
+                        .iter()
+                        .filter(|op| !version.contains(&op.id))
+                        .cloned()
+                        .collect(),
+                    None => Vec::new(),
+                };
+                let _ = reply.send(missing);
+            }
+        }
+    }
+
+    /// Sends a request to the task and waits for the reply.
+    async fn request<T>(&self, request: impl FnOnce(oneshot::Sender<T>) -> Request) -> T {
+        let (reply, receiver) = oneshot::channel();
+        self.requests
+            .send(request(reply))
+            .await
+            .expect("replica task stopped");
+        return receiver.await.expect("replica task stopped");
+    }
+
+    async fn edit(&self, edit: Edit) -> Result<BroadcastOperation, OperationError> {
+        return self.request(|reply| Request::Edit(edit, reply)).await;
+    }
+
+    /// Inserts a new value, see `RGA::local_insert`.
+    pub async fn insert(
+        &self,
+        value: String,
+        left: Option<S4Vector>,
+        right: Option<S4Vector>,
+    ) -> Result<BroadcastOperation, OperationError> {
+        return self.edit(Edit::Insert { value, left, right }).await;
+    }
+
+    /// Marks a node as logically deleted, see `RGA::local_delete`.
+    pub async fn delete(&self, s4vector: S4Vector) -> Result<BroadcastOperation, OperationError> {
+        return self.edit(Edit::Delete(s4vector)).await;
+    }
+
+    /// Updates the value of a node, see `RGA::local_update`.
+    pub async fn update(
+        &self,
+        s4vector: S4Vector,
+        value: String,
+    ) -> Result<BroadcastOperation, OperationError> {
+        return self.edit(Edit::Update(s4vector, value)).await;
+    }
+
+    /// Reads the current state of the RGA.
+    pub async fn read(&self) -> Vec<String> {
+        return self.request(Request::Read).await;
+    }
+
+    /// Returns the version of the RGA.
+    pub async fn version(&self) -> VersionVector {
+        return self.request(Request::Version).await;
+    }
+
+    /// Queues an operation received from another replica.
+    pub async fn apply(&self, operation: BroadcastOperation) {
+        self.remote
+            .send(operation)
+            .await
+            .expect("replica task stopped");
+    }
+
+    /// Returns a sender for operations received from other replicas.
+    pub fn remote_sender(&self) -> mpsc::Sender<BroadcastOperation> {
+        return self.remote.clone();
+    }
+
+    /// Returns a receiver of every operation produced by a local edit.
+    pub fn subscribe(&self) -> broadcast::Receiver<BroadcastOperation> {
+        return self.outgoing.subscribe();
+    }
+
+    /// Spawns a task forwarding every local edit of this replica to `other`.
+    ///
+    /// If `other` falls more than the channel capacity behind, every operation in the
+    /// OpLog not covered by the published version of `other` is sent again, so no
+    /// edit is lost. Operations `other` already integrated are ignored by its RGA.
+    /// The task stops when either replica stops.
+    pub fn forward_to(&self, other: &AsyncReplica) {
+        let mut operations: broadcast::Receiver<BroadcastOperation> = self.subscribe();
+        let remote: mpsc::Sender<BroadcastOperation> = other.remote_sender();
+        let version: watch::Receiver<VersionVector> = other.version.clone();
+        // A weak sender, so forwarding does not keep this replica's task running
+        let requests: mpsc::WeakSender<Request> = self.requests.downgrade();
+
+        tokio::spawn(async move {
+            loop {
+                match operations.recv().await {
+                    Ok(operation) => {
+                        if remote.send(operation).await.is_err() {
+                            return;
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Lagged(_)) => {
+                        let known: VersionVector = version.borrow().clone();
+                        let missing: Vec<BroadcastOperation> =
+                            match Self::missing(&requests, known).await {
+                                Some(missing) => missing,
+                                None => return,
+                            };
+                        for operation in missing {
+                            if remote.send(operation).await.is_err() {
+                                return;
+                            }
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return,
+                }
+            }
+        });
+    }
+
+    /// Asks the task owning the `RGA` for the logged operations not covered by
+    /// `version`, or returns `None` if the task stopped.
+    async fn missing(
+        requests: &mpsc::WeakSender<Request>,
+        version: VersionVector,
+    ) -> Option<Vec<BroadcastOperation>> {
+        let requests: mpsc::Sender<Request> = requests.upgrade()?;
+        let (reply, receiver) = oneshot::channel();
+        requests.send(Request::Missing(version, reply)).await.ok()?;
+        return receiver.await.ok();
+    }
+
+    /// Waits until `operation` has been integrated.
+    ///
+    /// The published version is watched, so the caller sleeps until the task owning the
+    /// `RGA` integrates the operation.
+    ///
+    /// # Panics
+    /// If the replica task stopped before integrating the operation.
+    pub async fn wait_for(&self, operation: &BroadcastOperation) {
+        let mut version: watch::Receiver<VersionVector> = self.version.clone();
+        version
+            .wait_for(|version| version.contains(&operation.id))
+            .await
+            .expect("replica task stopped");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn connect(replicas: &[AsyncReplica]) {
+        for (i, replica) in replicas.iter().enumerate() {
+            for (j, other) in replicas.iter().enumerate() {
+                if i != j {
+                    replica.forward_to(other);
+                }
+            }
+        }
+    }
+
+    /// Waits until every replica has seen the same operations.
+    async fn settle(replicas: &[AsyncReplica]) {
+        loop {
+            let mut versions: Vec<VersionVector> = Vec::new();
+            for replica in replicas {
+                versions.push(replica.version().await);
+            }
+            if versions.windows(2).all(|pair| pair[0] == pair[1]) {
+                return;
+            }
+            tokio::task::yield_now().await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_replicas_converge() {
+        let replicas: Vec<AsyncReplica> = (1..=3)
+            .map(|site_id| AsyncReplica::spawn(RGA::new(1, site_id)))
+            .collect();
+        connect(&replicas);
+
+        let a = replicas[0]
+            .insert("a".to_string(), None, None)
+            .await
+            .unwrap();
+        for replica in &replicas {
+            replica.wait_for(&a).await;
+        }
+
+        // Every replica types concurrently after "a"
+        let mut handles = Vec::new();
+        for (i, replica) in replicas.iter().enumerate() {
+            let replica: AsyncReplica = replica.clone();
+            handles.push(tokio::spawn(async move {
+                let mut left = Some(a.s4vector);
+                for c in ["x", "y", "z"] {
+                    let value: String = format!("{c}{i}");
+                    let op = replica.insert(value, left, None).await.unwrap();
+                    left = Some(op.s4vector);
+                    tokio::task::yield_now().await;
+                }
+                replica
+                    .update(left.unwrap(), format!("!{i}"))
+                    .await
+                    .unwrap();
+            }));
+        }
+        for handle in handles {
+            handle.await.unwrap();
+        }
+        replicas[2].delete(a.s4vector).await.unwrap();
+
+        settle(&replicas).await;
+        let state: Vec<String> = replicas[0].read().await;
+        assert_eq!(state.len(), 9);
+        for replica in &replicas[1..] {
+            assert_eq!(replica.read().await, state);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_remote_operations_over_channel() {
+        let replica = AsyncReplica::spawn(RGA::new(1, 1));
+        let peer = AsyncReplica::spawn(RGA::new(1, 2));
+        let mut outgoing = replica.subscribe();
+
+        let a = replica.insert("a".to_string(), None, None).await.unwrap();
+        let b = replica
+            .insert("b".to_string(), Some(a.s4vector), None)
+            .await
+            .unwrap();
+
+        // Deliver out of order through the remote channel
+        let sender = peer.remote_sender();
+        let first = outgoing.recv().await.unwrap();
+        let second = outgoing.recv().await.unwrap();
+        sender.send(second).await.unwrap();
+        sender.send(first).await.unwrap();
+
+        peer.wait_for(&b).await;
+        assert_eq!(peer.read().await, vec!["a".to_string(), "b".to_string()]);
+        assert!(matches!(
+            peer.delete(b.s4vector.offset(5)).await,
+            Err(OperationError::DependancyError)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_operation_without_visible_change() {
+        let replica = AsyncReplica::spawn(RGA::new(1, 1));
+        let peer = AsyncReplica::spawn(RGA::new(1, 2));
+
+        let a = replica.insert("a".to_string(), None, None).await.unwrap();
+        peer.apply(a.clone()).await;
+        peer.wait_for(&a).await;
+
+        // Both replicas delete "a", so the remote delete changes only the version
+        let delete = replica.delete(a.s4vector).await.unwrap();
+        peer.delete(a.s4vector).await.unwrap();
+
+        let waiting = tokio::spawn({
+            let peer: AsyncReplica = peer.clone();
+            let delete: BroadcastOperation = delete.clone();
+            async move { peer.wait_for(&delete).await }
+        });
+        tokio::task::yield_now().await;
+        assert!(!waiting.is_finished());
+
+        peer.apply(delete).await;
+        tokio::time::timeout(std::time::Duration::from_secs(5), waiting)
+            .await
+            .expect("wait_for was not woken")
+            .unwrap();
+        assert!(peer.read().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_forward_resends_operations_after_lagging() {
+        let replica = AsyncReplica::spawn_with_outgoing_capacity(RGA::new(1, 1), 2);
+        let peer = AsyncReplica::spawn(RGA::new(1, 2));
+        replica.forward_to(&peer);
+
+        // Queue every edit before the task runs, so the forwarding task falls more
+        // than the channel capacity behind
+        let mut replies = Vec::new();
+        for i in 0..10 {
+            let (reply, receiver) = oneshot::channel();
+            let edit = Edit::Insert {
+                value: i.to_string(),
+                left: None,
+                right: None,
+            };
+            replica
+                .requests
+                .try_send(Request::Edit(edit, reply))
+                .unwrap_or_else(|_| panic!("request queue is full"));
+            replies.push(receiver);
+        }
+        for receiver in replies {
+            receiver.await.unwrap().unwrap();
+        }
+
+        tokio::time::timeout(
+            std::time::Duration::from_secs(5),
+            settle(&[replica.clone(), peer.clone()]),
+        )
+        .await
+        .expect("operations were lost");
+        assert_eq!(peer.read().await, replica.read().await);
+    }
+}
@@ -0,0 +1,428 @@
+use crate::persistence::{DurableReplica, PersistenceError};
+use crate::storage::{FileStorage, MemoryStorage, Storage};
+use crate::OperationBatch;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// The prefix of the directory of every document in a `DirectoryStore`.
+const DIRECTORY_PREFIX: &str = "doc-";
+
+#[derive(Debug, thiserror::Error)]
+pub enum RepoError {
+    #[error("Document {0} does not exist")]
+    NotFound(String),
+    #[error("Document {0} already exists")]
+    AlreadyExists(String),
+    #[error(transparent)]
+    Persistence(#[from] PersistenceError),
+}
+
+/// Provides the `Storage` of every document of a `Repo`.
+pub trait DocumentStore {
+    type Storage: Storage;
+
+    /// Checks whether a document has been stored.
+    fn exists(&self, document: &str) -> Result<bool, PersistenceError>;
+
+    /// Returns the storage of a document, creating it if needed.
+    fn open(&mut self, document: &str) -> Result<Self::Storage, PersistenceError>;
+
+    /// Takes back the storage of a document evicted from memory.
+    fn close(&mut self, document: &str, storage: Self::Storage);
+
+    /// Returns the IDs of the stored documents.
+    fn documents(&self) -> Result<Vec<String>, PersistenceError>;
+}
+
+/// `MemoryStore` keeps the `MemoryStorage` of every document, for tests.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStore {
+    documents: HashMap<String, MemoryStorage>,
+}
+
+impl MemoryStore {
+    /// Creates an empty `MemoryStore`.
+    pub fn new() -> Self {
+        return MemoryStore::default();
+    }
+}
+
+impl DocumentStore for MemoryStore {
+    type Storage = MemoryStorage;
+
+    fn exists(&self, document: &str) -> Result<bool, PersistenceError> {
+        return Ok(self.documents.contains_key(document));
+    }
+
+    fn open(&mut self, document: &str) -> Result<MemoryStorage, PersistenceError> {
+        return Ok(self.documents.remove(document).unwrap_or_default());
+    }
+
+    fn close(&mut self, document: &str, storage: MemoryStorage) {
+        self.documents.insert(document.to_string(), storage);
+    }
+
+    fn documents(&self) -> Result<Vec<String>, PersistenceError> {
+        return Ok(self.documents.keys().cloned().collect());
+    }
+}
+
+/// `DirectoryStore` keeps every document in a `FileStorage` in a subdirectory of its
+/// root, named `doc-` followed by the hexadecimal encoding of the document ID so any
+/// ID is a valid file name.
+#[derive(Debug, Clone)]
+pub struct DirectoryStore {
+    root: PathBuf,
+}
+
+impl DirectoryStore {
+    /// Creates a store keeping documents in `root`.
+    pub fn new(root: impl AsRef<Path>) -> Self {
+        return DirectoryStore {
+            root: root.as_ref().to_path_buf(),
+        };
+    }
+
+    fn path(&self, document: &str) -> PathBuf {
+        let hex: String = document.bytes().map(|byte| format!("{byte:02x}")).collect();
+        return self.root.join(format!("{DIRECTORY_PREFIX}{hex}"));
+    }
+}
+
+/// Decodes a directory name produced by `DirectoryStore::path`.
+fn decode_name(name: &str) -> Option<String> {
+    let hex: &str = name.strip_prefix(DIRECTORY_PREFIX)?;
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    let bytes: Option<Vec<u8>> = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect();
+    return String::from_utf8(bytes?).ok();
+}
+
+impl DocumentStore for DirectoryStore {
+    type Storage = FileStorage;
+
+    fn exists(&self, document: &str) -> Result<bool, PersistenceError> {
+        return Ok(self.path(document).is_dir());
+    }
+
+    fn open(&mut self, document: &str) -> Result<FileStorage, PersistenceError> {
+        return FileStorage::open(self.path(document));
+    }
+
+    fn close(&mut self, _document: &str, _storage: FileStorage) {}
+
+    fn documents(&self) -> Result<Vec<String>, PersistenceError> {
+        let entries: fs::ReadDir = match fs::read_dir(&self.root) {
+            Ok(entries) => entries,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(error) => return Err(error.into()),
+        };
+
+        let mut documents: Vec<String> = Vec::new();
+        for entry in entries {
+            let entry: fs::DirEntry = entry?;
+            if let Some(document) = entry.file_name().to_str().and_then(decode_name) {
+                documents.push(document);
+            }
+        }
+        return Ok(documents);
+    }
+}
+
+/// A document loaded in memory.
+#[derive(Debug)]
+struct Loaded<S: Storage> {
+    replica: DurableReplica<S>,
+    last_used: Instant,
+    /// The value of `Repo::uses` when the document was last used, which orders uses
+    /// even when the clock does not advance between them.
+    use_count: u64,
+}
+
+/// `Repo` manages the replicas of many documents edited by the same site.
+///
+/// Every document is an `RGA` kept in a `DurableReplica`, with the site ID and
+/// session ID of the repo. Documents are loaded from the `DocumentStore` when first
+/// used and can be evicted from memory once idle, or when more than `capacity`
+/// documents are loaded; since every batch is persisted when it is applied, eviction
+/// never loses edits.
+///
+/// # Example
+/// ```
+/// use crdt::{MemoryStore, Repo};
+///
+/// let mut repo = Repo::new(MemoryStore::new(), 1, 1);
+/// let notes = repo.create("notes").unwrap();
+/// notes.insert("a".to_string(), None, None).unwrap();
+///
+/// repo.close("notes");
+/// assert_eq!(repo.open("notes").unwrap().rga().read(), vec!["a".to_string()]);
+/// ```
+#[derive(Debug)]
+pub struct Repo<D: DocumentStore> {
+    store: D,
+    session_id: u64,
+    site_id: u64,
+    loaded: HashMap<String, Loaded<D::Storage>>,
+    capacity: Option<usize>,
+    /// The number of times a document has been used.
+    uses: u64,
+}
+
+impl<D: DocumentStore> Repo<D> {
+    /// Creates a repo.
+    ///
+    /// # Parameters
+    /// - `store`: Where the documents are kept.
+    /// - `session_id`: The session ID of every document. Documents saved in an
+    ///   earlier session continue in this one, so it must be greater.
+    /// - `site_id`: The site ID of every document.
+    pub fn new(store: D, session_id: u64, site_id: u64) -> Self {
+        return Repo {
+            store,
+            session_id,
+            site_id,
+            loaded: HashMap::new(),
+            capacity: None,
+            uses: 0,
+        };
+    }
+
+    /// Returns the site ID shared by every document.
+    pub fn site_id(&self) -> u64 {
+        return self.site_id;
+    }
+
+    /// Returns the session ID shared by every document.
+    pub fn session_id(&self) -> u64 {
+        return self.session_id;
+    }
+
+    /// Limits the number of documents loaded at once, evicting the least recently
+    /// used documents when more are loaded.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = Some(capacity.max(1));
+        self.evict_over_capacity(None);
+    }
+
+    /// Creates a document.
+    ///
+    /// # Returns
+    /// The replica of the document, or `RepoError::AlreadyExists`.
+    pub fn create(&mut self, document: &str) -> Result<&mut DurableReplica<D::Storage>, RepoError> {
+        if self.loaded.contains_key(document) || self.store.exists(document)? {
+            return Err(RepoError::AlreadyExists(document.to_string()));
+        }
+        return self.load(document);
+    }
+
+    /// Returns the replica of a document, loading it if needed.
+    ///
+    /// # Returns
+    /// The replica, or `RepoError::NotFound` if the document has never been created.
+    pub fn open(&mut self, document: &str) -> Result<&mut DurableReplica<D::Storage>, RepoError> {
+        if !self.loaded.contains_key(document) && !self.store.exists(document)? {
+            return Err(RepoError::NotFound(document.to_string()));
+        }
+        return self.load(document);
+    }
+
+    /// Evicts a document from memory.
+    ///
+    /// # Returns
+    /// `false` if the document was not loaded.
+    pub fn close(&mut self, document: &str) -> bool {
+        return match self.loaded.remove(document) {
+            Some(loaded) => {
+                self.store.close(document, loaded.replica.into_storage());
+                true
+            }
+            None => false,
+        };
+    }
+
+    /// Evicts every document that has not been used for `idle`.
+    ///
+    /// # Returns
+    /// The IDs of the evicted documents.
+    pub fn evict_idle(&mut self, idle: Duration) -> Vec<String> {
+        let now: Instant = Instant::now();
+        let idle: Vec<String> = self
+            .loaded
+            .iter()
+            .filter(|(_, loaded)| now.duration_since(loaded.last_used) >= idle)
+            .map(|(document, _)| document.clone())
+            .collect();
+        for document in &idle {
+            self.close(document);
+        }
+        return idle;
+    }
+
+    /// Checks whether a document is loaded in memory.
+    pub fn is_loaded(&self, document: &str) -> bool {
+        return self.loaded.contains_key(document);
+    }
+
+    /// Returns the IDs of every document, loaded or not.
+    pub fn documents(&self) -> Result<Vec<String>, RepoError> {
+        let mut documents: Vec<String> = self.store.documents()?;
+        documents.extend(self.loaded.keys().cloned());
+        documents.sort();
+        documents.dedup();
+        return Ok(documents);
+    }
+
+    /// Persists the batch of a local edit made on the replica of `document` through
+    /// `rga_mut`, see `DurableReplica::record`.
+    pub fn record(&mut self, document: &str, batch: &OperationBatch) -> Result<(), RepoError> {
+        self.open(document)?.record(batch)?;
+        return Ok(());
+    }
+
+    /// Applies a batch received from another replica to `document`, loading the
+    /// document if needed.
+    pub fn apply(&mut self, document: &str, batch: OperationBatch) -> Result<(), RepoError> {
+        self.open(document)?.apply(batch)?;
+        return Ok(());
+    }
+
+    /// Loads a document if needed and marks it as used.
+    fn load(&mut self, document: &str) -> Result<&mut DurableReplica<D::Storage>, RepoError> {
+        if !self.loaded.contains_key(document) {
+            let storage: D::Storage = self.store.open(document)?;
+            let mut replica: DurableReplica<D::Storage> =
+                DurableReplica::with_storage(storage, self.session_id, self.site_id)?;
+            replica
+                .rga_mut()
+                .set_identity(self.session_id, self.site_id);
+            self.loaded.insert(
+                document.to_string(),
+                Loaded {
+                    replica,
+                    last_used: Instant::now(),
+                    use_count: 0,
+                },
+            );
+            self.evict_over_capacity(Some(document));
+        }
+
+        self.uses += 1;
+        let loaded: &mut Loaded<D::Storage> = self
+            .loaded
+            .get_mut(document)
+            .ok_or_else(|| RepoError::NotFound(document.to_string()))?;
+        loaded.last_used = Instant::now();
+        loaded.use_count = self.uses;
+        return Ok(&mut loaded.replica);
+    }
+
+    /// Evicts the least recently used documents until at most `capacity` are loaded,
+    /// never evicting `keep`.
+    fn evict_over_capacity(&mut self, keep: Option<&str>) {
+        while self
+            .capacity
+            .is_some_and(|capacity| self.loaded.len() > capacity)
+        {
+            let least_recently_used: Option<String> = self
+                .loaded
+                .iter()
+                .filter(|(document, _)| Some(document.as_str()) != keep)
+                .min_by_key(|(_, loaded)| loaded.use_count)
+                .map(|(document, _)| document.clone());
+            match least_recently_used {
+                Some(document) => self.close(&document),
+                None => break,
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rga::rga::RGA;
+    use crate::storage::test_directory;
+    use crate::test_support::append;
+
+    #[test]
+    fn test_create_open_close() {
+        let mut repo = Repo::new(MemoryStore::new(), 1, 7);
+        append(repo.create("a").unwrap(), "x");
+        append(repo.create("b").unwrap(), "y");
+        assert!(matches!(repo.create("a"), Err(RepoError::AlreadyExists(_))));
+        assert!(matches!(repo.open("c"), Err(RepoError::NotFound(_))));
+
+        assert!(repo.close("a"));
+        assert!(!repo.is_loaded("a"));
+        assert!(matches!(repo.create("a"), Err(RepoError::AlreadyExists(_))));
+        let a = repo.open("a").unwrap();
+        assert_eq!(a.rga().read(), vec!["x"]);
+        assert_eq!(a.rga().site_id(), 7);
+        assert_eq!(repo.documents().unwrap(), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn test_apply_routes_to_document() {
+        let mut repo = Repo::new(MemoryStore::new(), 1, 1);
+        repo.create("a").unwrap();
+        repo.create("b").unwrap();
+        repo.evict_idle(Duration::ZERO);
+        assert!(!repo.is_loaded("a") && !repo.is_loaded("b"));
+
+        let mut peer = RGA::new(1, 2);
+        let op = peer.local_insert("p".to_string(), None, None).unwrap();
+        repo.apply("b", op.into()).unwrap();
+        assert!(repo.is_loaded("b") && !repo.is_loaded("a"));
+        assert_eq!(repo.open("b").unwrap().rga().read(), vec!["p"]);
+        assert!(repo.open("a").unwrap().rga().read().is_empty());
+
+        let op = peer.local_insert("q".to_string(), None, None).unwrap();
+        assert!(matches!(
+            repo.apply("c", op.into()),
+            Err(RepoError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_capacity_evicts_least_recently_used() {
+        let mut repo = Repo::new(MemoryStore::new(), 1, 1);
+        repo.set_capacity(2);
+        for document in ["a", "b", "c"] {
+            append(repo.create(document).unwrap(), document);
+        }
+        assert!(!repo.is_loaded("a"));
+        assert!(repo.is_loaded("b") && repo.is_loaded("c"));
+
+        // Using `b` makes `c` the least recently used
+        repo.open("b").unwrap();
+        assert_eq!(repo.open("a").unwrap().rga().read(), vec!["a"]);
+        assert!(repo.is_loaded("b") && !repo.is_loaded("c"));
+    }
+
+    #[test]
+    fn test_documents_survive_new_session() {
+        let path: PathBuf = test_directory("repo");
+        let mut repo = Repo::new(DirectoryStore::new(&path), 1, 1);
+        let first = append(repo.create("notes/2024").unwrap(), "a");
+        append(repo.create("").unwrap(), "b");
+        drop(repo);
+
+        let mut repo = Repo::new(DirectoryStore::new(&path), 2, 1);
+        assert_eq!(repo.documents().unwrap(), vec!["", "notes/2024"]);
+        let notes = repo.open("notes/2024").unwrap();
+        assert_eq!(notes.rga().read(), vec!["a"]);
+
+        // New operations belong to the new session and continue the site's sequence
+        let second = append(notes, "c");
+        assert_eq!(second.id.ssn, 2);
+        assert!(second.id.seq > first.id.seq);
+        fs::remove_dir_all(&path).unwrap();
+    }
+}
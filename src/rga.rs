@@ -1,3 +1,4 @@
+#[allow(clippy::module_inception)]
 pub mod rga {
     /// The `RGA` module implements a Replicated Growable Array (RGA),
     /// a Conflict-free Replicated Data Type (CRDT) designed for distributed systems.
@@ -12,6 +13,8 @@ pub mod rga {
     ///   without the need for centralized coordination.
     /// - **Efficient Buffering**: Handles out-of-order operations with a buffering
     ///   mechanism that resolves dependencies dynamically.
+    /// - **Run-Length Encoding**: Consecutive single character inserts from the same
+    ///   site are stored as a single run node, which is split when edited inside.
     ///
     /// # Example Usage
     /// ```rust
@@ -33,13 +36,17 @@ pub mod rga {
     /// let result = rga.read();
     /// assert_eq!(result, vec!["B".to_string()]);
     /// ```
-    use crate::S4Vector;
-    use std::cell::RefCell;
-    use std::collections::{HashMap, VecDeque};
-    use std::rc::Rc;
-    #[allow(dead_code)]
+    use crate::observer::Observers;
+    use crate::{Change, OpLog, S4Vector, VersionVector};
+    use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+    use std::ops::Range;
 
-    /// Represents a node in the RGA, containing the actual data and metadata for traversal and consistency.    
+    /// Represents a node in the RGA, containing the actual data and metadata for traversal and consistency.
+    ///
+    /// A node holds a run of `len` elements with consecutive sequence numbers, the
+    /// element at offset `i` is identified by `s4vector.offset(i)`. When `len` is
+    /// greater than one every element is a single character of `value`.
+    #[allow(dead_code)]
     #[derive(Debug, Clone)]
     pub struct Node {
         /// The value of the node.
@@ -52,24 +59,63 @@ pub mod rga {
         pub left: Option<S4Vector>,
         /// The `S4Vector` of the right neighbor
         pub right: Option<S4Vector>,
+        /// The `S4Vector` of the previous node in the list
+        pub previous: Option<S4Vector>,
+        /// The `S4Vector` of the right neighbor at the time the node was inserted
+        pub origin_right: Option<S4Vector>,
+        /// The number of elements stored in the node.
+        pub len: usize,
+        /// The `S4Vector` of the update that set the current value, if any.
+        pub updated: Option<S4Vector>,
+        /// For a node marking a position an element was moved to, the `S4Vector` of
+        /// the moved element.
+        pub target: Option<S4Vector>,
+        /// The `S4Vector`s of the positions the element has been moved to, the
+        /// greatest of which is its current position.
+        pub moves: BTreeSet<S4Vector>,
+        /// The `S4Vector`s of the operations that deleted the element.
+        pub deletes: BTreeSet<S4Vector>,
+        /// Every value the element has had, keyed by the `S4Vector` of the update that
+        /// set it, or of the element for its inserted value. Empty until the first
+        /// update.
+        pub values: BTreeMap<S4Vector, String>,
     }
 
     /// Enum representing different types of operations that can be applied to the RGA.
-    #[derive(Debug, Clone)]
+    #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
     pub enum OperationType {
         Insert,
+        /// Inserts one element per character of the value, with consecutive sequence
+        /// numbers starting at the operation's `S4Vector`.
+        InsertRun,
         Update,
         Delete,
+        /// Deletes every element from the operation's `S4Vector` to `to` that is
+        /// covered by `version`, the version of the replica that deleted the range.
+        DeleteRange {
+            to: S4Vector,
+            version: VersionVector,
+        },
+        /// Updates every element from the operation's `S4Vector` to `to` that is
+        /// covered by `version` to the operation's value.
+        UpdateRange {
+            to: S4Vector,
+            version: VersionVector,
+        },
+        /// Moves the element identified by the operation's `S4Vector` to a new position
+        /// between `left` and `right`, identified by the operation's `id`.
+        Move,
     }
 
     /// Represents an operation in the RGA.
     #[derive(Debug, Clone)]
-    struct Operation {
-        operation: OperationType,
-        s4vector: S4Vector,
-        value: Option<String>, //Optional for deletes
-        left: Option<S4Vector>,
-        right: Option<S4Vector>,
+    pub(crate) struct Operation {
+        pub(crate) operation: OperationType,
+        pub(crate) s4vector: S4Vector,
+        pub(crate) value: Option<String>, //Optional for deletes
+        pub(crate) left: Option<S4Vector>,
+        pub(crate) right: Option<S4Vector>,
+        pub(crate) id: S4Vector,
     }
 
     /// Represents the RGA structure, which is a distributed data structure
@@ -78,30 +124,94 @@ pub mod rga {
     pub struct RGA {
         /// The head of the linked list.
         head: Option<S4Vector>,
-        /// Maps `S4Vector` identifiers to `Node` instances.
-        hash_map: HashMap<S4Vector, Rc<RefCell<Node>>>,
-        /// A Buffer for out-of-order operations.
-        buffer: VecDeque<Operation>,
+        /// The last node of the linked list.
+        tail: Option<S4Vector>,
+        /// Maps the `S4Vector` of the first element of each node to the node.
+        nodes: BTreeMap<S4Vector, Node>,
+        /// A Buffer for out-of-order operations, operations received together are
+        /// kept together so they are released atomically.
+        buffer: VecDeque<Vec<Operation>>,
         /// The current session ID.
         session_id: u64,
         /// The site ID for the current replica.
         site_id: u64,
         /// The local logical clock.
         local_sequence: u64,
+        /// The operations integrated so far, contiguous for each site.
+        version: VersionVector,
+        /// Sequence numbers integrated ahead of `version`, for each site.
+        out_of_order: HashMap<u64, BTreeSet<u64>>,
+        /// The subscribers notified of changes to the visible sequence.
+        observers: Observers,
+        /// The log of integrated operations, if enabled.
+        log: Option<OpLog>,
     }
 
     #[derive(Debug, thiserror::Error)]
     pub enum OperationError {
         #[error("Failed to perform operation, dependancies have not been met")]
         DependancyError,
+        #[error("Failed to perform operation, index out of bounds")]
+        IndexError,
+        #[error("Failed to perform operation, position splits a character")]
+        BoundaryError,
+        /// A value that must be a single character, such as an element of a `Text`,
+        /// is not.
+        #[error("Failed to perform operation, value is not a single character")]
+        CharacterError,
+        #[error("Failed to perform operation, left and right are not neighbours")]
+        NeighbourError,
     }
 
+    /// An operation that has been applied locally and must be sent to the other replicas.
+    #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
     pub struct BroadcastOperation {
         pub operation: OperationType,
         pub s4vector: S4Vector,
         pub value: Option<String>,
         pub left: Option<S4Vector>,
         pub right: Option<S4Vector>,
+        /// The `S4Vector` identifying the operation itself, equal to `s4vector` for inserts.
+        pub id: S4Vector,
+    }
+
+    impl From<BroadcastOperation> for Operation {
+        fn from(op: BroadcastOperation) -> Self {
+            return Operation {
+                operation: op.operation,
+                s4vector: op.s4vector,
+                value: op.value,
+                left: op.left,
+                right: op.right,
+                id: op.id,
+            };
+        }
+    }
+
+    impl From<Operation> for BroadcastOperation {
+        fn from(op: Operation) -> Self {
+            return BroadcastOperation {
+                operation: op.operation,
+                s4vector: op.s4vector,
+                value: op.value,
+                left: op.left,
+                right: op.right,
+                id: op.id,
+            };
+        }
+    }
+
+    impl Operation {
+        /// Returns the number of elements the operation inserts.
+        fn run_len(&self) -> usize {
+            return match self.operation {
+                OperationType::Insert => 1,
+                OperationType::InsertRun => {
+                    self.value.as_ref().map_or(0, |value| value.chars().count())
+                }
+                _ => 0,
+            };
+        }
     }
 
     impl Node {
@@ -127,8 +237,31 @@ pub mod rga {
                 tombstone: false,
                 left,
                 right,
+                previous: None,
+                origin_right: right,
+                len: 1,
+                updated: None,
+                target: None,
+                moves: BTreeSet::new(),
+                deletes: BTreeSet::new(),
+                values: BTreeMap::new(),
             };
         }
+
+        /// Returns the `S4Vector` of the last element in the node.
+        pub fn last(&self) -> S4Vector {
+            return self.s4vector.offset(self.len as u64 - 1);
+        }
+
+        /// Checks whether every element of the node is a single character, so more
+        /// characters can be appended to the run.
+        fn is_char_run(&self) -> bool {
+            return !self.tombstone
+                && self.updated.is_none()
+                && self.target.is_none()
+                && self.moves.is_empty()
+                && self.value.chars().count() == self.len;
+        }
     }
 
     impl std::hash::Hash for Node {
@@ -138,6 +271,12 @@ pub mod rga {
             self.tombstone.hash(state);
             self.left.hash(state);
             self.right.hash(state);
+            self.previous.hash(state);
+            self.origin_right.hash(state);
+            self.len.hash(state);
+            self.updated.hash(state);
+            self.target.hash(state);
+            self.moves.hash(state);
         }
     }
 
@@ -147,7 +286,13 @@ pub mod rga {
                 && self.s4vector == other.s4vector
                 && self.tombstone == other.tombstone
                 && self.left == other.left
-                && self.right == other.right;
+                && self.right == other.right
+                && self.previous == other.previous
+                && self.origin_right == other.origin_right
+                && self.len == other.len
+                && self.updated == other.updated
+                && self.target == other.target
+                && self.moves == other.moves;
         }
     }
 
@@ -155,7 +300,7 @@ pub mod rga {
 
     impl PartialOrd for Node {
         fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-            Some(self.cmp(&other))
+            Some(self.cmp(other))
         }
     }
     impl Ord for Node {
@@ -176,370 +321,1454 @@ pub mod rga {
         pub fn new(session_id: u64, site_id: u64) -> Self {
             return RGA {
                 head: None,
-                hash_map: HashMap::new(),
+                tail: None,
+                nodes: BTreeMap::new(),
                 buffer: VecDeque::new(),
                 session_id,
                 site_id,
                 local_sequence: 0,
+                version: VersionVector::new(),
+                out_of_order: HashMap::new(),
+                observers: Observers::default(),
+                log: None,
             };
         }
 
-        fn insert_into_list(&mut self, node: Rc<RefCell<Node>>) -> Rc<RefCell<Node>> {
-            let left: Option<S4Vector> = node.borrow().left.clone();
+        /// Returns the key of the node containing the element `s4vector`.
+        fn containing(&self, s4vector: &S4Vector) -> Option<S4Vector> {
+            let (key, node) = self.nodes.range(..=*s4vector).next_back()?;
 
-            if let Some(left) = left {
-                let mut current: S4Vector = left.clone();
-                while let Some(node) = self.hash_map.get(&current) {
-                    if let Some(next_s4) = &node.borrow_mut().right {
-                        if next_s4 > &node.borrow().s4vector {
-                            break;
-                        }
-                        current = next_s4.clone();
-                    } else {
-                        break;
-                    }
-                }
+            if key.ssn == s4vector.ssn
+                && key.sum == s4vector.sum
+                && key.sid == s4vector.sid
+                && s4vector.seq < key.seq + node.len as u64
+            {
+                return Some(*key);
+            }
+            return None;
+        }
 
-                if let Some(other) = self.hash_map.get(&current) {
-                    node.borrow_mut().right = other.borrow().right;
-                    other.borrow_mut().right = Some(node.borrow().s4vector);
-                }
+        /// Checks whether the element `s4vector` has been integrated.
+        pub(crate) fn contains(&self, s4vector: &S4Vector) -> bool {
+            return self.containing(s4vector).is_some();
+        }
+
+        /// Returns the element following `left`, or the head when `left` is `None`.
+        pub(crate) fn successor(&self, left: Option<S4Vector>) -> Option<S4Vector> {
+            let left: S4Vector = match left {
+                Some(left) => left,
+                None => return self.head,
+            };
+            let key: S4Vector = self.containing(&left)?;
+            let node: &Node = &self.nodes[&key];
+
+            if left == node.last() {
+                return node.right;
             }
+            return Some(left.offset(1));
+        }
+
+        /// Returns the element preceding `right`, or the last element when `right` is `None`.
+        pub(crate) fn predecessor(&self, right: Option<S4Vector>) -> Option<S4Vector> {
+            let right: S4Vector = match right {
+                Some(right) => right,
+                None => return self.tail.map(|tail| self.nodes[&tail].last()),
+            };
+            let key: S4Vector = self.containing(&right)?;
 
-            if self.head.is_none() {
-                self.head = Some(node.borrow().s4vector);
-            } else if left.is_none() {
-                self.head = Some(node.borrow().s4vector);
+            if right != key {
+                return Some(S4Vector {
+                    seq: right.seq - 1,
+                    ..right
+                });
             }
+            return self.nodes[&key]
+                .previous
+                .map(|previous| self.nodes[&previous].last());
+        }
 
-            return Rc::clone(&node);
+        /// Returns the first node of the list.
+        pub(crate) fn head(&self) -> Option<S4Vector> {
+            return self.head;
         }
 
-        /// Inserts a new value into the RGA.
-        ///
-        /// # Parameters
-        /// - `value`: The value to insert.
-        /// - `left`: The S4Vector of the left neighbor (if any).
-        /// - `right`: The S4Vector of the right neighbor (if any).
+        /// Returns the last node of the list.
+        pub(crate) fn tail(&self) -> Option<S4Vector> {
+            return self.tail;
+        }
+
+        /// Returns the node whose first element is `key`.
+        pub(crate) fn node(&self, key: &S4Vector) -> Option<&Node> {
+            return self.nodes.get(key);
+        }
+
+        /// Returns the node holding the element `s4vector`.
+        pub(crate) fn node_containing(&self, s4vector: &S4Vector) -> Option<&Node> {
+            return self.containing(s4vector).map(|key| &self.nodes[&key]);
+        }
+
+        /// Splits the node containing `s4vector` so the element starts a node.
+        fn split_before(&mut self, s4vector: &S4Vector) {
+            let key: S4Vector = match self.containing(s4vector) {
+                Some(key) => key,
+                None => return,
+            };
+            if key == *s4vector {
+                return;
+            }
+
+            let at: usize = (s4vector.seq - key.seq) as usize;
+            let node: &mut Node = self.nodes.get_mut(&key).unwrap();
+            let byte: usize = node
+                .value
+                .char_indices()
+                .nth(at)
+                .map_or(node.value.len(), |(i, _)| i);
+
+            let tail: Node = Node {
+                value: node.value.split_off(byte),
+                s4vector: *s4vector,
+                tombstone: node.tombstone,
+                left: Some(key.offset(at as u64 - 1)),
+                right: node.right,
+                previous: Some(key),
+                origin_right: node.origin_right,
+                len: node.len - at,
+                updated: node.updated,
+                target: node.target,
+                moves: BTreeSet::new(),
+                deletes: node.deletes.clone(),
+                values: node.values.clone(),
+            };
+            node.len = at;
+            node.right = Some(*s4vector);
+
+            self.set_previous(tail.right, Some(*s4vector));
+            self.nodes.insert(*s4vector, tail);
+        }
+
+        /// Splits the node containing `s4vector` so the element ends a node.
+        fn split_after(&mut self, s4vector: &S4Vector) {
+            if let Some(key) = self.containing(s4vector) {
+                if self.nodes[&key].last() != *s4vector {
+                    self.split_before(&s4vector.offset(1));
+                }
+            }
+        }
+
+        /// Splits the run containing `s4vector` so the element is a node of its own.
         ///
         /// # Returns
-        /// `Ok(())` if the insertion is successful, otherwise an error message.
-        ///
-        /// # Example
-        /// ```rust
-        /// use crdt::rga::rga::RGA;
-        /// use crdt::S4Vector;
-        /// let mut rga = RGA::new(1,1);
-        /// rga.local_insert("A".to_string(), None, None).unwrap();
-        /// ```
-        pub fn local_insert(
-            &mut self,
-            value: String,
-            left: Option<S4Vector>,
-            right: Option<S4Vector>,
-        ) -> Result<BroadcastOperation, OperationError> {
-            let new_node: Node = match (left, right) {
-                (Some(l), Some(r)) => {
-                    // Generate the S4Vector
-                    let new_s4: S4Vector = S4Vector::generate(
-                        Some(&l),
-                        Some(&r),
-                        self.session_id,
-                        self.site_id,
-                        &mut self.local_sequence,
-                    );
+        /// The node holding the element, if it has been integrated.
+        pub(crate) fn isolate(&mut self, s4vector: &S4Vector) -> Option<&mut Node> {
+            if !self.contains(s4vector) {
+                return None;
+            }
 
-                    // Check if the dependensies are resolved
-                    if !self.hash_map.contains_key(&l) {
-                        self.buffer.push_back(Operation {
-                            operation: OperationType::Insert,
-                            s4vector: new_s4,
-                            value: Some(value),
-                            left,
-                            right,
-                        });
-                        return Err(OperationError::DependancyError);
-                    }
+            self.split_before(s4vector);
+            self.split_after(s4vector);
+            return self.nodes.get_mut(s4vector);
+        }
 
-                    Node::new(value, new_s4, Some(l), Some(r))
-                }
-                (Some(l), None) => {
-                    let new_s4: S4Vector = S4Vector::generate(
-                        Some(&l),
-                        None,
-                        self.session_id,
-                        self.site_id,
-                        &mut self.local_sequence,
-                    );
+        /// Finds the element a new node should follow, starting from its left origin.
+        ///
+        /// Concurrent inserts between the same origins are ordered by their `S4Vector`,
+        /// skipping over the nodes inserted after them, so every replica places the node
+        /// at the same position regardless of the order the inserts arrive in.
+        ///
+        /// Skipping over everything inserted after a conflicting node keeps the text
+        /// typed by one site contiguous, whether it was typed forwards or backwards, so
+        /// concurrent edits at the same position never interleave.
+        fn find_position(&self, node: &Node) -> Option<S4Vector> {
+            let origin_left: Option<S4Vector> = node.left;
+            let origin_right: Option<S4Vector> = node.origin_right;
 
-                    // Check if the dependensies are resolved
-                    if !self.hash_map.contains_key(&l) {
-                        self.buffer.push_back(Operation {
-                            operation: OperationType::Insert,
-                            s4vector: new_s4,
-                            value: Some(value),
-                            left,
-                            right,
-                        });
-                        return Err(OperationError::DependancyError);
-                    }
+            let mut left: Option<S4Vector> = origin_left;
+            let mut current: Option<S4Vector> = self.successor(origin_left);
+            let mut before_origin: HashSet<S4Vector> = HashSet::new();
+            let mut conflicting: HashSet<S4Vector> = HashSet::new();
 
-                    Node::new(value, new_s4, Some(l), None)
+            while let Some(current_s4) = current {
+                if Some(current_s4) == origin_right {
+                    break;
                 }
-                (None, Some(r)) => {
-                    let new_s4: S4Vector = S4Vector::generate(
-                        None,
-                        Some(&r),
-                        self.session_id,
-                        self.site_id,
-                        &mut self.local_sequence,
-                    );
 
-                    // Check if the dependensies are resolved
-                    if !self.hash_map.contains_key(&r) {
-                        self.buffer.push_back(Operation {
-                            operation: OperationType::Insert,
-                            s4vector: new_s4,
-                            value: Some(value),
-                            left,
-                            right,
-                        });
-                        return Err(OperationError::DependancyError);
-                    }
+                let other: &Node = match self.nodes.get(&current_s4) {
+                    Some(other) => other,
+                    None => break,
+                };
+                before_origin.insert(current_s4);
+                conflicting.insert(current_s4);
 
-                    Node::new(value, new_s4, None, Some(r))
+                if other.left == origin_left {
+                    // Concurrent insert after the same node
+                    if other.s4vector < node.s4vector {
+                        left = Some(other.last());
+                        conflicting.clear();
+                    } else if other.origin_right == origin_right {
+                        break;
+                    }
+                } else if let Some(other_left) = other
+                    .left
+                    .and_then(|other_left| self.containing(&other_left))
+                    .filter(|other_left| before_origin.contains(other_left))
+                {
+                    // Inserted after one of the nodes already skipped
+                    if !conflicting.contains(&other_left) {
+                        left = Some(other.last());
+                        conflicting.clear();
+                    }
+                } else {
+                    break;
                 }
-                (None, None) => {
-                    let new_s4: S4Vector = S4Vector::generate(
-                        None,
-                        None,
-                        self.session_id,
-                        self.site_id,
-                        &mut self.local_sequence,
-                    );
 
-                    Node::new(value, new_s4, None, None)
-                }
-            };
-            let new_node: Rc<RefCell<Node>> = Rc::new(RefCell::new(new_node));
-            let node: Rc<RefCell<Node>> = self.insert_into_list(new_node);
+                current = other.right;
+            }
 
-            // Insert into the hash table
-            self.hash_map
-                .insert(node.borrow().s4vector, Rc::clone(&node));
+            return left;
+        }
 
-            self.apply_buffered_operations();
+        /// Links a node into the list between its left and right origins.
+        fn insert_into_list(&mut self, mut node: Node) {
+            let s4: S4Vector = node.s4vector;
 
-            return Ok(BroadcastOperation {
-                operation: OperationType::Insert,
-                s4vector: node.borrow().s4vector,
-                value: Some(node.borrow().value.clone()),
-                left: node.borrow().left.clone(),
-                right: node.borrow().right.clone(),
-            });
+            match self.find_position(&node) {
+                Some(left) => {
+                    let key: S4Vector = self.containing(&left).unwrap();
+                    let previous: &mut Node = self.nodes.get_mut(&key).unwrap();
+                    node.right = previous.right;
+                    node.previous = Some(key);
+                    previous.right = Some(s4);
+                }
+                None => {
+                    node.right = self.head;
+                    node.previous = None;
+                    self.head = Some(s4);
+                }
+            }
+
+            self.set_previous(node.right, Some(s4));
+            self.nodes.insert(s4, node);
         }
 
-        /// Marks a node as logically deleted.
-        ///
-        /// # Parameters
-        /// - `s4vector`: The unique identifier of the node to delete.
+        /// Appends an insert to the run ending at its left origin when the insert
+        /// continues that run.
         ///
         /// # Returns
-        /// `Ok(())` if the deletion is successful, otherwise an error message.
-        pub fn local_delete(
-            &mut self,
-            s4vector: S4Vector,
-        ) -> Result<BroadcastOperation, OperationError> {
-            let node: Rc<RefCell<Node>> = match self.hash_map.get(&s4vector) {
-                Some(node) => Rc::clone(&node),
-                None => {
-                    self.buffer.push_back(Operation {
-                        operation: OperationType::Delete,
-                        s4vector,
-                        value: None,
-                        left: None,
-                        right: None,
-                    });
-                    return Err(OperationError::DependancyError);
-                }
+        /// `true` if the run was extended.
+        fn extend_run(&mut self, op: &Operation) -> bool {
+            let left: S4Vector = match op.left {
+                Some(left) => left,
+                None => return false,
             };
+            let value: &String = match &op.value {
+                Some(value) => value,
+                None => return false,
+            };
+            if op.run_len() != value.chars().count() || left.offset(1) != op.s4vector {
+                return false;
+            }
 
-            node.borrow_mut().tombstone = true;
-
-            self.apply_buffered_operations();
+            let key: S4Vector = match self.containing(&left) {
+                Some(key) => key,
+                None => return false,
+            };
+            let node: &mut Node = self.nodes.get_mut(&key).unwrap();
+            if node.last() != left
+                || !node.is_char_run()
+                || node.right != op.right
+                || node.origin_right != op.right
+            {
+                return false;
+            }
 
-            return Ok(BroadcastOperation {
-                operation: OperationType::Delete,
-                s4vector: node.borrow().s4vector,
-                value: None,
-                left: node.borrow().left.clone(),
-                right: node.borrow().right.clone(),
-            });
+            node.value.push_str(value);
+            node.len += op.run_len();
+            return true;
         }
 
-        /// Marks a node as logically deleted.
-        ///
-        /// # Parameters
-        /// - `s4vector`: The unique identifier of the node to delete.
+        /// Removes an element inserted by the current transaction, used to roll back
+        /// a local insert.
         ///
-        /// # Returns
-        /// `Ok(())` if the deletion is successful, otherwise an error message.
-        pub fn local_update(
-            &mut self,
-            s4vector: S4Vector,
-            value: String,
-        ) -> Result<BroadcastOperation, OperationError> {
-            let node: Rc<RefCell<Node>> = match &self.hash_map.get(&s4vector) {
-                Some(node) => Rc::clone(node),
-                None => {
-                    self.buffer.push_back(Operation {
-                        operation: OperationType::Update,
-                        s4vector,
-                        value: Some(value),
-                        left: None,
-                        right: None,
-                    });
-                    return Err(OperationError::DependancyError);
-                }
+        /// The element must be the most recent local insert after its left origin.
+        pub(crate) fn unlink(&mut self, s4vector: &S4Vector) {
+            let key: S4Vector = match self.containing(s4vector) {
+                Some(key) => key,
+                None => return,
             };
-            if !node.borrow().tombstone {
-                node.borrow_mut().value = value;
+
+            if key != *s4vector {
+                // The insert extended a run
+                let node: &mut Node = self.nodes.get_mut(&key).unwrap();
+                node.value.pop();
+                node.len -= 1;
+                return;
             }
-            self.apply_buffered_operations();
 
-            return Ok(BroadcastOperation {
-                operation: OperationType::Update,
-                s4vector,
-                value: Some(node.borrow().value.clone()),
-                left: node.borrow().left.clone(),
-                right: node.borrow().right.clone(),
-            });
+            let node: Node = self.nodes.remove(&key).unwrap();
+            match node.previous {
+                Some(previous) => self.nodes.get_mut(&previous).unwrap().right = node.right,
+                None => self.head = node.right,
+            }
+            self.set_previous(node.right, node.previous);
         }
 
-        /// Remote operation to add a new element at a position based on a provided UID
-        /// This operation updates the RGA to ensure eventual consistency
-        pub fn remote_insert(
+        /// Links the node `key` back to `previous`, or makes `previous` the tail when
+        /// `key` is `None`.
+        fn set_previous(&mut self, key: Option<S4Vector>, previous: Option<S4Vector>) {
+            match key.and_then(|key| self.nodes.get_mut(&key)) {
+                Some(node) => node.previous = previous,
+                None => self.tail = previous,
+            }
+        }
+
+        pub(crate) fn local_sequence(&self) -> u64 {
+            return self.local_sequence;
+        }
+
+        /// Resets the local logical clock, used to roll back a failed transaction.
+        pub(crate) fn set_local_sequence(&mut self, local_sequence: u64) {
+            self.local_sequence = local_sequence;
+
+            let seen: u64 = self.version.get(self.site_id).min(local_sequence);
+            self.version.set(self.site_id, seen);
+            if let Some(pending) = self.out_of_order.get_mut(&self.site_id) {
+                pending.retain(|seq| *seq <= local_sequence);
+            }
+        }
+
+        /// Returns the current session ID.
+        pub fn session_id(&self) -> u64 {
+            return self.session_id;
+        }
+
+        /// Returns the site ID of this replica.
+        pub fn site_id(&self) -> u64 {
+            return self.site_id;
+        }
+
+        /// Returns the version of the RGA, covering every operation integrated so far.
+        pub fn version(&self) -> &VersionVector {
+            return &self.version;
+        }
+
+        /// Iterates over every node in order of `S4Vector`.
+        pub(crate) fn nodes(&self) -> impl Iterator<Item = &Node> + '_ {
+            return self.nodes.values();
+        }
+
+        /// Delivers the changes recorded so far to subscribers.
+        pub(crate) fn flush_changes(&mut self) {
+            self.observers.flush(&self.version);
+        }
+
+        /// Merges `version` into the version of the RGA, used once the RGA is known to
+        /// hold the effects of every operation in `version` without having received
+        /// them as operations.
+        pub(crate) fn merge_version(&mut self, version: &VersionVector) {
+            self.version.merge(version);
+            for (sid, seq) in version.iter() {
+                self.observe(
+                    &S4Vector {
+                        ssn: 0,
+                        sum: 0,
+                        sid,
+                        seq,
+                    },
+                    0,
+                );
+            }
+
+            self.resume_local_sequence();
+        }
+
+        /// Changes the session and site the RGA generates operations for, used when a
+        /// replica saved by another session or site is opened.
+        pub(crate) fn set_identity(&mut self, session_id: u64, site_id: u64) {
+            self.session_id = session_id;
+            self.site_id = site_id;
+            self.resume_local_sequence();
+        }
+
+        /// Moves the local clock past every operation of this site the RGA has seen, so
+        /// a replica rebuilt from its own operations never reuses their sequence numbers.
+        pub(crate) fn resume_local_sequence(&mut self) {
+            let seen: u64 = self.version.get(self.site_id);
+            self.local_sequence = self.local_sequence.max(seen);
+        }
+
+        /// Returns the groups of operations waiting for their dependencies.
+        pub(crate) fn buffered(&self) -> impl Iterator<Item = &Vec<Operation>> + '_ {
+            return self.buffer.iter();
+        }
+
+        /// Returns the sequence numbers integrated ahead of the version, for each site.
+        pub(crate) fn out_of_order(&self) -> &HashMap<u64, BTreeSet<u64>> {
+            return &self.out_of_order;
+        }
+
+        /// Restores the bookkeeping of a replica whose nodes have just been restored,
+        /// replacing whatever integrating the nodes recorded.
+        pub(crate) fn restore(
             &mut self,
-            value: String,
-            s4vector: S4Vector,
-            left: Option<S4Vector>,
-            right: Option<S4Vector>,
+            local_sequence: u64,
+            version: VersionVector,
+            out_of_order: HashMap<u64, BTreeSet<u64>>,
+            buffer: VecDeque<Vec<Operation>>,
         ) {
-            let new_node: Node = match (left, right) {
-                (Some(l), Some(r)) => Node::new(value, s4vector, Some(l), Some(r)),
-                (Some(l), None) => Node::new(value, s4vector, Some(l), None),
-                (None, Some(r)) => Node::new(value, s4vector, None, Some(r)),
-                (None, None) => Node::new(value, s4vector, None, None),
-            };
-            let new_node: Rc<RefCell<Node>> = Rc::new(RefCell::new(new_node));
-            let node: Rc<RefCell<Node>> = self.insert_into_list(new_node);
+            self.local_sequence = local_sequence;
+            self.version = version;
+            self.out_of_order = out_of_order;
+            self.buffer = buffer;
+        }
 
-            self.hash_map
-                .insert(node.borrow().s4vector, Rc::clone(&node));
-            self.apply_buffered_operations();
+        /// Records that the `len` operations starting at `id` have been integrated.
+        fn observe(&mut self, id: &S4Vector, len: usize) {
+            let pending: &mut BTreeSet<u64> = self.out_of_order.entry(id.sid).or_default();
+            pending.extend(id.seq..id.seq + len as u64);
+
+            let mut seen: u64 = self.version.get(id.sid);
+            while pending.remove(&(seen + 1)) {
+                seen += 1;
+            }
+            pending.retain(|seq| *seq > seen);
+
+            if pending.is_empty() {
+                self.out_of_order.remove(&id.sid);
+            }
+            self.version.set(id.sid, seen);
         }
 
-        /// Remote operation to remove an ekement given the UID
-        /// This operation updates the RGA to ensure eventual consistency
-        pub fn remote_delete(&mut self, s4vector: S4Vector) {
-            let node: Rc<RefCell<Node>> = match self.hash_map.get(&s4vector) {
-                Some(node) => Rc::clone(&node),
-                None => {
-                    // The values has not been added yet
-                    return;
+        /// Generates the `S4Vector` of a local operation that must take precedence
+        /// over `latest`.
+        fn next_id(&mut self, latest: Option<&S4Vector>) -> S4Vector {
+            return S4Vector::generate(
+                latest,
+                None,
+                self.session_id,
+                self.site_id,
+                &mut self.local_sequence,
+            );
+        }
+
+        /// Checks whether every element an operation refers to is known, treating the
+        /// elements in `pending` as known and `version` as the version of the RGA.
+        fn dependencies_met(
+            &self,
+            op: &Operation,
+            pending: &HashSet<S4Vector>,
+            version: &VersionVector,
+        ) -> bool {
+            let known = |s4: &S4Vector| self.contains(s4) || pending.contains(s4);
+
+            return match &op.operation {
+                OperationType::Insert | OperationType::InsertRun => {
+                    op.left.as_ref().is_none_or(known) && op.right.as_ref().is_none_or(known)
+                }
+                OperationType::Update | OperationType::Delete => known(&op.s4vector),
+                OperationType::Move => {
+                    known(&op.s4vector)
+                        && op.left.as_ref().is_none_or(known)
+                        && op.right.as_ref().is_none_or(known)
+                }
+                OperationType::DeleteRange { to, version: seen }
+                | OperationType::UpdateRange { to, version: seen } => {
+                    known(&op.s4vector) && known(to) && version.dominates(seen)
                 }
             };
-            node.borrow_mut().tombstone = true;
-            self.apply_buffered_operations();
         }
 
-        /// Remote operation to update an element
-        /// This operation updates the RGA to ensure eventual consistency
-        pub fn remote_update(&mut self, s4vector: S4Vector, value: String) {
-            let node: Rc<RefCell<Node>> = Rc::clone(&self.hash_map[&s4vector]);
-            if !node.borrow().tombstone {
-                node.borrow_mut().value = value;
+        /// Checks whether a group of operations can be applied in order.
+        fn group_ready(&self, group: &[Operation]) -> bool {
+            let mut pending: HashSet<S4Vector> = HashSet::new();
+            let mut version: VersionVector = self.version.clone();
+
+            for op in group {
+                if !self.dependencies_met(op, &pending, &version) {
+                    return false;
+                }
+
+                let len: usize = op.run_len().max(1);
+                for i in 0..op.run_len() {
+                    pending.insert(op.s4vector.offset(i as u64));
+                }
+                if let OperationType::Move = op.operation {
+                    pending.insert(op.id);
+                }
+                if op.id.seq <= version.get(op.id.sid) + 1 {
+                    let seen: u64 = version.get(op.id.sid).max(op.id.seq + len as u64 - 1);
+                    version.set(op.id.sid, seen);
+                }
             }
-            self.apply_buffered_operations();
+            return true;
         }
 
-        /// Reads the current state of the RGA, skipping tombstoned nodes.
-        ///
-        /// # Returns
-        /// A vector of strings representing the current sequence.
-        pub fn read(&self) -> Vec<String> {
-            let mut result: Vec<String> = Vec::new();
-            let mut current: Option<S4Vector> = self.head;
+        /// Returns the keys of the nodes from `from` to `to`, splitting the runs at
+        /// either end. The range may be given in either order.
+        fn range_keys(&mut self, from: &S4Vector, to: &S4Vector) -> Option<Vec<S4Vector>> {
+            if !self.contains(from) || !self.contains(to) {
+                return None;
+            }
 
-            while let Some(current_s4) = current {
-                if let Some(node) = self.hash_map.get(&current_s4) {
-                    if !node.borrow().tombstone {
-                        result.push(node.borrow().value.clone());
+            for (start, end) in [(from, to), (to, from)] {
+                self.split_before(start);
+                self.split_after(end);
+
+                let mut keys: Vec<S4Vector> = Vec::new();
+                let mut current: Option<S4Vector> = Some(*start);
+                while let Some(key) = current {
+                    let node: &Node = &self.nodes[&key];
+                    keys.push(key);
+                    if node.last() == *end {
+                        return Some(keys);
                     }
+                    current = node.right;
+                }
+            }
+            return None;
+        }
 
-                    current = node.borrow().right;
-                } else {
-                    break;
+        /// Sets the value of an element unless a later update has already been applied.
+        fn update_element(&mut self, s4vector: &S4Vector, value: &str, id: &S4Vector) {
+            if let Some(node) = self.isolate(s4vector) {
+                if node.values.is_empty() {
+                    node.values.insert(node.s4vector, node.value.clone());
+                }
+                // Updates concurrent with a delete are kept for reading past versions
+                node.values.insert(*id, value.to_string());
+
+                if !node.tombstone && *id > node.updated.unwrap_or(node.s4vector) {
+                    node.value = value.to_string();
+                    node.updated = Some(*id);
                 }
             }
-            return result;
         }
 
-        pub fn apply_buffered_operations(&mut self) {
-            let mut buffer: VecDeque<Operation> = self.buffer.clone();
+        /// Applies a range operation to every element in the range covered by `version`.
+        fn integrate_range(&mut self, op: &Operation, to: &S4Vector, version: &VersionVector) {
+            let keys: Vec<S4Vector> = match self.range_keys(&op.s4vector, to) {
+                Some(keys) => keys,
+                None => return,
+            };
 
-            buffer.retain(|op| {
-                if let Some(left) = &op.left.clone() {
-                    if !self.hash_map.contains_key(left) {
-                        return true;
-                    }
+            for key in keys {
+                // Elements inserted concurrently with the range operation are kept
+                let seen: u64 = version.get(key.sid);
+                if key.seq > seen {
+                    continue;
                 }
+                self.split_after(&S4Vector { seq: seen, ..key });
 
-                match op.operation {
-                    OperationType::Insert => {
-                        if let Some(value) = &op.value {
-                            self.remote_insert(value.clone(), op.s4vector, op.left, op.right);
-                        }
+                // Moved elements are only affected at the position the range covered
+                let node: &Node = &self.nodes[&key];
+                let (len, element): (usize, S4Vector) = (node.len, node.target.unwrap_or(key));
+                if self.position_at(&element, version) != key {
+                    continue;
+                }
+
+                match &op.operation {
+                    OperationType::DeleteRange { .. } => {
+                        let node: &mut Node = self.nodes.get_mut(&element).unwrap();
+                        node.tombstone = true;
+                        node.deletes.insert(op.id);
                     }
-                    OperationType::Update => {
-                        if let Some(value) = &op.value {
-                            self.remote_update(op.s4vector, value.to_string());
+                    OperationType::UpdateRange { .. } => {
+                        let value: &str = op.value.as_deref().unwrap_or_default();
+                        for i in 0..len {
+                            self.update_element(&element.offset(i as u64), value, &op.id);
                         }
                     }
-                    OperationType::Delete => {
-                        self.remote_delete(op.s4vector);
-                    }
+                    _ => {}
                 }
+            }
+        }
 
-                false
-            });
-
-            self.buffer = buffer;
+        /// Applies an operation whose dependencies have been met, adding it to the log
+        /// if enabled.
+        pub(crate) fn integrate(&mut self, op: &Operation) {
+            if let Some(log) = self.log.as_mut() {
+                log.record(BroadcastOperation::from(op.clone()));
+            }
+            self.integrate_unlogged(op);
         }
-    }
 
-    #[cfg(test)]
-    mod tests {
-        use super::*;
+        /// Applies an operation whose dependencies have been met without adding it to
+        /// the log, recording the changes to the visible sequence if anyone is subscribed.
+        pub(crate) fn integrate_unlogged(&mut self, op: &Operation) {
+            if !self.observers.is_active() {
+                self.integrate_operation(op);
+                return;
+            }
 
-        #[test]
-        fn test_insert() {
-            let mut rga = RGA::new(1, 1);
-            let result = rga.local_insert("A".to_string(), None, None);
-            assert!(result.is_ok());
-            assert_eq!(rga.hash_map.len(), 1);
+            let slots: HashSet<S4Vector> = self.affected_slots(op);
+            let before: HashMap<S4Vector, (usize, S4Vector, String)> = self.visible_slots(&slots);
+            self.integrate_operation(op);
+            let after: HashMap<S4Vector, (usize, S4Vector, String)> = self.visible_slots(&slots);
+
+            let mut deleted: Vec<Change> = Vec::new();
+            let mut inserted: Vec<Change> = Vec::new();
+            let mut updated: Vec<Change> = Vec::new();
+            for slot in &slots {
+                match (before.get(slot), after.get(slot)) {
+                    (Some((index, s4vector, _)), None) => deleted.push(Change::Delete {
+                        index: *index,
+                        s4vector: *s4vector,
+                    }),
+                    (None, Some((index, s4vector, value))) => inserted.push(Change::Insert {
+                        index: *index,
+                        s4vector: *s4vector,
+                        value: value.clone(),
+                    }),
+                    (Some((_, _, old)), Some((index, s4vector, value))) if old != value => updated
+                        .push(Change::Update {
+                            index: *index,
+                            s4vector: *s4vector,
+                            value: value.clone(),
+                        }),
+                    _ => {}
+                }
+            }
+
+            // Deletions from the back first so every index is valid when it is applied
+            let index = |change: &Change| -> usize {
+                return match change {
+                    Change::Insert { index, .. }
+                    | Change::Delete { index, .. }
+                    | Change::Update { index, .. } => *index,
+                };
+            };
+            deleted.sort_by_key(|change| std::cmp::Reverse(index(change)));
+            inserted.sort_by_key(index);
+            updated.sort_by_key(index);
+            self.observers
+                .record(deleted.into_iter().chain(inserted).chain(updated));
         }
 
-        #[test]
-        fn test_delete() {
-            let mut rga = RGA::new(1, 1);
-            let s4 = rga
-                .local_insert("A".to_string(), None, None)
+        /// Returns the list positions whose visibility or value an operation may change.
+        fn affected_slots(&mut self, op: &Operation) -> HashSet<S4Vector> {
+            return match &op.operation {
+                OperationType::Insert | OperationType::InsertRun => (0..op.run_len())
+                    .map(|i| op.s4vector.offset(i as u64))
+                    .collect(),
+                OperationType::Update | OperationType::Delete => {
+                    HashSet::from([self.position_of(&op.s4vector)])
+                }
+                OperationType::Move => HashSet::from([self.position_of(&op.s4vector), op.id]),
+                OperationType::DeleteRange { to, .. } | OperationType::UpdateRange { to, .. } => {
+                    let keys: Vec<S4Vector> = self.range_keys(&op.s4vector, to).unwrap_or_default();
+
+                    // An element moved out of the range is shown at its current position
+                    let mut slots: HashSet<S4Vector> = HashSet::new();
+                    for key in keys {
+                        let node: &Node = &self.nodes[&key];
+                        let element: S4Vector = node.target.unwrap_or(key);
+                        for i in 0..node.len as u64 {
+                            slots.insert(key.offset(i));
+                            slots.insert(self.position_of(&element.offset(i)));
+                        }
+                    }
+                    slots
+                }
+            };
+        }
+
+        /// Returns the visible index, `S4Vector` and value of every visible list position
+        /// in `slots`.
+        fn visible_slots(
+            &self,
+            slots: &HashSet<S4Vector>,
+        ) -> HashMap<S4Vector, (usize, S4Vector, String)> {
+            let mut visible: HashMap<S4Vector, (usize, S4Vector, String)> = HashMap::new();
+            let mut index: usize = 0;
+            let mut current: Option<S4Vector> = self.head;
+
+            while let Some(key) = current {
+                let node: &Node = &self.nodes[&key];
+                if let Some(element) = self.displayed(node) {
+                    // Runs can be much longer than the set of slots
+                    let offsets: Vec<usize> = match node.len <= slots.len() {
+                        true => (0..node.len).collect(),
+                        false => slots
+                            .iter()
+                            .filter(|slot| self.containing(slot) == Some(key))
+                            .map(|slot| (slot.seq - key.seq) as usize)
+                            .collect(),
+                    };
+                    for i in offsets {
+                        let slot: S4Vector = key.offset(i as u64);
+                        if slots.contains(&slot) {
+                            let value: String = match element.len {
+                                1 => element.value.clone(),
+                                _ => element
+                                    .value
+                                    .chars()
+                                    .nth(i)
+                                    .map(String::from)
+                                    .unwrap_or_default(),
+                            };
+                            visible.insert(slot, (index + i, node.target.unwrap_or(slot), value));
+                        }
+                    }
+                    index += node.len;
+                }
+                current = node.right;
+            }
+            return visible;
+        }
+
+        /// Returns the subscribers of the RGA.
+        pub(crate) fn observers(&mut self) -> &mut Observers {
+            return &mut self.observers;
+        }
+
+        /// Returns the log of the RGA, `None` unless enabled.
+        pub(crate) fn log_slot(&mut self) -> &mut Option<OpLog> {
+            return &mut self.log;
+        }
+
+        /// Returns the log of the RGA, if enabled.
+        pub fn log(&self) -> Option<&OpLog> {
+            return self.log.as_ref();
+        }
+
+        /// Applies an operation whose dependencies have been met.
+        fn integrate_operation(&mut self, op: &Operation) {
+            self.observe(&op.id, op.run_len().max(1));
+
+            match &op.operation {
+                OperationType::Insert | OperationType::InsertRun => {
+                    if op.run_len() == 0 || self.contains(&op.s4vector) {
+                        return;
+                    }
+
+                    // Neighbours inside a run must be node boundaries
+                    if let Some(left) = op.left {
+                        self.split_after(&left);
+                    }
+                    if let Some(right) = op.right {
+                        self.split_before(&right);
+                    }
+
+                    if self.successor(op.left) == op.right && self.extend_run(op) {
+                        return;
+                    }
+
+                    let mut new_node: Node = Node::new(
+                        op.value.clone().unwrap_or_default(),
+                        op.s4vector,
+                        op.left,
+                        op.right,
+                    );
+                    new_node.len = op.run_len();
+                    self.insert_into_list(new_node);
+                }
+                OperationType::Update => {
+                    if let Some(value) = &op.value {
+                        self.update_element(&op.s4vector, value, &op.id);
+                    }
+                }
+                OperationType::Delete => {
+                    if let Some(node) = self.isolate(&op.s4vector) {
+                        node.tombstone = true;
+                        node.deletes.insert(op.id);
+                    }
+                }
+                OperationType::DeleteRange { to, version }
+                | OperationType::UpdateRange { to, version } => {
+                    self.integrate_range(op, to, version);
+                }
+                OperationType::Move => {
+                    if self.contains(&op.id) {
+                        return;
+                    }
+
+                    if let Some(left) = op.left {
+                        self.split_after(&left);
+                    }
+                    if let Some(right) = op.right {
+                        self.split_before(&right);
+                    }
+
+                    let mut position: Node = Node::new(String::new(), op.id, op.left, op.right);
+                    position.target = Some(op.s4vector);
+                    self.insert_into_list(position);
+
+                    if let Some(element) = self.isolate(&op.s4vector) {
+                        element.moves.insert(op.id);
+                    }
+                }
+            }
+        }
+
+        /// Returns the `S4Vector` of the element shown at `s4vector`, which differs
+        /// from `s4vector` for a position an element was moved to.
+        pub(crate) fn identity_of(&self, s4vector: &S4Vector) -> S4Vector {
+            return self
+                .nodes
+                .get(s4vector)
+                .and_then(|node| node.target)
+                .unwrap_or(*s4vector);
+        }
+
+        /// Returns the `S4Vector` of the current position of an element.
+        pub(crate) fn position_of(&self, s4vector: &S4Vector) -> S4Vector {
+            return self
+                .nodes
+                .get(s4vector)
+                .and_then(|node| node.moves.last().copied())
+                .unwrap_or(*s4vector);
+        }
+
+        /// Returns the `S4Vector` of the position of an element as of `version`.
+        pub(crate) fn position_at(&self, s4vector: &S4Vector, version: &VersionVector) -> S4Vector {
+            return self
+                .nodes
+                .get(s4vector)
+                .and_then(|node| node.moves.iter().rev().find(|id| version.contains(id)))
+                .copied()
+                .unwrap_or(*s4vector);
+        }
+
+        /// Returns the node whose value is shown at `node`, if any.
+        ///
+        /// A moved element is only shown at its current position, and a position is
+        /// only shown while it is the current position of its element.
+        pub(crate) fn displayed<'a>(&'a self, node: &'a Node) -> Option<&'a Node> {
+            let element: &Node = match node.target {
+                Some(target) => self.nodes.get(&target)?,
+                None => node,
+            };
+
+            if element.tombstone || self.position_of(&element.s4vector) != node.s4vector {
+                return None;
+            }
+            return Some(element);
+        }
+
+        /// Applies a group of remote operations atomically, buffering the whole group
+        /// until all of its dependencies have been met.
+        pub(crate) fn apply_remote_group(&mut self, group: Vec<Operation>) {
+            if !self.group_ready(&group) {
+                self.buffer.push_back(group);
+                return;
+            }
+
+            for op in &group {
+                self.integrate(op);
+            }
+            self.apply_buffered_operations();
+        }
+
+        /// Generates the insert operation for a local insert.
+        ///
+        /// A single character typed directly after the last local insert continues
+        /// that insert's run, so it shares the run's `sum` instead of generating a new one.
+        ///
+        /// # Returns
+        /// The operation, positioned directly after `left`, or directly before `right`
+        /// when only `right` is given. `OperationError::DependancyError` if a neighbour
+        /// is unknown, or `OperationError::NeighbourError` if a visible element lies
+        /// between `left` and `right`, in which case no `S4Vector` is generated.
+        pub(crate) fn prepare_local_insert(
+            &mut self,
+            value: String,
+            left: Option<S4Vector>,
+            right: Option<S4Vector>,
+        ) -> Result<Operation, OperationError> {
+            let left: Option<S4Vector> = left.map(|left| self.position_of(&left));
+            let right: Option<S4Vector> = right.map(|right| self.position_of(&right));
+
+            // Check if the dependencies are resolved
+            for dependency in [left, right].into_iter().flatten() {
+                if !self.contains(&dependency) {
+                    return Err(OperationError::DependancyError);
+                }
+            }
+
+            let left: Option<S4Vector> = match (left, right) {
+                (None, Some(right)) => self.predecessor(Some(right)),
+                _ => left,
+            };
+            if let Some(right) = right {
+                // Only deleted or moved elements may separate the neighbours
+                let mut current: Option<S4Vector> = self.successor(left);
+                while current != Some(right) {
+                    match current {
+                        Some(element) if !self.is_visible(&element) => {
+                            current = self.successor(current);
+                        }
+                        _ => return Err(OperationError::NeighbourError),
+                    }
+                }
+            }
+
+            let continues_run: bool = left.is_some_and(|left| {
+                left.ssn == self.session_id
+                    && left.sid == self.site_id
+                    && left.seq == self.local_sequence
+                    && value.chars().count() == 1
+                    && self.containing(&left).is_some_and(|key| {
+                        let node: &Node = &self.nodes[&key];
+                        node.last() == left && node.is_char_run() && node.right == node.origin_right
+                    })
+            });
+
+            // Generate the S4Vector
+            let new_s4: S4Vector = match left.filter(|_| continues_run) {
+                Some(left) => {
+                    self.local_sequence += 1;
+                    left.offset(1)
+                }
+                None => S4Vector::generate(
+                    left.as_ref(),
+                    right.as_ref(),
+                    self.session_id,
+                    self.site_id,
+                    &mut self.local_sequence,
+                ),
+            };
+
+            return Ok(Operation {
+                operation: OperationType::Insert,
+                s4vector: new_s4,
+                value: Some(value),
+                left,
+                right: self.successor(left),
+                id: new_s4,
+            });
+        }
+
+        /// Generates the delete operation for a local delete of an element that has
+        /// been integrated.
+        pub(crate) fn prepare_local_delete(&mut self, s4vector: S4Vector) -> Operation {
+            let s4vector: S4Vector = self.identity_of(&s4vector);
+            let id: S4Vector = self.next_id(Some(&s4vector));
+            let node: &Node = self.isolate(&s4vector).unwrap();
+
+            return Operation {
+                operation: OperationType::Delete,
+                s4vector,
+                value: None,
+                left: node.left,
+                right: node.right,
+                id,
+            };
+        }
+
+        /// Generates the update operation for a local update of an element that has
+        /// been integrated, taking precedence over the element's current value.
+        pub(crate) fn prepare_local_update(
+            &mut self,
+            s4vector: S4Vector,
+            value: String,
+        ) -> Operation {
+            let s4vector: S4Vector = self.identity_of(&s4vector);
+            let node: &Node = self.isolate(&s4vector).unwrap();
+            let latest: S4Vector = node.updated.unwrap_or(node.s4vector);
+            let (left, right) = (node.left, node.right);
+            let id: S4Vector = self.next_id(Some(&latest));
+
+            return Operation {
+                operation: OperationType::Update,
+                s4vector,
+                value: Some(value),
+                left,
+                right,
+                id,
+            };
+        }
+
+        /// Inserts a new value into the RGA.
+        ///
+        /// # Parameters
+        /// - `value`: The value to insert.
+        /// - `left`: The S4Vector of the left neighbor (if any).
+        /// - `right`: The S4Vector of the right neighbor (if any). Given alone, the
+        ///   value is inserted directly before it; given with `left`, only deleted or
+        ///   moved elements may lie between the two.
+        ///
+        /// # Returns
+        /// The operation to broadcast, `OperationError::DependancyError` if a neighbour
+        /// is unknown, or `OperationError::NeighbourError` if `left` and `right` are
+        /// not neighbours, in which case nothing is applied.
+        ///
+        /// # Example
+        /// ```rust
+        /// use crdt::rga::rga::RGA;
+        /// use crdt::S4Vector;
+        /// let mut rga = RGA::new(1,1);
+        /// rga.local_insert("A".to_string(), None, None).unwrap();
+        /// ```
+        pub fn local_insert(
+            &mut self,
+            value: String,
+            left: Option<S4Vector>,
+            right: Option<S4Vector>,
+        ) -> Result<BroadcastOperation, OperationError> {
+            let operation: Operation = self.prepare_local_insert(value, left, right)?;
+            self.integrate(&operation);
+            self.apply_buffered_operations();
+
+            return Ok(BroadcastOperation::from(operation));
+        }
+
+        /// Marks a node as logically deleted.
+        ///
+        /// # Parameters
+        /// - `s4vector`: The unique identifier of the node to delete.
+        ///
+        /// # Returns
+        /// The operation to broadcast, or `OperationError::DependancyError` if the node
+        /// is unknown, in which case nothing is applied.
+        pub fn local_delete(
+            &mut self,
+            s4vector: S4Vector,
+        ) -> Result<BroadcastOperation, OperationError> {
+            if !self.contains(&s4vector) {
+                return Err(OperationError::DependancyError);
+            }
+
+            let operation: Operation = self.prepare_local_delete(s4vector);
+            self.integrate(&operation);
+            self.apply_buffered_operations();
+
+            return Ok(BroadcastOperation::from(operation));
+        }
+
+        /// Updates the value of a node.
+        ///
+        /// # Parameters
+        /// - `s4vector`: The unique identifier of the node to update.
+        /// - `value`: The new value.
+        ///
+        /// # Returns
+        /// The operation to broadcast, or `OperationError::DependancyError` if the node
+        /// is unknown, in which case nothing is applied.
+        pub fn local_update(
+            &mut self,
+            s4vector: S4Vector,
+            value: String,
+        ) -> Result<BroadcastOperation, OperationError> {
+            if !self.contains(&s4vector) {
+                return Err(OperationError::DependancyError);
+            }
+
+            // Updates to deleted nodes are ignored on integration, but the value is still
+            // broadcast so every replica reads the same past versions
+            let operation: Operation = self.prepare_local_update(s4vector, value);
+            self.integrate(&operation);
+            self.apply_buffered_operations();
+
+            return Ok(BroadcastOperation::from(operation));
+        }
+
+        /// Returns the `S4Vector` of the visible element at `index`.
+        fn element_at(&self, index: usize) -> Option<S4Vector> {
+            let mut remaining: usize = index;
+            let mut current: Option<S4Vector> = self.head;
+
+            while let Some(key) = current {
+                let node: &Node = self.nodes.get(&key)?;
+                if let Some(element) = self.displayed(node) {
+                    if remaining < element.len {
+                        return Some(key.offset(remaining as u64));
+                    }
+                    remaining -= element.len;
+                }
+                current = node.right;
+            }
+            return None;
+        }
+
+        /// Maps a range of visible indices to the `S4Vector`s of its first and last elements.
+        fn element_range(
+            &self,
+            range: Range<usize>,
+        ) -> Result<(S4Vector, S4Vector), OperationError> {
+            if range.is_empty() {
+                return Err(OperationError::IndexError);
+            }
+
+            let from: S4Vector = self
+                .element_at(range.start)
+                .ok_or(OperationError::IndexError)?;
+            let to: S4Vector = self
+                .element_at(range.end - 1)
+                .ok_or(OperationError::IndexError)?;
+            return Ok((from, to));
+        }
+
+        /// Generates a range operation covering every element this replica has seen.
+        fn prepare_local_range(
+            &mut self,
+            from: S4Vector,
+            to: S4Vector,
+            update: Option<String>,
+        ) -> Result<Operation, OperationError> {
+            let (from, to) = (self.position_of(&from), self.position_of(&to));
+            let keys: Vec<S4Vector> = self
+                .range_keys(&from, &to)
+                .ok_or(OperationError::DependancyError)?;
+            let version: VersionVector = self.version.clone();
+
+            let operation: OperationType = match update {
+                Some(_) => OperationType::UpdateRange { to, version },
+                None => OperationType::DeleteRange { to, version },
+            };
+
+            // A range update must take precedence over the current value of every element
+            let latest: Option<S4Vector> = keys
+                .iter()
+                .map(|key| {
+                    let node: &Node = &self.nodes[key];
+                    let element: &Node = node.target.map_or(node, |target| &self.nodes[&target]);
+                    element.updated.unwrap_or(element.last())
+                })
+                .max_by_key(|s4| (s4.ssn, s4.sum));
+            let id: S4Vector = self.next_id(latest.as_ref());
+
+            return Ok(Operation {
+                operation,
+                s4vector: from,
+                value: update,
+                left: None,
+                right: None,
+                id,
+            });
+        }
+
+        /// Marks every element from `from` to `to` (inclusive) as logically deleted,
+        /// using a single operation.
+        ///
+        /// Elements inserted into the range by other replicas concurrently with the
+        /// delete are kept.
+        ///
+        /// # Parameters
+        /// - `from`: The `S4Vector` of the first element of the range.
+        /// - `to`: The `S4Vector` of the last element of the range.
+        ///
+        /// # Returns
+        /// The operation to broadcast, or `OperationError::DependancyError` if either
+        /// end of the range is unknown.
+        ///
+        /// # Example
+        /// ```rust
+        /// use crdt::rga::rga::RGA;
+        /// let mut rga = RGA::new(1, 1);
+        /// let a = rga.local_insert("A".to_string(), None, None).unwrap().s4vector;
+        /// let b = rga.local_insert("B".to_string(), Some(a), None).unwrap().s4vector;
+        /// let c = rga.local_insert("C".to_string(), Some(b), None).unwrap().s4vector;
+        ///
+        /// rga.local_delete_range(a, b).unwrap();
+        /// assert_eq!(rga.read(), vec!["C".to_string()]);
+        /// ```
+        pub fn local_delete_range(
+            &mut self,
+            from: S4Vector,
+            to: S4Vector,
+        ) -> Result<BroadcastOperation, OperationError> {
+            let operation: Operation = self.prepare_local_range(from, to, None)?;
+            self.integrate(&operation);
+            self.apply_buffered_operations();
+
+            return Ok(BroadcastOperation::from(operation));
+        }
+
+        /// Marks the visible elements in `range` as logically deleted, using a single
+        /// operation.
+        ///
+        /// # Parameters
+        /// - `range`: The range of indices into the result of `read()`.
+        ///
+        /// # Returns
+        /// The operation to broadcast, or `OperationError::IndexError` if the range is
+        /// empty or out of bounds.
+        pub fn local_delete_range_at(
+            &mut self,
+            range: Range<usize>,
+        ) -> Result<BroadcastOperation, OperationError> {
+            let (from, to) = self.element_range(range)?;
+            return self.local_delete_range(from, to);
+        }
+
+        /// Updates the value of every element from `from` to `to` (inclusive), using a
+        /// single operation.
+        ///
+        /// # Parameters
+        /// - `from`: The `S4Vector` of the first element of the range.
+        /// - `to`: The `S4Vector` of the last element of the range.
+        /// - `value`: The new value of every element in the range.
+        ///
+        /// # Returns
+        /// The operation to broadcast, or `OperationError::DependancyError` if either
+        /// end of the range is unknown.
+        pub fn local_update_range(
+            &mut self,
+            from: S4Vector,
+            to: S4Vector,
+            value: String,
+        ) -> Result<BroadcastOperation, OperationError> {
+            let operation: Operation = self.prepare_local_range(from, to, Some(value))?;
+            self.integrate(&operation);
+            self.apply_buffered_operations();
+
+            return Ok(BroadcastOperation::from(operation));
+        }
+
+        /// Updates the value of the visible elements in `range`, using a single operation.
+        ///
+        /// # Parameters
+        /// - `range`: The range of indices into the result of `read()`.
+        /// - `value`: The new value of every element in the range.
+        pub fn local_update_range_at(
+            &mut self,
+            range: Range<usize>,
+            value: String,
+        ) -> Result<BroadcastOperation, OperationError> {
+            let (from, to) = self.element_range(range)?;
+            return self.local_update_range(from, to, value);
+        }
+
+        /// Moves an element to a new position, keeping its identity and value.
+        ///
+        /// If several replicas move the same element concurrently, the element ends up
+        /// at exactly one of the new positions on every replica. Concurrent updates and
+        /// deletes still apply to the element wherever it is moved to.
+        ///
+        /// # Parameters
+        /// - `s4vector`: The unique identifier of the element to move.
+        /// - `left`: The S4Vector of the new left neighbor (if any).
+        /// - `right`: The S4Vector of the new right neighbor (if any).
+        ///
+        /// # Returns
+        /// The operation to broadcast, or `OperationError::DependancyError` if the
+        /// element or either neighbour is unknown.
+        ///
+        /// # Example
+        /// ```rust
+        /// use crdt::rga::rga::RGA;
+        /// let mut rga = RGA::new(1, 1);
+        /// let a = rga.local_insert("A".to_string(), None, None).unwrap().s4vector;
+        /// let b = rga.local_insert("B".to_string(), Some(a), None).unwrap().s4vector;
+        ///
+        /// rga.local_move(a, Some(b), None).unwrap();
+        /// assert_eq!(rga.read(), vec!["B".to_string(), "A".to_string()]);
+        /// ```
+        pub fn local_move(
+            &mut self,
+            s4vector: S4Vector,
+            left: Option<S4Vector>,
+            right: Option<S4Vector>,
+        ) -> Result<BroadcastOperation, OperationError> {
+            let s4vector: S4Vector = self.identity_of(&s4vector);
+            let left: Option<S4Vector> = left.map(|left| self.position_of(&left));
+            let right: Option<S4Vector> = right.map(|right| self.position_of(&right));
+
+            let known: bool = [Some(s4vector), left, right]
+                .iter()
+                .flatten()
+                .all(|s4| self.contains(s4));
+            if !known {
+                return Err(OperationError::DependancyError);
+            }
+
+            // The new position must take precedence over the element's current position
+            let current: S4Vector = self.position_of(&s4vector);
+            let id: S4Vector = self.next_id(Some(&current));
+            if let Some(left) = left {
+                self.split_after(&left);
+            }
+
+            let operation: Operation = Operation {
+                operation: OperationType::Move,
+                s4vector,
+                value: None,
+                left,
+                right: self.successor(left),
+                id,
+            };
+            self.integrate(&operation);
+            self.apply_buffered_operations();
+
+            return Ok(BroadcastOperation::from(operation));
+        }
+
+        /// Moves the visible element at index `from` so it ends up at index `to`.
+        ///
+        /// # Parameters
+        /// - `from`: The index of the element in the result of `read()`.
+        /// - `to`: The index of the element after the move.
+        ///
+        /// # Returns
+        /// The operation to broadcast, or `OperationError::IndexError` if either index
+        /// is out of bounds.
+        pub fn local_move_at(
+            &mut self,
+            from: usize,
+            to: usize,
+        ) -> Result<BroadcastOperation, OperationError> {
+            let s4vector: S4Vector = self.element_at(from).ok_or(OperationError::IndexError)?;
+
+            // Indices after `from` shift down by one once the element is removed
+            let left: Option<S4Vector> = match to {
+                0 => None,
+                to if to > from => Some(self.element_at(to).ok_or(OperationError::IndexError)?),
+                to => Some(self.element_at(to - 1).ok_or(OperationError::IndexError)?),
+            };
+            return self.local_move(s4vector, left, None);
+        }
+
+        /// Applies an operation broadcast by another replica.
+        /// This operation updates the RGA to ensure eventual consistency
+        pub fn remote_apply(&mut self, op: BroadcastOperation) {
+            self.apply_remote_group(vec![Operation::from(op)]);
+        }
+
+        /// Remote operation to add a new element at a position based on a provided UID
+        /// This operation updates the RGA to ensure eventual consistency
+        pub fn remote_insert(
+            &mut self,
+            value: String,
+            s4vector: S4Vector,
+            left: Option<S4Vector>,
+            right: Option<S4Vector>,
+        ) {
+            self.apply_remote_group(vec![Operation {
+                operation: OperationType::Insert,
+                s4vector,
+                value: Some(value),
+                left,
+                right,
+                id: s4vector,
+            }]);
+        }
+
+        /// Remote operation to add a run of characters, one element per character,
+        /// with consecutive sequence numbers starting at `s4vector`.
+        /// This operation updates the RGA to ensure eventual consistency
+        pub fn remote_insert_run(
+            &mut self,
+            value: String,
+            s4vector: S4Vector,
+            left: Option<S4Vector>,
+            right: Option<S4Vector>,
+        ) {
+            self.apply_remote_group(vec![Operation {
+                operation: OperationType::InsertRun,
+                s4vector,
+                value: Some(value),
+                left,
+                right,
+                id: s4vector,
+            }]);
+        }
+
+        /// Remote operation to move an element to the position identified by `id`.
+        /// This operation updates the RGA to ensure eventual consistency
+        pub fn remote_move(
+            &mut self,
+            s4vector: S4Vector,
+            id: S4Vector,
+            left: Option<S4Vector>,
+            right: Option<S4Vector>,
+        ) {
+            self.apply_remote_group(vec![Operation {
+                operation: OperationType::Move,
+                s4vector,
+                value: None,
+                left,
+                right,
+                id,
+            }]);
+        }
+
+        /// Remote operation to remove an element given the UID and the UID of the
+        /// delete operation.
+        /// This operation updates the RGA to ensure eventual consistency
+        pub fn remote_delete(&mut self, s4vector: S4Vector, id: S4Vector) {
+            self.apply_remote_group(vec![Operation {
+                operation: OperationType::Delete,
+                s4vector,
+                value: None,
+                left: None,
+                right: None,
+                id,
+            }]);
+        }
+
+        /// Remote operation to update an element given the UID and the UID of the
+        /// update operation, concurrent updates are resolved by the latter.
+        /// This operation updates the RGA to ensure eventual consistency
+        pub fn remote_update(&mut self, s4vector: S4Vector, value: String, id: S4Vector) {
+            self.apply_remote_group(vec![Operation {
+                operation: OperationType::Update,
+                s4vector,
+                value: Some(value),
+                left: None,
+                right: None,
+                id,
+            }]);
+        }
+
+        /// Reads the current state of the RGA, skipping tombstoned nodes.
+        ///
+        /// # Returns
+        /// A vector of strings representing the current sequence.
+        pub fn read(&self) -> Vec<String> {
+            return self.iter().map(String::from).collect();
+        }
+
+        /// Applies every buffered operation whose dependencies have been met, repeating
+        /// until no more operations can be released.
+        pub fn apply_buffered_operations(&mut self) {
+            loop {
+                let mut released: bool = false;
+                let mut remaining: VecDeque<Vec<Operation>> = VecDeque::new();
+
+                while let Some(group) = self.buffer.pop_front() {
+                    if self.group_ready(&group) {
+                        for op in &group {
+                            self.integrate(op);
+                        }
+                        released = true;
+                    } else {
+                        remaining.push_back(group);
+                    }
+                }
+
+                self.buffer = remaining;
+                if !released {
+                    break;
+                }
+            }
+
+            self.observers.flush(&self.version);
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::test_support::{deliver, type_text};
+
+        #[test]
+        fn test_insert() {
+            let mut rga = RGA::new(1, 1);
+            let result = rga.local_insert("A".to_string(), None, None);
+            assert!(result.is_ok());
+            assert_eq!(rga.nodes.len(), 1);
+        }
+
+        #[test]
+        fn test_delete() {
+            let mut rga = RGA::new(1, 1);
+            let s4 = rga
+                .local_insert("A".to_string(), None, None)
                 .unwrap()
                 .s4vector;
-            let result = rga.local_delete(s4.clone());
+            let result = rga.local_delete(s4);
             assert!(result.is_ok());
-            assert!(rga.hash_map[&s4].borrow().tombstone);
+            assert!(rga.nodes[&s4].tombstone);
         }
 
         #[test]
@@ -549,9 +1778,9 @@ pub mod rga {
                 .local_insert("A".to_string(), None, None)
                 .unwrap()
                 .s4vector;
-            let result = rga.local_update(s4.clone(), "B".to_string());
+            let result = rga.local_update(s4, "B".to_string());
             assert!(result.is_ok());
-            assert_eq!(rga.hash_map[&s4].borrow().value, "B".to_string());
+            assert_eq!(rga.nodes[&s4].value, "B".to_string());
         }
 
         #[test]
@@ -565,5 +1794,517 @@ pub mod rga {
             let result = rga.read();
             assert_eq!(result, vec!["B".to_string()]);
         }
+
+        #[test]
+        fn test_insert_between_nodes() {
+            let mut rga = RGA::new(1, 1);
+            let a = rga.local_insert("A".to_string(), None, None).unwrap();
+            let b = rga
+                .local_insert("B".to_string(), Some(a.s4vector), None)
+                .unwrap();
+            let x = rga
+                .local_insert("X".to_string(), Some(a.s4vector), Some(b.s4vector))
+                .unwrap();
+            rga.local_insert("Y".to_string(), Some(x.s4vector), Some(b.s4vector))
+                .unwrap();
+            rga.local_insert("Z".to_string(), Some(a.s4vector), Some(x.s4vector))
+                .unwrap();
+
+            assert_eq!(rga.read(), vec!["A", "Z", "X", "Y", "B"]);
+        }
+
+        #[test]
+        fn test_concurrent_inserts_converge() {
+            let mut rga1 = RGA::new(1, 1);
+            let mut rga2 = RGA::new(1, 2);
+
+            let a = rga1.local_insert("A".to_string(), None, None).unwrap();
+            rga2.remote_insert("A".to_string(), a.s4vector, a.left, a.right);
+
+            let x = rga1
+                .local_insert("X".to_string(), Some(a.s4vector), None)
+                .unwrap();
+            let y = rga2
+                .local_insert("Y".to_string(), Some(a.s4vector), None)
+                .unwrap();
+
+            rga1.remote_insert("Y".to_string(), y.s4vector, y.left, y.right);
+            rga2.remote_insert("X".to_string(), x.s4vector, x.left, x.right);
+
+            assert_eq!(rga1.read(), rga2.read());
+            assert_eq!(rga1.read().len(), 3);
+        }
+
+        #[test]
+        fn test_out_of_order_operations_are_buffered() {
+            let mut rga1 = RGA::new(1, 1);
+            let mut rga2 = RGA::new(1, 2);
+
+            let a = rga1.local_insert("A".to_string(), None, None).unwrap();
+            let b = rga1
+                .local_insert("B".to_string(), Some(a.s4vector), None)
+                .unwrap();
+            let delete = rga1.local_delete(a.s4vector).unwrap();
+
+            rga2.remote_delete(delete.s4vector, delete.id);
+            rga2.remote_insert("B".to_string(), b.s4vector, b.left, b.right);
+            assert!(rga2.read().is_empty());
+
+            rga2.remote_insert("A".to_string(), a.s4vector, a.left, a.right);
+            assert_eq!(rga2.read(), vec!["B".to_string()]);
+            assert!(rga2.buffer.is_empty());
+        }
+
+        #[test]
+        fn test_local_operations_on_unknown_nodes_are_rejected() {
+            let mut rga1 = RGA::new(1, 1);
+            let mut rga2 = RGA::new(1, 2);
+            let operations = type_text(&mut rga1, None, "ab");
+            deliver(&mut rga2, &operations);
+
+            let unknown: S4Vector = operations[1].s4vector.offset(5);
+            assert!(rga1.local_delete(unknown).is_err());
+            assert!(rga1.local_update(unknown, "x".to_string()).is_err());
+            assert!(rga1
+                .local_insert("x".to_string(), Some(unknown), None)
+                .is_err());
+            assert_eq!(rga1.version().get(1), 2);
+            assert!(rga1.buffer.is_empty());
+
+            // The next operation must not claim the ids of the rejected ones
+            let range = rga1
+                .local_delete_range(operations[0].s4vector, operations[1].s4vector)
+                .unwrap();
+            assert_eq!(range.id.seq, 3);
+            deliver(&mut rga2, &[range]);
+            assert!(rga1.read().is_empty());
+            assert_eq!(rga1.read(), rga2.read());
+        }
+
+        #[test]
+        fn test_insert_before_right_neighbour() {
+            let mut rga1 = RGA::new(1, 1);
+            let mut rga2 = RGA::new(1, 2);
+            let a = rga1.local_insert("a".to_string(), None, None).unwrap();
+            let b = rga1
+                .local_insert("b".to_string(), Some(a.s4vector), None)
+                .unwrap();
+            let c = rga1
+                .local_insert("c".to_string(), Some(b.s4vector), None)
+                .unwrap();
+
+            // A right neighbour alone places the value directly before it
+            let x = rga1
+                .local_insert("x".to_string(), None, Some(b.s4vector))
+                .unwrap();
+            assert_eq!(rga1.read(), vec!["a", "x", "b", "c"]);
+
+            // Deleted elements may separate the neighbours, visible ones may not
+            let delete = rga1.local_delete(b.s4vector).unwrap();
+            let y = rga1
+                .local_insert("y".to_string(), Some(x.s4vector), Some(c.s4vector))
+                .unwrap();
+            assert_eq!(rga1.read(), vec!["a", "x", "y", "c"]);
+            assert!(matches!(
+                rga1.local_insert("z".to_string(), Some(c.s4vector), Some(a.s4vector)),
+                Err(OperationError::NeighbourError)
+            ));
+            assert!(matches!(
+                rga1.local_insert("z".to_string(), Some(a.s4vector), Some(c.s4vector)),
+                Err(OperationError::NeighbourError)
+            ));
+            assert_eq!(rga1.version().get(1), 6);
+
+            deliver(&mut rga2, &[a, b, c, x, delete, y]);
+            assert_eq!(rga1.read(), rga2.read());
+        }
+
+        #[test]
+        fn test_typing_forms_single_run() {
+            let mut rga1 = RGA::new(1, 1);
+            let mut rga2 = RGA::new(1, 2);
+
+            let operations = type_text(&mut rga1, None, "hello");
+            deliver(&mut rga2, &operations);
+
+            assert_eq!(rga1.nodes.len(), 1);
+            assert_eq!(rga2.nodes.len(), 1);
+            assert_eq!(rga2.read().concat(), "hello");
+        }
+
+        #[test]
+        fn test_insert_inside_run_splits() {
+            let mut rga1 = RGA::new(1, 1);
+            let mut rga2 = RGA::new(1, 2);
+
+            let operations = type_text(&mut rga1, None, "helo");
+            deliver(&mut rga2, &operations);
+
+            let l = type_text(&mut rga2, Some(operations[2].s4vector), "l");
+            deliver(&mut rga1, &l);
+
+            assert_eq!(rga1.read().concat(), "hello");
+            assert_eq!(rga2.read().concat(), "hello");
+            assert_eq!(rga1.nodes.len(), 3);
+        }
+
+        #[test]
+        fn test_concurrent_edits_inside_run_converge() {
+            let mut rga1 = RGA::new(1, 1);
+            let mut rga2 = RGA::new(1, 2);
+
+            let operations = type_text(&mut rga1, None, "abcd");
+            deliver(&mut rga2, &operations);
+
+            let delete = rga1.local_delete(operations[1].s4vector).unwrap();
+            let update = rga1
+                .local_update(operations[3].s4vector, "D".to_string())
+                .unwrap();
+            let x = type_text(&mut rga2, Some(operations[1].s4vector), "xy");
+            let z = type_text(&mut rga2, Some(operations[2].s4vector), "z");
+
+            deliver(&mut rga1, &x);
+            deliver(&mut rga1, &z);
+            deliver(&mut rga2, &[delete, update]);
+
+            assert_eq!(rga1.read(), rga2.read());
+            assert_eq!(rga1.read().concat(), "axyczD");
+        }
+
+        #[test]
+        fn test_continuing_a_run_after_another_insert() {
+            let mut rga = RGA::new(1, 1);
+            let ab = type_text(&mut rga, None, "ab");
+            type_text(&mut rga, None, "x");
+
+            // The run is no longer the last local insert, so "c" starts a new node
+            type_text(&mut rga, Some(ab[1].s4vector), "c");
+
+            assert_eq!(rga.read().concat(), "xabc");
+            assert_eq!(rga.nodes.len(), 3);
+        }
+
+        #[test]
+        fn test_concurrent_updates_converge() {
+            let mut rga1 = RGA::new(1, 1);
+            let mut rga2 = RGA::new(1, 2);
+
+            let operations = type_text(&mut rga1, None, "ab");
+            deliver(&mut rga2, &operations);
+
+            let x = rga1
+                .local_update(operations[0].s4vector, "x".to_string())
+                .unwrap();
+            let y = rga2
+                .local_update(operations[0].s4vector, "y".to_string())
+                .unwrap();
+            deliver(&mut rga1, &[y]);
+            deliver(&mut rga2, &[x]);
+
+            assert_eq!(rga1.read(), rga2.read());
+        }
+
+        #[test]
+        fn test_delete_range() {
+            let mut rga = RGA::new(1, 1);
+            let operations = type_text(&mut rga, None, "hello");
+
+            let delete = rga
+                .local_delete_range(operations[1].s4vector, operations[3].s4vector)
+                .unwrap();
+
+            assert!(matches!(
+                delete.operation,
+                OperationType::DeleteRange { .. }
+            ));
+            assert_eq!(rga.read().concat(), "ho");
+        }
+
+        #[test]
+        fn test_delete_range_at() {
+            let mut rga1 = RGA::new(1, 1);
+            let mut rga2 = RGA::new(1, 2);
+            let operations = type_text(&mut rga1, None, "hello world");
+            deliver(&mut rga2, &operations);
+
+            let delete = rga1.local_delete_range_at(5..11).unwrap();
+            deliver(&mut rga2, &[delete]);
+
+            assert_eq!(rga1.read().concat(), "hello");
+            assert_eq!(rga2.read().concat(), "hello");
+            assert!(matches!(
+                rga1.local_delete_range_at(3..9),
+                Err(OperationError::IndexError)
+            ));
+        }
+
+        #[test]
+        fn test_delete_range_keeps_concurrent_inserts() {
+            let mut rga1 = RGA::new(1, 1);
+            let mut rga2 = RGA::new(1, 2);
+            let operations = type_text(&mut rga1, None, "abcd");
+            deliver(&mut rga2, &operations);
+
+            let delete = rga1
+                .local_delete_range(operations[0].s4vector, operations[3].s4vector)
+                .unwrap();
+            let x = type_text(&mut rga2, Some(operations[1].s4vector), "x");
+
+            deliver(&mut rga1, &x);
+            deliver(&mut rga2, &[delete]);
+
+            assert_eq!(rga1.read().concat(), "x");
+            assert_eq!(rga2.read().concat(), "x");
+        }
+
+        #[test]
+        fn test_delete_range_waits_for_its_version() {
+            let mut rga1 = RGA::new(1, 1);
+            let mut rga2 = RGA::new(1, 2);
+            let mut rga3 = RGA::new(1, 3);
+            let operations = type_text(&mut rga1, None, "ab");
+            deliver(&mut rga2, &operations);
+            deliver(&mut rga3, &operations);
+
+            // rga2 inserts inside the range, rga1 deletes the range after seeing it
+            let x = type_text(&mut rga2, Some(operations[0].s4vector), "x");
+            deliver(&mut rga1, &x);
+            let delete = rga1
+                .local_delete_range(operations[0].s4vector, operations[1].s4vector)
+                .unwrap();
+
+            deliver(&mut rga3, std::slice::from_ref(&delete));
+            assert_eq!(rga3.read().concat(), "ab");
+
+            deliver(&mut rga3, &x);
+            assert!(rga3.read().is_empty());
+            assert_eq!(rga1.read(), rga3.read());
+        }
+
+        #[test]
+        fn test_update_range() {
+            let mut rga1 = RGA::new(1, 1);
+            let mut rga2 = RGA::new(1, 2);
+            let operations = type_text(&mut rga1, None, "abcd");
+            deliver(&mut rga2, &operations);
+
+            let update = rga1.local_update_range_at(1..3, "*".to_string()).unwrap();
+            deliver(&mut rga2, &[update]);
+
+            assert_eq!(rga1.read().concat(), "a**d");
+            assert_eq!(rga2.read().concat(), "a**d");
+        }
+
+        #[test]
+        fn test_update_range_concurrent_with_update_converges() {
+            let mut rga1 = RGA::new(1, 1);
+            let mut rga2 = RGA::new(1, 2);
+            let operations = type_text(&mut rga1, None, "abcd");
+            deliver(&mut rga2, &operations);
+
+            let range = rga1
+                .local_update_range(
+                    operations[0].s4vector,
+                    operations[3].s4vector,
+                    "-".to_string(),
+                )
+                .unwrap();
+            let update = rga2
+                .local_update(operations[2].s4vector, "C".to_string())
+                .unwrap();
+
+            deliver(&mut rga1, &[update]);
+            deliver(&mut rga2, &[range]);
+
+            assert_eq!(rga1.read(), rga2.read());
+            assert_eq!(rga1.read()[..2].concat(), "--");
+        }
+
+        #[test]
+        fn test_move() {
+            let mut rga1 = RGA::new(1, 1);
+            let mut rga2 = RGA::new(1, 2);
+            let operations = type_text(&mut rga1, None, "abcd");
+            deliver(&mut rga2, &operations);
+
+            let moved = rga1.local_move_at(0, 2).unwrap();
+            assert!(matches!(moved.operation, OperationType::Move));
+            assert_eq!(rga1.read().concat(), "bcad");
+
+            let moved_back = rga1.local_move_at(2, 0).unwrap();
+            assert_eq!(rga1.read().concat(), "abcd");
+
+            deliver(&mut rga2, &[moved, moved_back]);
+            assert_eq!(rga2.read().concat(), "abcd");
+        }
+
+        #[test]
+        fn test_concurrent_moves_do_not_duplicate() {
+            let mut rga1 = RGA::new(1, 1);
+            let mut rga2 = RGA::new(1, 2);
+            let operations = type_text(&mut rga1, None, "abcd");
+            deliver(&mut rga2, &operations);
+
+            let a = operations[0].s4vector;
+            let move1 = rga1
+                .local_move(a, Some(operations[1].s4vector), None)
+                .unwrap();
+            let move2 = rga2
+                .local_move(a, Some(operations[3].s4vector), None)
+                .unwrap();
+            deliver(&mut rga1, &[move2]);
+            deliver(&mut rga2, &[move1]);
+
+            assert_eq!(rga1.read(), rga2.read());
+            assert_eq!(rga1.read().len(), 4);
+            assert_eq!(rga1.read().iter().filter(|c| *c == "a").count(), 1);
+        }
+
+        #[test]
+        fn test_move_keeps_concurrent_update() {
+            let mut rga1 = RGA::new(1, 1);
+            let mut rga2 = RGA::new(1, 2);
+            let operations = type_text(&mut rga1, None, "abc");
+            deliver(&mut rga2, &operations);
+
+            let moved = rga1.local_move_at(0, 2).unwrap();
+            let update = rga2
+                .local_update(operations[0].s4vector, "A".to_string())
+                .unwrap();
+            deliver(&mut rga1, &[update]);
+            deliver(&mut rga2, &[moved]);
+
+            assert_eq!(rga1.read().concat(), "bcA");
+            assert_eq!(rga2.read().concat(), "bcA");
+
+            // The moved element keeps its identity
+            rga2.local_delete(operations[0].s4vector).unwrap();
+            assert_eq!(rga2.read().concat(), "bc");
+        }
+
+        #[test]
+        fn test_move_concurrent_with_delete_range_converges() {
+            let mut rga1 = RGA::new(1, 1);
+            let mut rga2 = RGA::new(1, 2);
+            let operations = type_text(&mut rga1, None, "abcd");
+            deliver(&mut rga2, &operations);
+
+            // "b" is moved out of the range concurrently with the range being deleted
+            let moved = rga1.local_move_at(1, 3).unwrap();
+            let delete = rga2.local_delete_range_at(0..2).unwrap();
+            deliver(&mut rga1, &[delete]);
+            deliver(&mut rga2, &[moved]);
+
+            assert_eq!(rga1.read(), rga2.read());
+            assert_eq!(rga1.read().concat(), "cd");
+        }
+
+        fn type_backward(
+            rga: &mut RGA,
+            left: Option<S4Vector>,
+            right: Option<S4Vector>,
+            text: &str,
+        ) -> Vec<BroadcastOperation> {
+            let mut right: Option<S4Vector> = right;
+            let mut operations: Vec<BroadcastOperation> = Vec::new();
+
+            for c in text.chars().rev() {
+                let op = rga.local_insert(c.to_string(), left, right).unwrap();
+                right = Some(op.s4vector);
+                operations.push(op);
+            }
+            return operations;
+        }
+
+        /// Reproduces the example from Kleppmann et al., "Interleaving anomalies in
+        /// collaborative text editors": two users concurrently type a name between
+        /// "Hello" and "!", delivered to each other one character at a time.
+        fn assert_no_interleaving(backward: [bool; 2]) {
+            for site_ids in [(1, 2), (2, 1)] {
+                let mut rga1 = RGA::new(1, site_ids.0);
+                let mut rga2 = RGA::new(1, site_ids.1);
+                let hello = type_text(&mut rga1, None, "Hello!");
+                deliver(&mut rga2, &hello);
+
+                let (left, right) = (Some(hello[4].s4vector), Some(hello[5].s4vector));
+                let alice = match backward[0] {
+                    true => type_backward(&mut rga1, left, right, " Alice"),
+                    false => type_text(&mut rga1, left, " Alice"),
+                };
+                let charlie = match backward[1] {
+                    true => type_backward(&mut rga2, left, right, " Charlie"),
+                    false => type_text(&mut rga2, left, " Charlie"),
+                };
+
+                // Interleave the deliveries of both users' characters
+                for (a, c) in alice.iter().zip(&charlie) {
+                    deliver(&mut rga2, std::slice::from_ref(a));
+                    deliver(&mut rga1, std::slice::from_ref(c));
+                }
+                deliver(&mut rga1, &charlie[alice.len()..]);
+
+                let result: String = rga1.read().concat();
+                assert_eq!(result, rga2.read().concat());
+                assert!(
+                    result == "Hello Alice Charlie!" || result == "Hello Charlie Alice!",
+                    "interleaved: {result}"
+                );
+            }
+        }
+
+        #[test]
+        fn test_concurrent_typing_does_not_interleave() {
+            assert_no_interleaving([false, false]);
+        }
+
+        #[test]
+        fn test_concurrent_backward_typing_does_not_interleave() {
+            assert_no_interleaving([true, true]);
+            assert_no_interleaving([true, false]);
+            assert_no_interleaving([false, true]);
+        }
+
+        /// Inserts one element per word. Unlike characters, words are not merged
+        /// into runs, so every element is integrated on its own.
+        fn type_words(
+            rga: &mut RGA,
+            left: Option<S4Vector>,
+            words: &[&str],
+        ) -> Vec<BroadcastOperation> {
+            let mut left: Option<S4Vector> = left;
+            let mut operations: Vec<BroadcastOperation> = Vec::new();
+
+            for word in words {
+                let op = rga.local_insert(word.to_string(), left, None).unwrap();
+                left = Some(op.s4vector);
+                operations.push(op);
+            }
+            return operations;
+        }
+
+        #[test]
+        fn test_concurrent_word_typing_does_not_interleave() {
+            for site_ids in [(1, 2), (2, 1)] {
+                let mut rga1 = RGA::new(1, site_ids.0);
+                let mut rga2 = RGA::new(1, site_ids.1);
+                let hello = type_text(&mut rga1, None, "Hello!");
+                deliver(&mut rga2, &hello);
+
+                let left: Option<S4Vector> = Some(hello[4].s4vector);
+                let alice = type_words(&mut rga1, left, &[" Al", "ic", "e"]);
+                let charlie = type_words(&mut rga2, left, &[" Ch", "ar", "lie"]);
+                for (a, c) in alice.iter().zip(&charlie) {
+                    deliver(&mut rga2, std::slice::from_ref(a));
+                    deliver(&mut rga1, std::slice::from_ref(c));
+                }
+
+                let result: String = rga1.read().concat();
+                assert_eq!(result, rga2.read().concat());
+                assert!(
+                    result == "Hello Alice Charlie!" || result == "Hello Charlie Alice!",
+                    "interleaved: {result}"
+                );
+            }
+        }
     }
 }
@@ -0,0 +1,451 @@
+use crate::rga::rga::{BroadcastOperation, OperationError};
+use crate::{OperationBatch, S4Vector, Text};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::ops::Range;
+
+/// Whether text inserted at the edges of a mark is formatted by it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Expand {
+    /// Text inserted at either edge is not formatted, e.g. for links.
+    None,
+    /// Text inserted directly before the mark is formatted.
+    Before,
+    /// Text inserted directly after the mark is formatted, e.g. for bold.
+    After,
+    /// Text inserted at either edge is formatted.
+    Both,
+}
+
+/// A boundary of a mark, anchored to an element of the underlying `RGA` so it keeps
+/// its place when text is inserted or deleted around it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Anchor {
+    /// The start of the text.
+    Start,
+    /// Directly before the element.
+    Before(S4Vector),
+    /// Directly after the element.
+    After(S4Vector),
+    /// The end of the text.
+    End,
+}
+
+impl Anchor {
+    /// Returns the element the anchor refers to, if any.
+    fn element(&self) -> Option<S4Vector> {
+        return match self {
+            Anchor::Before(s4vector) | Anchor::After(s4vector) => Some(*s4vector),
+            Anchor::Start | Anchor::End => None,
+        };
+    }
+}
+
+/// An operation adding or removing formatting on a range of text.
+///
+/// Concurrent marks with the same name are resolved by their `id`: where they overlap
+/// the mark with the greatest `id` wins, so every replica formats the text the same way.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct MarkOperation {
+    /// The unique identifier of the operation, the `sum` is a Lamport timestamp.
+    pub id: S4Vector,
+    /// The name of the formatting, e.g. `bold` or `link`.
+    pub name: String,
+    /// The value of the formatting, or `None` to remove it.
+    pub value: Option<String>,
+    /// The boundary the formatting starts at.
+    pub start: Anchor,
+    /// The boundary the formatting ends at.
+    pub end: Anchor,
+}
+
+/// A run of text with the same formatting.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Span {
+    /// The text of the span.
+    pub text: String,
+    /// The formatting of the span, by name.
+    pub marks: BTreeMap<String, String>,
+}
+
+/// `RichText` is a collaborative `Text` with formatting marks in the style of Peritext.
+///
+/// # Example
+/// ```
+/// use crdt::{Expand, RichText};
+///
+/// let mut text = RichText::new(1, 1);
+/// text.insert_str(0, "hello world").unwrap();
+/// text.mark(0..5, "bold", "true", Expand::After).unwrap();
+///
+/// // Bold expands to text typed at its end
+/// text.insert_str(5, "!").unwrap();
+///
+/// let spans = text.spans();
+/// assert_eq!(spans[0].text, "hello!");
+/// assert_eq!(spans[0].marks["bold"], "true");
+/// assert_eq!(spans[1].text, " world");
+/// ```
+#[derive(Debug)]
+pub struct RichText {
+    text: Text,
+    marks: BTreeMap<S4Vector, MarkOperation>,
+    /// Marks whose anchors have not been received yet.
+    pending: Vec<MarkOperation>,
+    /// The Lamport clock for mark operations.
+    clock: u64,
+    /// The number of mark operations created locally.
+    sequence: u64,
+}
+
+impl RichText {
+    /// Creates an empty `RichText`.
+    ///
+    /// # Parameters
+    /// - `session_id`: The current session ID.
+    /// - `site_id`: The site ID for this replica.
+    pub fn new(session_id: u64, site_id: u64) -> Self {
+        return RichText {
+            text: Text::new(session_id, site_id),
+            marks: BTreeMap::new(),
+            pending: Vec::new(),
+            clock: 0,
+            sequence: 0,
+        };
+    }
+
+    /// Returns the plain text.
+    pub fn text(&self) -> &Text {
+        return &self.text;
+    }
+
+    /// Inserts `value` at the character position `pos`, see `Text::insert_str`.
+    pub fn insert_str(
+        &mut self,
+        pos: usize,
+        value: &str,
+    ) -> Result<OperationBatch, OperationError> {
+        return self.text.insert_str(pos, value);
+    }
+
+    /// Deletes the characters in `range`, see `Text::delete`.
+    pub fn delete(&mut self, range: Range<usize>) -> Result<BroadcastOperation, OperationError> {
+        return self.text.delete(range);
+    }
+
+    /// Applies a text operation broadcast by another replica, see `Text::apply`.
+    pub fn apply(&mut self, operation: BroadcastOperation) -> Result<(), OperationError> {
+        self.text.apply(operation)?;
+        self.apply_pending_marks();
+        return Ok(());
+    }
+
+    /// Applies a batch of text operations broadcast by another replica, see
+    /// `Text::apply_batch`.
+    pub fn apply_batch(&mut self, batch: OperationBatch) -> Result<(), OperationError> {
+        self.text.apply_batch(batch)?;
+        self.apply_pending_marks();
+        return Ok(());
+    }
+
+    /// Formats the characters in `range`.
+    ///
+    /// # Parameters
+    /// - `range`: The range of character positions to format.
+    /// - `name`: The name of the formatting, e.g. `bold`.
+    /// - `value`: The value of the formatting, e.g. `true` or a link target.
+    /// - `expand`: Whether text later inserted at the edges is formatted too.
+    ///
+    /// # Returns
+    /// The operation to broadcast, or `OperationError::IndexError` if the range is
+    /// empty or out of bounds.
+    pub fn mark(
+        &mut self,
+        range: Range<usize>,
+        name: &str,
+        value: &str,
+        expand: Expand,
+    ) -> Result<MarkOperation, OperationError> {
+        return self.local_mark(range, name, Some(value.to_string()), expand);
+    }
+
+    /// Removes formatting from the characters in `range`.
+    ///
+    /// # Parameters
+    /// - `range`: The range of character positions to clear.
+    /// - `name`: The name of the formatting to remove.
+    /// - `expand`: Whether text later inserted at the edges is cleared too.
+    pub fn unmark(
+        &mut self,
+        range: Range<usize>,
+        name: &str,
+        expand: Expand,
+    ) -> Result<MarkOperation, OperationError> {
+        return self.local_mark(range, name, None, expand);
+    }
+
+    fn local_mark(
+        &mut self,
+        range: Range<usize>,
+        name: &str,
+        value: Option<String>,
+        expand: Expand,
+    ) -> Result<MarkOperation, OperationError> {
+        let len: usize = self.text.len_chars();
+        if range.is_empty() || range.end > len {
+            return Err(OperationError::IndexError);
+        }
+        let element = |index: usize| -> S4Vector {
+            return self.text.rga().iter_with_ids().nth(index).unwrap().0;
+        };
+
+        let start: Anchor = match expand {
+            Expand::Before | Expand::Both if range.start == 0 => Anchor::Start,
+            Expand::Before | Expand::Both => Anchor::After(element(range.start - 1)),
+            Expand::None | Expand::After => Anchor::Before(element(range.start)),
+        };
+        let end: Anchor = match expand {
+            Expand::After | Expand::Both if range.end == len => Anchor::End,
+            Expand::After | Expand::Both => Anchor::Before(element(range.end)),
+            Expand::None | Expand::Before => Anchor::After(element(range.end - 1)),
+        };
+
+        self.clock += 1;
+        self.sequence += 1;
+        let operation: MarkOperation = MarkOperation {
+            id: S4Vector {
+                ssn: self.text.rga().session_id(),
+                sum: self.clock,
+                sid: self.text.rga().site_id(),
+                seq: self.sequence,
+            },
+            name: name.to_string(),
+            value,
+            start,
+            end,
+        };
+
+        self.marks.insert(operation.id, operation.clone());
+        return Ok(operation);
+    }
+
+    /// Applies a mark broadcast by another replica, buffering it until the text it
+    /// is anchored to has been received.
+    pub fn apply_mark(&mut self, operation: MarkOperation) {
+        self.clock = self.clock.max(operation.id.sum);
+        self.pending.push(operation);
+        self.apply_pending_marks();
+    }
+
+    fn apply_pending_marks(&mut self) {
+        let pending: Vec<MarkOperation> = std::mem::take(&mut self.pending);
+        for operation in pending {
+            let known: bool = [operation.start, operation.end]
+                .iter()
+                .filter_map(Anchor::element)
+                .all(|s4vector| self.text.rga().contains(&s4vector));
+
+            if known {
+                self.marks.insert(operation.id, operation);
+            } else {
+                self.pending.push(operation);
+            }
+        }
+    }
+
+    /// Returns the text split into runs with the same formatting.
+    pub fn spans(&self) -> Vec<Span> {
+        let mut starts: HashMap<Anchor, Vec<S4Vector>> = HashMap::new();
+        let mut ends: HashMap<Anchor, Vec<S4Vector>> = HashMap::new();
+        for operation in self.marks.values() {
+            starts
+                .entry(operation.start)
+                .or_default()
+                .push(operation.id);
+            ends.entry(operation.end).or_default().push(operation.id);
+        }
+
+        let mut active: BTreeSet<S4Vector> = BTreeSet::new();
+        let mut spans: Vec<Span> = Vec::new();
+        let update = |active: &mut BTreeSet<S4Vector>, anchor: Anchor| {
+            active.extend(starts.get(&anchor).into_iter().flatten());
+            for id in ends.get(&anchor).into_iter().flatten() {
+                active.remove(id);
+            }
+        };
+
+        update(&mut active, Anchor::Start);
+        for (s4vector, value, visible) in self.text.rga().iter_including_tombstones() {
+            update(&mut active, Anchor::Before(s4vector));
+
+            if visible {
+                let marks: BTreeMap<String, String> = self.formatting(&active);
+                match spans.last_mut().filter(|span| span.marks == marks) {
+                    Some(span) => span.text.push_str(value),
+                    None => spans.push(Span {
+                        text: value.to_string(),
+                        marks,
+                    }),
+                }
+            }
+
+            update(&mut active, Anchor::After(s4vector));
+        }
+        return spans;
+    }
+
+    /// Resolves the active marks to the formatting they apply, the mark with the
+    /// greatest `id` winning for each name.
+    fn formatting(&self, active: &BTreeSet<S4Vector>) -> BTreeMap<String, String> {
+        let mut formatting: BTreeMap<&str, Option<&String>> = BTreeMap::new();
+        for id in active {
+            let operation: &MarkOperation = &self.marks[id];
+            formatting.insert(&operation.name, operation.value.as_ref());
+        }
+
+        return formatting
+            .into_iter()
+            .filter_map(|(name, value)| Some((name.to_string(), value?.clone())))
+            .collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spans(text: &RichText) -> Vec<(String, Vec<(String, String)>)> {
+        return text
+            .spans()
+            .into_iter()
+            .map(|span| (span.text, span.marks.into_iter().collect()))
+            .collect();
+    }
+
+    fn mark(name: &str, value: &str) -> Vec<(String, String)> {
+        return vec![(name.to_string(), value.to_string())];
+    }
+
+    #[test]
+    fn test_expand_semantics() {
+        let mut text = RichText::new(1, 1);
+        text.insert_str(0, "ab cd").unwrap();
+        text.mark(0..2, "bold", "true", Expand::After).unwrap();
+        text.mark(3..5, "link", "x.com", Expand::None).unwrap();
+
+        text.insert_str(5, "!").unwrap();
+        text.insert_str(3, "[").unwrap();
+        text.insert_str(2, "c").unwrap();
+        text.insert_str(0, "_").unwrap();
+
+        assert_eq!(
+            spans(&text),
+            vec![
+                ("_".to_string(), vec![]),
+                ("abc".to_string(), mark("bold", "true")),
+                (" [".to_string(), vec![]),
+                ("cd".to_string(), mark("link", "x.com")),
+                ("!".to_string(), vec![]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unmark() {
+        let mut text = RichText::new(1, 1);
+        text.insert_str(0, "abcd").unwrap();
+        text.mark(0..4, "italic", "true", Expand::After).unwrap();
+        text.unmark(1..3, "italic", Expand::None).unwrap();
+
+        assert_eq!(
+            spans(&text),
+            vec![
+                ("a".to_string(), mark("italic", "true")),
+                ("bc".to_string(), vec![]),
+                ("d".to_string(), mark("italic", "true")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_concurrent_marks_converge() {
+        let mut text1 = RichText::new(1, 1);
+        let mut text2 = RichText::new(1, 2);
+        text2
+            .apply_batch(text1.insert_str(0, "abcdef").unwrap())
+            .unwrap();
+
+        let bold = text1.mark(0..4, "bold", "true", Expand::After).unwrap();
+        let link1 = text1.mark(2..6, "link", "a.com", Expand::None).unwrap();
+        let link2 = text2.mark(0..3, "link", "b.com", Expand::None).unwrap();
+        let unbold = text2.unmark(3..6, "bold", Expand::After).unwrap();
+
+        for operation in [link2, unbold] {
+            text1.apply_mark(operation);
+        }
+        for operation in [bold, link1] {
+            text2.apply_mark(operation);
+        }
+
+        assert_eq!(text1.spans(), text2.spans());
+        assert_eq!(
+            text1
+                .spans()
+                .iter()
+                .map(|span| span.text.as_str())
+                .collect::<String>(),
+            "abcdef"
+        );
+    }
+
+    #[test]
+    fn test_mark_waits_for_its_text() {
+        let mut text1 = RichText::new(1, 1);
+        let mut text2 = RichText::new(1, 2);
+
+        let batch = text1.insert_str(0, "abc").unwrap();
+        let bold = text1.mark(1..2, "bold", "true", Expand::None).unwrap();
+
+        text2.apply_mark(bold);
+        text2.apply_batch(batch).unwrap();
+
+        assert_eq!(text1.spans(), text2.spans());
+        assert_eq!(text2.spans()[1].text, "b");
+    }
+
+    #[test]
+    fn test_marks_survive_deleted_boundaries() {
+        let mut text = RichText::new(1, 1);
+        text.insert_str(0, "abcd").unwrap();
+        text.mark(1..3, "bold", "true", Expand::After).unwrap();
+        text.delete(2..4).unwrap();
+        text.insert_str(2, "x").unwrap();
+
+        assert_eq!(
+            spans(&text),
+            vec![
+                ("a".to_string(), vec![]),
+                ("bx".to_string(), mark("bold", "true")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_marks_round_trip_through_json() {
+        let mut text1 = RichText::new(1, 1);
+        let mut text2 = RichText::new(1, 2);
+        let batch: OperationBatch = text1.insert_str(0, "abcd").unwrap();
+        let bold: MarkOperation = text1.mark(0..2, "bold", "true", Expand::After).unwrap();
+        let link: MarkOperation = text1.mark(2..4, "link", "x.com", Expand::None).unwrap();
+        let unbold: MarkOperation = text1.unmark(1..2, "bold", Expand::Both).unwrap();
+
+        text2.apply_batch(batch).unwrap();
+        for operation in [bold, link, unbold] {
+            let json: String = serde_json::to_string(&operation).unwrap();
+            let restored: MarkOperation = serde_json::from_str(&json).unwrap();
+            assert_eq!(restored, operation);
+            text2.apply_mark(restored);
+        }
+        assert_eq!(spans(&text2), spans(&text1));
+    }
+}
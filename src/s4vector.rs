@@ -28,7 +28,7 @@
 ///
 /// assert!(s4_1 < s4_2); // Demonstrates correct ordering
 /// ```
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize)]
 pub struct S4Vector {
     /// Session ID, ensuring global uniqueness of operations within a session.
     pub ssn: u64,
@@ -134,6 +134,23 @@ impl S4Vector {
             seq: *local_sequence,
         };
     }
+
+    /// Returns the `S4Vector` of the element `n` positions after this one in a run of
+    /// consecutive inserts from the same site.
+    ///
+    /// # Examples
+    /// ```
+    /// use crdt::S4Vector;
+    /// let s4 = S4Vector { ssn: 1, sum: 10, sid: 42, seq: 3 };
+    /// assert_eq!(s4.offset(2).seq, 5);
+    /// assert!(s4 < s4.offset(1));
+    /// ```
+    pub fn offset(&self, n: u64) -> Self {
+        return S4Vector {
+            seq: self.seq + n,
+            ..*self
+        };
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,430 @@
+use crate::protocol::{Channel, Connection, Message, SyncError};
+use crate::rga::rga::RGA;
+use crate::{OperationBatch, S4Vector, VersionVector};
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream, ToSocketAddrs};
+use tokio::sync::broadcast;
+
+/// The number of batches kept for clients that fall behind the live stream. A client
+/// further behind is disconnected and catches up from the log when it reconnects.
+const LIVE_CAPACITY: usize = 1024;
+
+/// How long the server waits after failing to accept a connection, for instance when
+/// it has run out of file descriptors, before accepting again.
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+
+/// The server's copy of a document.
+#[derive(Debug)]
+struct Document {
+    /// Every batch received for the document, in the order the server received them.
+    log: Vec<OperationBatch>,
+    /// The ids of the operations in `log`, used to drop batches received twice.
+    seen: HashSet<S4Vector>,
+    rga: RGA,
+    /// The site IDs of the clients currently connected.
+    sites: HashSet<u64>,
+    /// Relays every new batch, together with the site ID of its sender.
+    live: broadcast::Sender<(u64, OperationBatch)>,
+}
+
+impl Document {
+    fn new() -> Self {
+        return Document {
+            log: Vec::new(),
+            seen: HashSet::new(),
+            // The server never edits, so site ID 0 is never used by an operation
+            rga: RGA::new(0, 0),
+            sites: HashSet::new(),
+            live: broadcast::channel(LIVE_CAPACITY).0,
+        };
+    }
+
+    /// Returns the batches containing operations not covered by `version`.
+    fn missing(&self, version: &VersionVector) -> Vec<OperationBatch> {
+        return self
+            .log
+            .iter()
+            .filter(|batch| batch.operations.iter().any(|op| !version.contains(&op.id)))
+            .cloned()
+            .collect();
+    }
+
+    /// Appends a batch to the log and relays it to the other clients.
+    ///
+    /// # Returns
+    /// `false` if every operation of the batch was already in the log.
+    fn append(&mut self, site_id: u64, batch: OperationBatch) -> bool {
+        let mut new: bool = false;
+        for op in &batch.operations {
+            new |= self.seen.insert(op.id);
+        }
+        if !new {
+            return false;
+        }
+
+        self.rga.remote_apply_batch(batch.clone());
+        self.log.push(batch.clone());
+        // No client may be listening
+        let _ = self.live.send((site_id, batch));
+        return true;
+    }
+}
+
+type Documents = Arc<Mutex<HashMap<String, Document>>>;
+
+/// `SyncServer` relays operations between the clients editing a document and holds
+/// the authoritative log of every document.
+///
+/// Clients connect over TCP, or over WebSocket once `listen_websocket` has been
+/// called, and speak the JSON protocol described by `Message`. A client is
+/// identified by the document it edits and its site ID, and only one connection per
+/// site ID is accepted for a document, since two replicas sharing a site ID would
+/// generate conflicting operation ids. Documents are created when their first
+/// client connects and are kept in memory.
+///
+/// # Example
+/// ```
+/// use crdt::rga::rga::RGA;
+/// use crdt::{SyncClient, SyncServer};
+///
+/// # tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap().block_on(async {
+/// let server = SyncServer::bind("127.0.0.1:0").await.unwrap();
+/// let addr = server.local_addr().unwrap();
+/// tokio::spawn(server.clone().run());
+///
+/// let mut alice = SyncClient::connect(addr, "notes", RGA::new(1, 1)).await.unwrap();
+/// let mut bob = SyncClient::connect(addr, "notes", RGA::new(1, 2)).await.unwrap();
+///
+/// let op = alice.rga_mut().local_insert("a".to_string(), None, None).unwrap();
+/// alice.send(op.clone().into()).await.unwrap();
+/// bob.wait_for(&op).await.unwrap();
+///
+/// assert_eq!(bob.rga().read(), vec!["a".to_string()]);
+/// assert_eq!(server.read("notes"), Some(vec!["a".to_string()]));
+/// # });
+/// ```
+#[derive(Debug, Clone)]
+pub struct SyncServer {
+    listener: Arc<TcpListener>,
+    websocket: Option<Arc<TcpListener>>,
+    documents: Documents,
+}
+
+impl SyncServer {
+    /// Binds the server to `addr` without accepting connections yet.
+    pub async fn bind(addr: impl ToSocketAddrs) -> Result<Self, SyncError> {
+        return Ok(SyncServer {
+            listener: Arc::new(TcpListener::bind(addr).await?),
+            websocket: None,
+            documents: Arc::new(Mutex::new(HashMap::new())),
+        });
+    }
+
+    /// Returns the address the server is listening on.
+    pub fn local_addr(&self) -> Result<SocketAddr, SyncError> {
+        return Ok(self.listener.local_addr()?);
+    }
+
+    /// Also accepts WebSocket connections on `addr`, for clients such as browsers that
+    /// cannot open raw TCP connections. Each text frame carries one JSON `Message`.
+    pub async fn listen_websocket(mut self, addr: impl ToSocketAddrs) -> Result<Self, SyncError> {
+        self.websocket = Some(Arc::new(TcpListener::bind(addr).await?));
+        return Ok(self);
+    }
+
+    /// Returns the address the WebSocket endpoint is listening on, if any.
+    pub fn websocket_addr(&self) -> Option<Result<SocketAddr, SyncError>> {
+        return self
+            .websocket
+            .as_ref()
+            .map(|listener| Ok(listener.local_addr()?));
+    }
+
+    /// Accepts connections forever, serving each client in its own task.
+    ///
+    /// A connection that fails to be accepted is logged and skipped after a short
+    /// pause, so it never stops the server for the other clients.
+    pub async fn run(self) {
+        loop {
+            let documents: Documents = self.documents.clone();
+            tokio::select! {
+                accepted = self.listener.accept() => match accepted {
+                    Ok((stream, _)) => {
+                        tokio::spawn(async move {
+                            // A failing client only ends its own session
+                            let _ = Self::serve(documents, Connection::new(stream)).await;
+                        });
+                    }
+                    Err(error) => Self::backoff(error).await,
+                },
+                accepted = accept(self.websocket.as_deref()) => match accepted {
+                    Ok((stream, _)) => {
+                        tokio::spawn(async move {
+                            if let Ok(websocket) = tokio_tungstenite::accept_async(stream).await {
+                                let _ = Self::serve(documents, websocket).await;
+                            }
+                        });
+                    }
+                    Err(error) => Self::backoff(error).await,
+                },
+            }
+        }
+    }
+
+    /// Logs a failed `accept` and waits before accepting again, since errors such as
+    /// running out of file descriptors would otherwise repeat immediately.
+    async fn backoff(error: std::io::Error) {
+        eprintln!("Failed to accept a connection: {error}");
+        tokio::time::sleep(ACCEPT_BACKOFF).await;
+    }
+
+    /// Reads the server's copy of a document.
+    ///
+    /// # Returns
+    /// `None` if no client has connected to the document.
+    pub fn read(&self, document: &str) -> Option<Vec<String>> {
+        return lock(&self.documents)
+            .get(document)
+            .map(|document| document.rga.read());
+    }
+
+    /// Returns the log of a document, every batch in the order it was received.
+    pub fn log(&self, document: &str) -> Vec<OperationBatch> {
+        return lock(&self.documents)
+            .get(document)
+            .map(|document| document.log.clone())
+            .unwrap_or_default();
+    }
+
+    async fn serve<C: Channel>(documents: Documents, mut connection: C) -> Result<(), SyncError> {
+        let (name, site_id, version) = match connection.receive().await? {
+            Some(Message::Hello {
+                document,
+                site_id,
+                version,
+            }) => (document, site_id, version),
+            Some(_) => return Err(SyncError::UnexpectedMessage),
+            None => return Err(SyncError::Disconnected),
+        };
+
+        let joined: Result<(VersionVector, Vec<OperationBatch>, broadcast::Receiver<_>), String> = {
+            let mut documents: MutexGuard<'_, HashMap<String, Document>> = lock(&documents);
+            let document: &mut Document =
+                documents.entry(name.clone()).or_insert_with(Document::new);
+
+            if site_id == 0 {
+                Err("site ID 0 is reserved for the server".to_string())
+            } else if !document.sites.insert(site_id) {
+                Err(format!("site {site_id} is already connected"))
+            } else {
+                // Subscribing while holding the lock ensures no batch falls between
+                // the missing batches and the live stream
+                Ok((
+                    document.rga.version().clone(),
+                    document.missing(&version),
+                    document.live.subscribe(),
+                ))
+            }
+        };
+        let (server_version, missing, mut live) = match joined {
+            Ok(joined) => joined,
+            Err(reason) => {
+                connection.send(&Message::Rejected { reason }).await?;
+                return Ok(());
+            }
+        };
+
+        let result: Result<(), SyncError> = async {
+            connection
+                .send(&Message::Welcome {
+                    version: server_version,
+                })
+                .await?;
+            for batch in missing {
+                connection.send(&Message::Batch { batch }).await?;
+            }
+
+            loop {
+                tokio::select! {
+                    message = connection.receive() => match message? {
+                        Some(Message::Batch { batch }) => {
+                            if let Some(document) = lock(&documents).get_mut(&name) {
+                                document.append(site_id, batch);
+                            }
+                        }
+                        Some(Message::RequestSnapshot) => {
+                            let snapshot: Option<Message> = lock(&documents).get(&name).map(|document| {
+                                Message::Snapshot {
+                                    version: document.rga.version().clone(),
+                                    values: document.rga.read(),
+                                }
+                            });
+                            if let Some(snapshot) = snapshot {
+                                connection.send(&snapshot).await?;
+                            }
+                        }
+                        Some(_) => return Err(SyncError::UnexpectedMessage),
+                        None => return Ok(()),
+                    },
+                    relayed = live.recv() => match relayed {
+                        Ok((from, batch)) if from != site_id => {
+                            connection.send(&Message::Batch { batch }).await?;
+                        }
+                        Ok(_) => {}
+                        Err(_) => return Err(SyncError::Disconnected),
+                    },
+                }
+            }
+        }
+        .await;
+
+        if let Some(document) = lock(&documents).get_mut(&name) {
+            document.sites.remove(&site_id);
+        }
+        return result;
+    }
+}
+
+/// Accepts the next connection on `listener`, or waits forever if there is none.
+async fn accept(listener: Option<&TcpListener>) -> std::io::Result<(TcpStream, SocketAddr)> {
+    return match listener {
+        Some(listener) => listener.accept().await,
+        None => std::future::pending().await,
+    };
+}
+
+fn lock(documents: &Documents) -> MutexGuard<'_, HashMap<String, Document>> {
+    return documents.lock().expect("documents poisoned");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rga::rga::BroadcastOperation;
+    use crate::SyncClient;
+    use futures_util::{SinkExt, StreamExt};
+    use serde_json::{json, Value};
+    use tokio_tungstenite::tungstenite;
+
+    type WebSocket = tokio_tungstenite::WebSocketStream<
+        tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>,
+    >;
+
+    async fn start() -> (SyncServer, SocketAddr, SocketAddr) {
+        let server: SyncServer = SyncServer::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .listen_websocket("127.0.0.1:0")
+            .await
+            .unwrap();
+        let addr: SocketAddr = server.local_addr().unwrap();
+        let websocket: SocketAddr = server.websocket_addr().unwrap().unwrap();
+        tokio::spawn(server.clone().run());
+        return (server, addr, websocket);
+    }
+
+    /// Connects the way a browser would, sending and receiving raw JSON.
+    async fn connect(addr: SocketAddr) -> WebSocket {
+        let (websocket, _) = tokio_tungstenite::connect_async(format!("ws://{addr}"))
+            .await
+            .unwrap();
+        return websocket;
+    }
+
+    async fn send(websocket: &mut WebSocket, message: Value) {
+        let text: String = message.to_string();
+        websocket
+            .send(tungstenite::Message::text(text))
+            .await
+            .unwrap();
+    }
+
+    async fn receive(websocket: &mut WebSocket) -> Option<Value> {
+        while let Some(frame) = websocket.next().await {
+            if let Ok(tungstenite::Message::Text(text)) = frame {
+                return Some(serde_json::from_str(&text).unwrap());
+            }
+        }
+        return None;
+    }
+
+    #[tokio::test]
+    async fn test_websocket_protocol() {
+        let (server, addr, websocket_addr) = start().await;
+        let mut alice = SyncClient::connect(addr, "doc", RGA::new(1, 1))
+            .await
+            .unwrap();
+        let a: BroadcastOperation = alice
+            .rga_mut()
+            .local_insert("a".to_string(), None, None)
+            .unwrap();
+        alice.send(a.clone().into()).await.unwrap();
+
+        let mut browser: WebSocket = connect(websocket_addr).await;
+        send(
+            &mut browser,
+            json!({"type": "hello", "document": "doc", "site_id": 9, "version": {}}),
+        )
+        .await;
+        let welcome: Value = receive(&mut browser).await.unwrap();
+        assert_eq!(welcome["type"], "welcome");
+
+        // The insert the browser missed
+        let batch: Value = receive(&mut browser).await.unwrap();
+        assert_eq!(batch["type"], "batch");
+        let op: &Value = &batch["batch"]["operations"][0];
+        assert_eq!(op["operation"], "Insert");
+        assert_eq!(op["value"], "a");
+        assert_eq!(
+            op["s4vector"],
+            json!({"ssn": 1, "sum": 1, "sid": 1, "seq": 1})
+        );
+
+        // An insert written by hand, after "a"
+        let id: Value = json!({"ssn": 1, "sum": 2, "sid": 9, "seq": 1});
+        send(
+            &mut browser,
+            json!({"type": "batch", "batch": {"operations": [{
+                "operation": "Insert",
+                "s4vector": id,
+                "value": "b",
+                "left": op["s4vector"],
+                "right": null,
+                "id": id,
+            }]}}),
+        )
+        .await;
+        alice.receive().await.unwrap();
+        assert_eq!(alice.rga().read(), vec!["a".to_string(), "b".to_string()]);
+
+        send(&mut browser, json!({"type": "request_snapshot"})).await;
+        let snapshot: Value = receive(&mut browser).await.unwrap();
+        assert_eq!(
+            snapshot,
+            json!({"type": "snapshot", "version": {"1": 1, "9": 1}, "values": ["a", "b"]})
+        );
+        assert_eq!(server.log("doc").len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_websocket_rejections() {
+        let (_server, _, websocket_addr) = start().await;
+        let mut first: WebSocket = connect(websocket_addr).await;
+        let hello: Value = json!({"type": "hello", "document": "doc", "site_id": 3, "version": {}});
+        send(&mut first, hello.clone()).await;
+        assert_eq!(receive(&mut first).await.unwrap()["type"], "welcome");
+
+        let mut second: WebSocket = connect(websocket_addr).await;
+        send(&mut second, hello).await;
+        let rejected: Value = receive(&mut second).await.unwrap();
+        assert_eq!(rejected["type"], "rejected");
+
+        // Malformed messages close the connection
+        let mut third: WebSocket = connect(websocket_addr).await;
+        send(&mut third, json!({"type": "hello", "document": "doc"})).await;
+        assert!(receive(&mut third).await.is_none());
+    }
+}
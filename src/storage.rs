@@ -0,0 +1,257 @@
+use crate::persistence::{PersistenceError, Snapshot, Wal};
+use crate::{OperationBatch, VersionVector};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// The name of the snapshot file in the directory of a `FileStorage`.
+pub(crate) const SNAPSHOT_FILE: &str = "snapshot.json";
+
+/// The name of the write-ahead log in the directory of a `FileStorage`.
+pub(crate) const WAL_FILE: &str = "wal.log";
+
+/// Where a `DurableReplica` keeps its snapshot and the batches appended since.
+///
+/// Implementations must make a batch durable before `append` returns, and replace
+/// the snapshot in `compact` so that a crash leaves either the old snapshot with every
+/// batch or the new snapshot. Errors of other systems, such as a database, can be
+/// reported as `PersistenceError::Backend`.
+pub trait Storage {
+    /// Returns the latest snapshot, or `None` if the storage has never been compacted.
+    fn load_snapshot(&mut self) -> Result<Option<Snapshot>, PersistenceError>;
+
+    /// Appends a batch after the latest snapshot.
+    fn append(&mut self, batch: &OperationBatch) -> Result<(), PersistenceError>;
+
+    /// Returns the appended batches holding an operation not covered by `version`,
+    /// oldest first.
+    fn read_since(
+        &mut self,
+        version: &VersionVector,
+    ) -> Result<Vec<OperationBatch>, PersistenceError>;
+
+    /// Returns the number of batches appended since the latest snapshot.
+    fn appended(&self) -> usize;
+
+    /// Saves `snapshot`, which holds the effects of every batch appended so far, and
+    /// drops those batches.
+    fn compact(&mut self, snapshot: &Snapshot) -> Result<(), PersistenceError>;
+}
+
+/// Keeps only the batches holding an operation not covered by `version`.
+fn not_covered(batches: Vec<OperationBatch>, version: &VersionVector) -> Vec<OperationBatch> {
+    return batches
+        .into_iter()
+        .filter(|batch| batch.operations.iter().any(|op| !version.contains(&op.id)))
+        .collect();
+}
+
+/// `MemoryStorage` keeps everything in memory, for tests and replicas that only need
+/// to survive being closed and reopened within a process.
+///
+/// # Example
+/// ```
+/// use crdt::{DurableReplica, MemoryStorage};
+///
+/// let mut replica = DurableReplica::with_storage(MemoryStorage::new(), 1, 1).unwrap();
+/// replica.insert("a".to_string(), None, None).unwrap();
+///
+/// let storage = replica.into_storage();
+/// let replica = DurableReplica::with_storage(storage, 1, 1).unwrap();
+/// assert_eq!(replica.rga().read(), vec!["a".to_string()]);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStorage {
+    snapshot: Option<Snapshot>,
+    batches: Vec<OperationBatch>,
+}
+
+impl MemoryStorage {
+    /// Creates an empty `MemoryStorage`.
+    pub fn new() -> Self {
+        return MemoryStorage::default();
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn load_snapshot(&mut self) -> Result<Option<Snapshot>, PersistenceError> {
+        return Ok(self.snapshot.clone());
+    }
+
+    fn append(&mut self, batch: &OperationBatch) -> Result<(), PersistenceError> {
+        self.batches.push(batch.clone());
+        return Ok(());
+    }
+
+    fn read_since(
+        &mut self,
+        version: &VersionVector,
+    ) -> Result<Vec<OperationBatch>, PersistenceError> {
+        return Ok(not_covered(self.batches.clone(), version));
+    }
+
+    fn appended(&self) -> usize {
+        return self.batches.len();
+    }
+
+    fn compact(&mut self, snapshot: &Snapshot) -> Result<(), PersistenceError> {
+        self.snapshot = Some(snapshot.clone());
+        self.batches.clear();
+        return Ok(());
+    }
+}
+
+/// `FileStorage` keeps a snapshot file and a `Wal` in a directory.
+///
+/// Snapshots are written to a temporary file that is then renamed over the previous
+/// snapshot, so a crash while compacting leaves the previous snapshot, or the new
+/// snapshot with a log whose batches it already covers.
+#[derive(Debug)]
+pub struct FileStorage {
+    directory: PathBuf,
+    wal: Wal,
+}
+
+impl FileStorage {
+    /// Opens the storage in `directory`, creating the directory if needed. A record
+    /// torn by a crash at the end of the log is discarded.
+    pub fn open(directory: impl AsRef<Path>) -> Result<Self, PersistenceError> {
+        let directory: PathBuf = directory.as_ref().to_path_buf();
+        fs::create_dir_all(&directory)?;
+        let (wal, _) = Wal::open(directory.join(WAL_FILE))?;
+        return Ok(FileStorage { directory, wal });
+    }
+
+    /// Returns the directory of the storage.
+    pub fn directory(&self) -> &Path {
+        return &self.directory;
+    }
+}
+
+impl Storage for FileStorage {
+    /// # Returns
+    /// `PersistenceError::CorruptSnapshot` if the snapshot cannot be read.
+    fn load_snapshot(&mut self) -> Result<Option<Snapshot>, PersistenceError> {
+        return match fs::read(self.directory.join(SNAPSHOT_FILE)) {
+            Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(error.into()),
+        };
+    }
+
+    fn append(&mut self, batch: &OperationBatch) -> Result<(), PersistenceError> {
+        return self.wal.append(batch);
+    }
+
+    fn read_since(
+        &mut self,
+        version: &VersionVector,
+    ) -> Result<Vec<OperationBatch>, PersistenceError> {
+        return Ok(not_covered(self.wal.read()?, version));
+    }
+
+    fn appended(&self) -> usize {
+        return self.wal.len();
+    }
+
+    fn compact(&mut self, snapshot: &Snapshot) -> Result<(), PersistenceError> {
+        let bytes: Vec<u8> = serde_json::to_vec(snapshot)?;
+        let temporary: PathBuf = self.directory.join(format!("{SNAPSHOT_FILE}.tmp"));
+        let mut file: File = File::create(&temporary)?;
+        file.write_all(&bytes)?;
+        file.sync_all()?;
+
+        fs::rename(&temporary, self.directory.join(SNAPSHOT_FILE))?;
+        if let Ok(directory) = File::open(&self.directory) {
+            let _ = directory.sync_all();
+        }
+        return self.wal.clear();
+    }
+}
+
+/// Returns an empty directory for a test.
+#[cfg(test)]
+pub(crate) fn test_directory(name: &str) -> PathBuf {
+    let path: PathBuf = std::env::temp_dir().join(format!("crdt-{}-{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&path);
+    return path;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rga::rga::RGA;
+    use crate::test_support::append;
+    use crate::{DurableReplica, S4Vector};
+
+    /// Checks the behaviour every `Storage` must have, calling `reopen` where a
+    /// process would restart.
+    fn check_storage<S: Storage>(mut storage: S, reopen: impl Fn(S) -> S) {
+        assert!(storage.load_snapshot().unwrap().is_none());
+
+        let mut rga1 = RGA::new(1, 1);
+        let mut rga2 = RGA::new(1, 2);
+        let a = rga1.local_insert("a".to_string(), None, None).unwrap();
+        let b = rga2.local_insert("b".to_string(), None, None).unwrap();
+        let c = rga1
+            .local_insert("c".to_string(), Some(a.s4vector), None)
+            .unwrap();
+        for op in [&a, &b, &c] {
+            storage.append(&op.clone().into()).unwrap();
+        }
+
+        let mut storage: S = reopen(storage);
+        assert_eq!(storage.appended(), 3);
+        let ids = |batches: Vec<OperationBatch>| -> Vec<S4Vector> {
+            return batches.iter().map(|batch| batch.operations[0].id).collect();
+        };
+        let everything: Vec<OperationBatch> = storage.read_since(&VersionVector::new()).unwrap();
+        assert_eq!(ids(everything), [a.id, b.id, c.id]);
+        let mut version: VersionVector = VersionVector::new();
+        version.set(1, 1);
+        assert_eq!(ids(storage.read_since(&version).unwrap()), [b.id, c.id]);
+
+        for op in [b, c] {
+            rga1.remote_apply(op);
+        }
+        storage.compact(&rga1.snapshot()).unwrap();
+        let mut storage: S = reopen(storage);
+        assert_eq!(storage.appended(), 0);
+        assert!(storage
+            .read_since(&VersionVector::new())
+            .unwrap()
+            .is_empty());
+        let restored: RGA = RGA::from_snapshot(storage.load_snapshot().unwrap().unwrap());
+        assert_eq!(restored.read(), rga1.read());
+    }
+
+    #[test]
+    fn test_memory_storage() {
+        check_storage(MemoryStorage::new(), |storage| storage);
+    }
+
+    #[test]
+    fn test_file_storage() {
+        let path: PathBuf = test_directory("storage");
+        check_storage(FileStorage::open(&path).unwrap(), |storage| {
+            let directory: PathBuf = storage.directory().to_path_buf();
+            drop(storage);
+            FileStorage::open(directory).unwrap()
+        });
+        fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn test_replica_on_memory_storage() {
+        let mut replica = DurableReplica::with_storage(MemoryStorage::new(), 1, 1).unwrap();
+        replica.set_snapshot_interval(2);
+        for value in ["a", "b", "c"] {
+            append(&mut replica, value);
+        }
+        assert_eq!(replica.storage().appended(), 1);
+
+        let read: Vec<String> = replica.rga().read();
+        let replica = DurableReplica::with_storage(replica.into_storage(), 1, 1).unwrap();
+        assert_eq!(replica.rga().read(), read);
+    }
+}
@@ -0,0 +1,39 @@
+use crate::rga::rga::{BroadcastOperation, RGA};
+use crate::storage::Storage;
+use crate::{DurableReplica, S4Vector};
+
+/// Types `text` one character at a time after `left`, as a user would.
+///
+/// # Returns
+/// The operations to broadcast, one per character.
+pub(crate) fn type_text(
+    rga: &mut RGA,
+    left: Option<S4Vector>,
+    text: &str,
+) -> Vec<BroadcastOperation> {
+    let mut left: Option<S4Vector> = left;
+    let mut operations: Vec<BroadcastOperation> = Vec::new();
+
+    for c in text.chars() {
+        let op = rga.local_insert(c.to_string(), left, None).unwrap();
+        left = Some(op.s4vector);
+        operations.push(op);
+    }
+    return operations;
+}
+
+/// Applies `operations` to `rga` in order.
+pub(crate) fn deliver(rga: &mut RGA, operations: &[BroadcastOperation]) {
+    for op in operations {
+        rga.remote_apply(op.clone());
+    }
+}
+
+/// Appends `value` to the end of a durable replica, storing it before it is applied.
+pub(crate) fn append<S: Storage>(
+    replica: &mut DurableReplica<S>,
+    value: &str,
+) -> BroadcastOperation {
+    let left: Option<S4Vector> = replica.rga().iter_with_ids().last().map(|(id, _)| id);
+    return replica.insert(value.to_string(), left, None).unwrap();
+}
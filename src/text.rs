@@ -0,0 +1,776 @@
+use crate::rga::rga::{BroadcastOperation, OperationError, OperationType, RGA};
+use crate::{Change, OperationBatch, S4Vector};
+use ropey::Rope;
+use similar::{DiffOp, TextDiff};
+use std::fmt;
+use std::ops::Range;
+use std::sync::{Arc, Mutex, OnceLock};
+use unicode_segmentation::{GraphemeCursor, GraphemeIncomplete};
+
+/// A step of the diff between two strings, in characters.
+enum Edit {
+    Keep(usize),
+    Delete(usize),
+    Insert(String),
+}
+
+/// The unit a position in a `Text` is measured in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Unit {
+    /// Unicode scalar values, the unit used by the `Text` API.
+    Char,
+    /// Extended grapheme clusters, the characters a user perceives.
+    Grapheme,
+    /// UTF-8 bytes, as used to index a Rust `str`.
+    Utf8,
+    /// UTF-16 code units, as used by browsers and JavaScript.
+    Utf16,
+}
+
+/// `Text` is a collaborative string built on an `RGA` holding one character per element.
+///
+/// Positions are character indices. Reads are served from a rope cached alongside the
+/// `RGA`, so reading a large document does not rebuild the whole string. The rope is
+/// built on the first read and then patched with the changes every local or remote
+/// operation makes to the sequence.
+///
+/// # Example
+/// ```
+/// use crdt::Text;
+///
+/// let mut text = Text::new(1, 1);
+/// let batch = text.insert_str(0, "hello world").unwrap();
+/// text.delete(5..11).unwrap();
+/// assert_eq!(text.to_string(), "hello");
+///
+/// let mut peer = Text::new(1, 2);
+/// peer.apply_batch(batch).unwrap();
+/// assert_eq!(peer.slice(6..11).unwrap(), "world");
+/// ```
+#[derive(Debug)]
+pub struct Text {
+    rga: RGA,
+    rope: OnceLock<Rope>,
+    /// The changes to the sequence not yet applied to the rope.
+    changes: Arc<Mutex<Vec<Change>>>,
+}
+
+impl Text {
+    /// Creates an empty `Text`.
+    ///
+    /// # Parameters
+    /// - `session_id`: The current session ID.
+    /// - `site_id`: The site ID for this replica.
+    pub fn new(session_id: u64, site_id: u64) -> Self {
+        return Text::wrap(RGA::new(session_id, site_id));
+    }
+
+    /// Wraps an existing `RGA` whose elements are single characters.
+    ///
+    /// # Returns
+    /// The text, or `OperationError::CharacterError` if a visible element or a
+    /// buffered operation holds anything but a single character.
+    pub fn from_rga(rga: RGA) -> Result<Self, OperationError> {
+        if rga.iter().any(|value| value.chars().count() != 1) {
+            return Err(OperationError::CharacterError);
+        }
+        for group in rga.buffered() {
+            for operation in group {
+                check_value(&BroadcastOperation::from(operation.clone()))?;
+            }
+        }
+        return Ok(Text::wrap(rga));
+    }
+
+    fn wrap(mut rga: RGA) -> Self {
+        let changes: Arc<Mutex<Vec<Change>>> = Arc::new(Mutex::new(Vec::new()));
+        let pending: Arc<Mutex<Vec<Change>>> = Arc::clone(&changes);
+        rga.subscribe(move |change| {
+            pending
+                .lock()
+                .expect("text changes poisoned")
+                .push(change.clone());
+        });
+
+        return Text {
+            rga,
+            rope: OnceLock::new(),
+            changes,
+        };
+    }
+
+    /// Returns the underlying `RGA`.
+    pub fn rga(&self) -> &RGA {
+        return &self.rga;
+    }
+
+    /// Returns the cached rope, building it on the first read.
+    fn rope(&self) -> &Rope {
+        return self.rope.get_or_init(|| {
+            let mut rope: Rope = Rope::new();
+            for value in self.rga.iter() {
+                rope.insert(rope.len_chars(), value);
+            }
+            rope
+        });
+    }
+
+    /// Applies the changes made to the `RGA` since the last call to the cached rope,
+    /// or drops them if the rope has not been built yet.
+    fn patch_rope(&mut self) {
+        let changes: Vec<Change> =
+            std::mem::take(&mut *self.changes.lock().expect("text changes poisoned"));
+        let rope: &mut Rope = match self.rope.get_mut() {
+            Some(rope) => rope,
+            None => return,
+        };
+
+        // Every element of a `Text` is a single character, see `check_value`
+        for change in changes {
+            match change {
+                Change::Insert { index, value, .. } => rope.insert(index, &value),
+                Change::Delete { index, .. } => rope.remove(index..index + 1),
+                Change::Update { index, value, .. } => {
+                    rope.remove(index..index + 1);
+                    rope.insert(index, &value);
+                }
+            }
+        }
+    }
+
+    /// Returns the number of characters in the text.
+    pub fn len_chars(&self) -> usize {
+        return self.rope().len_chars();
+    }
+
+    /// Checks whether the text is empty.
+    pub fn is_empty(&self) -> bool {
+        return self.len_chars() == 0;
+    }
+
+    /// Returns the characters in `range` as a `String`.
+    ///
+    /// # Returns
+    /// The slice, or `OperationError::IndexError` if the range is out of bounds.
+    pub fn slice(&self, range: Range<usize>) -> Result<String, OperationError> {
+        let rope: &Rope = self.rope();
+        if range.start > range.end || range.end > rope.len_chars() {
+            return Err(OperationError::IndexError);
+        }
+        return Ok(rope.slice(range).to_string());
+    }
+
+    /// Converts a position from one unit to another.
+    ///
+    /// # Parameters
+    /// - `pos`: The position, measured in `from`.
+    /// - `from`: The unit `pos` is measured in.
+    /// - `to`: The unit to convert to.
+    ///
+    /// # Returns
+    /// The position measured in `to`, `OperationError::IndexError` if `pos` is out of
+    /// bounds, or `OperationError::BoundaryError` if `pos` falls inside a character, or
+    /// inside a grapheme cluster when converting to graphemes.
+    ///
+    /// # Example
+    /// ```
+    /// use crdt::{Text, Unit};
+    ///
+    /// let mut text = Text::new(1, 1);
+    /// text.insert_str(0, "a😀b").unwrap();
+    ///
+    /// assert_eq!(text.convert(2, Unit::Char, Unit::Utf16).unwrap(), 3);
+    /// assert_eq!(text.convert(5, Unit::Utf8, Unit::Char).unwrap(), 2);
+    /// assert!(text.convert(2, Unit::Utf16, Unit::Char).is_err());
+    /// ```
+    pub fn convert(&self, pos: usize, from: Unit, to: Unit) -> Result<usize, OperationError> {
+        let char_idx: usize = match from {
+            Unit::Char => self.char_bounds(pos)?,
+            Unit::Grapheme => self.grapheme_to_char(pos)?,
+            Unit::Utf8 => self.byte_to_char(pos)?,
+            Unit::Utf16 => self.utf16_to_char(pos)?,
+        };
+
+        return match to {
+            Unit::Char => Ok(char_idx),
+            Unit::Grapheme => self.char_to_grapheme(char_idx),
+            Unit::Utf8 => Ok(self.rope().char_to_byte(char_idx)),
+            Unit::Utf16 => Ok(self.rope().char_to_utf16_cu(char_idx)),
+        };
+    }
+
+    /// Returns the length of the text measured in `unit`.
+    pub fn len(&self, unit: Unit) -> usize {
+        let rope: &Rope = self.rope();
+        return match unit {
+            Unit::Char => rope.len_chars(),
+            Unit::Grapheme => self.grapheme_boundaries().count(),
+            Unit::Utf8 => rope.len_bytes(),
+            Unit::Utf16 => rope.len_utf16_cu(),
+        };
+    }
+
+    /// Checks that a character index is within the text.
+    fn char_bounds(&self, char_idx: usize) -> Result<usize, OperationError> {
+        if char_idx > self.len_chars() {
+            return Err(OperationError::IndexError);
+        }
+        return Ok(char_idx);
+    }
+
+    /// Converts a UTF-8 byte offset to a character index.
+    fn byte_to_char(&self, byte_idx: usize) -> Result<usize, OperationError> {
+        let rope: &Rope = self.rope();
+        if byte_idx > rope.len_bytes() {
+            return Err(OperationError::IndexError);
+        }
+
+        let char_idx: usize = rope.byte_to_char(byte_idx);
+        if rope.char_to_byte(char_idx) != byte_idx {
+            return Err(OperationError::BoundaryError);
+        }
+        return Ok(char_idx);
+    }
+
+    /// Converts a UTF-16 code unit offset to a character index.
+    fn utf16_to_char(&self, utf16_idx: usize) -> Result<usize, OperationError> {
+        let rope: &Rope = self.rope();
+        if utf16_idx > rope.len_utf16_cu() {
+            return Err(OperationError::IndexError);
+        }
+
+        // Offsets between the two halves of a surrogate pair round down
+        let char_idx: usize = rope.utf16_cu_to_char(utf16_idx);
+        if rope.char_to_utf16_cu(char_idx) != utf16_idx {
+            return Err(OperationError::BoundaryError);
+        }
+        return Ok(char_idx);
+    }
+
+    /// Converts a grapheme cluster index to a character index.
+    fn grapheme_to_char(&self, grapheme_idx: usize) -> Result<usize, OperationError> {
+        if grapheme_idx == 0 {
+            return Ok(0);
+        }
+
+        let byte_idx: usize = self
+            .grapheme_boundaries()
+            .nth(grapheme_idx - 1)
+            .ok_or(OperationError::IndexError)?;
+        return Ok(self.rope().byte_to_char(byte_idx));
+    }
+
+    /// Converts a character index to a grapheme cluster index.
+    fn char_to_grapheme(&self, char_idx: usize) -> Result<usize, OperationError> {
+        let byte_idx: usize = self.rope().char_to_byte(char_idx);
+        if byte_idx == 0 {
+            return Ok(0);
+        }
+
+        let mut grapheme_idx: usize = 0;
+        for boundary in self.grapheme_boundaries() {
+            grapheme_idx += 1;
+            if boundary >= byte_idx {
+                if boundary != byte_idx {
+                    return Err(OperationError::BoundaryError);
+                }
+                return Ok(grapheme_idx);
+            }
+        }
+        return Err(OperationError::IndexError);
+    }
+
+    /// Iterates over the byte offsets of the end of every grapheme cluster, reading the
+    /// rope a chunk at a time.
+    fn grapheme_boundaries(&self) -> impl Iterator<Item = usize> + '_ {
+        let rope: &Rope = self.rope();
+        let mut cursor: GraphemeCursor = GraphemeCursor::new(0, rope.len_bytes(), true);
+
+        return std::iter::from_fn(move || {
+            let (mut chunk, mut chunk_start, _, _) = rope.chunk_at_byte(cursor.cur_cursor());
+            loop {
+                match cursor.next_boundary(chunk, chunk_start) {
+                    Ok(boundary) => return boundary,
+                    Err(GraphemeIncomplete::NextChunk) => {
+                        chunk_start += chunk.len();
+                        chunk = rope.chunk_at_byte(chunk_start).0;
+                    }
+                    Err(GraphemeIncomplete::PreContext(end)) => {
+                        let (context, context_start, _, _) = rope.chunk_at_byte(end - 1);
+                        cursor.provide_context(context, context_start);
+                    }
+                    Err(_) => return None,
+                }
+            }
+        });
+    }
+
+    /// Inserts `value` so its first character ends up at position `pos`.
+    ///
+    /// # Parameters
+    /// - `pos`: The character index to insert at.
+    /// - `value`: The string to insert.
+    ///
+    /// # Returns
+    /// The batch to broadcast, or `OperationError::IndexError` if `pos` is out of bounds.
+    pub fn insert_str(
+        &mut self,
+        pos: usize,
+        value: &str,
+    ) -> Result<OperationBatch, OperationError> {
+        if pos > self.len_chars() {
+            return Err(OperationError::IndexError);
+        }
+        let anchor: Option<S4Vector> = self.rga.cursor(pos)?.anchor();
+
+        let batch: OperationBatch = self.rga.transaction(|tx| {
+            let mut left: Option<S4Vector> = anchor;
+            for c in value.chars() {
+                left = Some(tx.insert(c.to_string(), left, None)?);
+            }
+            Ok(())
+        })?;
+
+        self.patch_rope();
+        return Ok(batch);
+    }
+
+    /// Deletes the characters in `range`.
+    ///
+    /// # Returns
+    /// The operation to broadcast, or `OperationError::IndexError` if the range is
+    /// empty or out of bounds.
+    pub fn delete(&mut self, range: Range<usize>) -> Result<BroadcastOperation, OperationError> {
+        let operation: BroadcastOperation = self.rga.local_delete_range_at(range)?;
+
+        self.patch_rope();
+        return Ok(operation);
+    }
+
+    /// Replaces the text with `new`, turning the difference into operations.
+    ///
+    /// The text is diffed line by line, and changed lines character by character, so
+    /// unchanged characters keep their identity and concurrent edits to them survive.
+    /// The edits are made in a single transaction.
+    ///
+    /// # Returns
+    /// The batch to broadcast, or the error of the first edit that failed, in which
+    /// case the text is left unchanged.
+    ///
+    /// # Example
+    /// ```
+    /// use crdt::Text;
+    ///
+    /// let mut text = Text::new(1, 1);
+    /// text.insert_str(0, "fn main() {}\n").unwrap();
+    ///
+    /// let batch = text.apply_diff("fn main() { run(); }\n").unwrap();
+    /// assert_eq!(batch.operations.len(), 1);
+    /// assert_eq!(text.to_string(), "fn main() { run(); }\n");
+    /// ```
+    pub fn apply_diff(&mut self, new: &str) -> Result<OperationBatch, OperationError> {
+        let old: String = self.to_string();
+        let ids: Vec<S4Vector> = self
+            .rga
+            .iter_with_ids()
+            .map(|(s4vector, _)| s4vector)
+            .collect();
+        let edits: Vec<Edit> = Text::diff(&old, new);
+
+        let batch: OperationBatch = self.rga.transaction(|tx| {
+            let mut index: usize = 0;
+            let mut left: Option<S4Vector> = None;
+
+            for edit in edits {
+                match edit {
+                    Edit::Keep(len) => {
+                        index += len;
+                        left = index.checked_sub(1).map(|i| ids[i]).or(left);
+                    }
+                    Edit::Delete(len) => {
+                        for s4vector in &ids[index..index + len] {
+                            tx.delete(*s4vector)?;
+                        }
+                        index += len;
+                    }
+                    Edit::Insert(value) => {
+                        for c in value.chars() {
+                            left = Some(tx.insert(c.to_string(), left, None)?);
+                        }
+                    }
+                }
+            }
+            Ok(())
+        })?;
+
+        self.patch_rope();
+        return Ok(batch);
+    }
+
+    /// Computes the edits turning `old` into `new`, diffing lines first and then the
+    /// characters of the lines that changed.
+    fn diff(old: &str, new: &str) -> Vec<Edit> {
+        let chars =
+            |lines: &[&str]| -> usize { lines.iter().map(|line| line.chars().count()).sum() };
+        let lines = TextDiff::from_lines(old, new);
+        let (old_lines, new_lines) = (lines.old_slices(), lines.new_slices());
+
+        let mut edits: Vec<Edit> = Vec::new();
+        for op in lines.ops() {
+            match *op {
+                DiffOp::Equal { old_index, len, .. } => {
+                    edits.push(Edit::Keep(chars(&old_lines[old_index..old_index + len])));
+                }
+                DiffOp::Delete {
+                    old_index, old_len, ..
+                } => {
+                    edits.push(Edit::Delete(chars(
+                        &old_lines[old_index..old_index + old_len],
+                    )));
+                }
+                DiffOp::Insert {
+                    new_index, new_len, ..
+                } => {
+                    edits.push(Edit::Insert(
+                        new_lines[new_index..new_index + new_len].concat(),
+                    ));
+                }
+                DiffOp::Replace {
+                    old_index,
+                    old_len,
+                    new_index,
+                    new_len,
+                } => {
+                    let old: String = old_lines[old_index..old_index + old_len].concat();
+                    let new: String = new_lines[new_index..new_index + new_len].concat();
+                    let characters = TextDiff::from_chars(old.as_str(), new.as_str());
+
+                    for op in characters.ops() {
+                        let (old_range, new_range) = (op.old_range(), op.new_range());
+                        match op {
+                            DiffOp::Equal { len, .. } => edits.push(Edit::Keep(*len)),
+                            DiffOp::Delete { old_len, .. } => edits.push(Edit::Delete(*old_len)),
+                            DiffOp::Insert { .. } => edits
+                                .push(Edit::Insert(characters.new_slices()[new_range].concat())),
+                            DiffOp::Replace { .. } => {
+                                edits.push(Edit::Delete(old_range.len()));
+                                edits.push(Edit::Insert(
+                                    characters.new_slices()[new_range].concat(),
+                                ));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        return edits;
+    }
+
+    /// Applies an operation broadcast by another replica.
+    ///
+    /// # Returns
+    /// `OperationError::CharacterError` if the operation would give an element
+    /// anything but a single character, in which case nothing is applied.
+    pub fn apply(&mut self, operation: BroadcastOperation) -> Result<(), OperationError> {
+        check_value(&operation)?;
+        self.rga.remote_apply(operation);
+        self.patch_rope();
+        return Ok(());
+    }
+
+    /// Applies a batch broadcast by another replica.
+    ///
+    /// # Returns
+    /// `OperationError::CharacterError` if an operation of the batch would give an
+    /// element anything but a single character, in which case nothing is applied.
+    pub fn apply_batch(&mut self, batch: OperationBatch) -> Result<(), OperationError> {
+        for operation in &batch.operations {
+            check_value(operation)?;
+        }
+        self.rga.remote_apply_batch(batch);
+        self.patch_rope();
+        return Ok(());
+    }
+}
+
+/// Checks that an operation gives every element it inserts or updates a single
+/// character, which the cached rope relies on.
+fn check_value(operation: &BroadcastOperation) -> Result<(), OperationError> {
+    let single: bool = match operation.operation {
+        OperationType::Insert | OperationType::Update | OperationType::UpdateRange { .. } => {
+            operation
+                .value
+                .as_ref()
+                .is_some_and(|value| value.chars().count() == 1)
+        }
+        // A run holds one element per character
+        OperationType::InsertRun => true,
+        OperationType::Delete | OperationType::DeleteRange { .. } | OperationType::Move => true,
+    };
+    if !single {
+        return Err(OperationError::CharacterError);
+    }
+    return Ok(());
+}
+
+impl fmt::Display for Text {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for chunk in self.rope().chunks() {
+            f.write_str(chunk)?;
+        }
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_insert_and_delete() {
+        let mut text = Text::new(1, 1);
+        text.insert_str(0, "hed").unwrap();
+        text.insert_str(2, "llo worl").unwrap();
+
+        assert_eq!(text.to_string(), "hello world");
+        assert_eq!(text.len_chars(), 11);
+
+        text.delete(0..6).unwrap();
+        assert_eq!(text.to_string(), "world");
+        assert_eq!(text.rga().read().concat(), "world");
+        assert!(text.insert_str(6, "!").is_err());
+    }
+
+    #[test]
+    fn test_concurrent_edits_converge() {
+        let mut text1 = Text::new(1, 1);
+        let mut text2 = Text::new(1, 2);
+        text2
+            .apply_batch(text1.insert_str(0, "ab").unwrap())
+            .unwrap();
+
+        let x = text1.insert_str(1, "xx").unwrap();
+        let y = text2.insert_str(1, "yy").unwrap();
+        let delete = text2.delete(0..1).unwrap();
+        text1.apply_batch(y).unwrap();
+        text1.apply(delete).unwrap();
+        text2.apply_batch(x).unwrap();
+
+        assert_eq!(text1.to_string(), text2.to_string());
+        assert_eq!(text1.len_chars(), 5);
+    }
+
+    #[test]
+    fn test_cache_matches_rga() {
+        let mut text1 = Text::new(1, 1);
+        let mut text2 = Text::new(1, 2);
+        let batch = text1.insert_str(0, "héllo wörld").unwrap();
+        text2.apply_batch(batch).unwrap();
+
+        // Local and remote edits patch the cache in place
+        assert_eq!(text2.slice(1..5).unwrap(), "éllo");
+        text2
+            .apply_batch(text1.insert_str(5, ",").unwrap())
+            .unwrap();
+        text2.insert_str(12, "!").unwrap();
+
+        assert_eq!(text2.to_string(), "héllo, wörld!");
+        assert_eq!(text2.to_string(), text2.rga().read().concat());
+        assert!(text2.slice(3..20).is_err());
+    }
+
+    #[test]
+    fn test_remote_operations_patch_cache() {
+        let mut text1 = Text::new(1, 1);
+        let mut text2 = Text::new(1, 2);
+        let mut rga: RGA = RGA::new(1, 3);
+        let batch: OperationBatch = text1.insert_str(0, "hello world").unwrap();
+        rga.remote_apply_batch(batch.clone());
+        text2.apply_batch(batch).unwrap();
+        assert_eq!(text2.to_string(), "hello world");
+
+        // Edits the `Text` API does not make, received from a plain replica
+        let ids: Vec<S4Vector> = text1.rga().iter_with_ids().map(|(id, _)| id).collect();
+        text2
+            .apply(rga.local_update(ids[0], "H".to_string()).unwrap())
+            .unwrap();
+        text2
+            .apply(rga.local_move(ids[5], Some(ids[10]), None).unwrap())
+            .unwrap();
+        text2.apply(text1.delete(6..11).unwrap()).unwrap();
+
+        assert!(text2.rope.get().is_some());
+        assert_eq!(text2.to_string(), text2.rga().read().concat());
+        assert_eq!(text2.to_string(), "Hello ");
+    }
+
+    #[test]
+    fn test_values_must_be_single_characters() {
+        let mut text = Text::new(1, 1);
+        let mut rga: RGA = RGA::new(1, 2);
+        let batch: OperationBatch = text.insert_str(0, "abc").unwrap();
+        rga.remote_apply_batch(batch);
+        assert_eq!(text.to_string(), "abc");
+
+        // Multi-character values from a plain replica would desynchronise the rope
+        let ids: Vec<S4Vector> = rga.iter_with_ids().map(|(id, _)| id).collect();
+        let update: BroadcastOperation = rga.local_update(ids[0], "xyz".to_string()).unwrap();
+        let insert: BroadcastOperation = rga
+            .local_insert("de".to_string(), Some(ids[2]), None)
+            .unwrap();
+        assert!(matches!(
+            text.apply(update),
+            Err(OperationError::CharacterError)
+        ));
+        assert!(matches!(
+            text.apply_batch(OperationBatch {
+                operations: vec![rga.local_delete(ids[1]).unwrap(), insert],
+            }),
+            Err(OperationError::CharacterError)
+        ));
+        assert_eq!(text.to_string(), "abc");
+        assert_eq!(text.rga().read().concat(), "abc");
+        assert!(matches!(
+            Text::from_rga(rga),
+            Err(OperationError::CharacterError)
+        ));
+
+        let mut rga: RGA = RGA::new(1, 3);
+        rga.local_insert("a".to_string(), None, None).unwrap();
+        assert_eq!(Text::from_rga(rga).unwrap().to_string(), "a");
+    }
+
+    #[test]
+    fn test_convert_emoji() {
+        // "😀" is one grapheme, one char, four UTF-8 bytes and a UTF-16 surrogate pair
+        let mut text = Text::new(1, 1);
+        text.insert_str(0, "a😀b").unwrap();
+
+        assert_eq!(text.len(Unit::Char), 3);
+        assert_eq!(text.len(Unit::Grapheme), 3);
+        assert_eq!(text.len(Unit::Utf8), 6);
+        assert_eq!(text.len(Unit::Utf16), 4);
+
+        assert_eq!(text.convert(2, Unit::Char, Unit::Utf8).unwrap(), 5);
+        assert_eq!(text.convert(2, Unit::Char, Unit::Utf16).unwrap(), 3);
+        assert_eq!(text.convert(3, Unit::Utf16, Unit::Char).unwrap(), 2);
+        assert_eq!(text.convert(5, Unit::Utf8, Unit::Grapheme).unwrap(), 2);
+        assert_eq!(text.convert(2, Unit::Grapheme, Unit::Utf16).unwrap(), 3);
+    }
+
+    #[test]
+    fn test_convert_rejects_split_characters() {
+        let mut text = Text::new(1, 1);
+        text.insert_str(0, "a😀b").unwrap();
+
+        // Inside the surrogate pair and inside the UTF-8 encoding
+        assert!(matches!(
+            text.convert(2, Unit::Utf16, Unit::Char),
+            Err(OperationError::BoundaryError)
+        ));
+        assert!(matches!(
+            text.convert(3, Unit::Utf8, Unit::Char),
+            Err(OperationError::BoundaryError)
+        ));
+        assert!(matches!(
+            text.convert(5, Unit::Utf16, Unit::Char),
+            Err(OperationError::IndexError)
+        ));
+    }
+
+    #[test]
+    fn test_convert_combining_marks() {
+        // "e\u{301}" is one grapheme made of two chars
+        let mut text = Text::new(1, 1);
+        text.insert_str(0, "cafe\u{301}!").unwrap();
+
+        assert_eq!(text.len(Unit::Char), 6);
+        assert_eq!(text.len(Unit::Grapheme), 5);
+        assert_eq!(text.convert(4, Unit::Grapheme, Unit::Char).unwrap(), 5);
+        assert_eq!(text.convert(6, Unit::Char, Unit::Grapheme).unwrap(), 5);
+        assert_eq!(text.convert(5, Unit::Char, Unit::Grapheme).unwrap(), 4);
+        assert!(matches!(
+            text.convert(4, Unit::Char, Unit::Grapheme),
+            Err(OperationError::BoundaryError)
+        ));
+    }
+
+    #[test]
+    fn test_convert_grapheme_sequences() {
+        // A family emoji joined by zero width joiners, and a flag made of two
+        // regional indicators, are single graphemes
+        let family: &str = "\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}";
+        let flag: &str = "\u{1F1F3}\u{1F1FF}";
+        let mut text = Text::new(1, 1);
+        text.insert_str(0, &format!("{family}{flag}x")).unwrap();
+
+        assert_eq!(text.len(Unit::Grapheme), 3);
+        assert_eq!(text.len(Unit::Char), 8);
+        assert_eq!(text.len(Unit::Utf16), 13);
+        assert_eq!(text.convert(1, Unit::Grapheme, Unit::Utf16).unwrap(), 8);
+        assert_eq!(text.convert(12, Unit::Utf16, Unit::Grapheme).unwrap(), 2);
+        assert_eq!(text.convert(3, Unit::Grapheme, Unit::Utf8).unwrap(), 27);
+    }
+
+    #[test]
+    fn test_graphemes_across_chunks() {
+        use unicode_segmentation::UnicodeSegmentation;
+
+        let value: String = "e\u{301}\u{1F1F3}\u{1F1FF}a".repeat(500);
+        let mut text = Text::new(1, 1);
+        text.insert_str(0, &value).unwrap();
+
+        assert_eq!(text.len(Unit::Grapheme), value.graphemes(true).count());
+        let (byte_idx, _) = value.grapheme_indices(true).nth(1001).unwrap();
+        assert_eq!(
+            text.convert(1001, Unit::Grapheme, Unit::Utf8).unwrap(),
+            byte_idx
+        );
+    }
+
+    #[test]
+    fn test_apply_diff() {
+        let mut text1 = Text::new(1, 1);
+        let mut text2 = Text::new(1, 2);
+        text2
+            .apply_batch(text1.insert_str(0, "one\ntwo\nthree\n").unwrap())
+            .unwrap();
+
+        let batch: OperationBatch = text1.apply_diff("zero\none\ntwo!\nfour\n").unwrap();
+        text2.apply_batch(batch).unwrap();
+
+        assert_eq!(text1.to_string(), "zero\none\ntwo!\nfour\n");
+        assert_eq!(text1.rga().read().concat(), text1.to_string());
+        assert_eq!(text2.to_string(), text1.to_string());
+    }
+
+    #[test]
+    fn test_apply_diff_is_minimal() {
+        let mut text = Text::new(1, 1);
+        text.insert_str(0, "héllo wörld").unwrap();
+
+        let operations = |batch: OperationBatch| -> usize { batch.operations.len() };
+        assert_eq!(operations(text.apply_diff("héllo wörld").unwrap()), 0);
+        assert_eq!(operations(text.apply_diff("hällo wörld!").unwrap()), 3);
+        assert_eq!(operations(text.apply_diff("").unwrap()), 12);
+        assert!(text.is_empty());
+    }
+
+    #[test]
+    fn test_apply_diff_keeps_concurrent_edits() {
+        let mut text1 = Text::new(1, 1);
+        let mut text2 = Text::new(1, 2);
+        text2
+            .apply_batch(text1.insert_str(0, "let x = 1;\nlet y = 2;\n").unwrap())
+            .unwrap();
+
+        // A formatter rewrites the first line while another user edits the second
+        let diff: OperationBatch = text1.apply_diff("let x = 10;\nlet y = 2;\n").unwrap();
+        let batch: OperationBatch = text2.insert_str(20, "0").unwrap();
+        text2.apply_batch(diff).unwrap();
+        text1.apply_batch(batch).unwrap();
+
+        assert_eq!(text1.to_string(), "let x = 10;\nlet y = 20;\n");
+        assert_eq!(text2.to_string(), text1.to_string());
+    }
+}
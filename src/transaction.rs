@@ -0,0 +1,374 @@
+use crate::rga::rga::{BroadcastOperation, Node, Operation, OperationError, OperationType, RGA};
+use crate::{OpLog, S4Vector};
+use std::collections::BTreeMap;
+
+/// `OperationBatch` is a group of operations produced by a single transaction.
+///
+/// Peers apply a batch with [`RGA::remote_apply_batch`], which integrates every
+/// operation in the batch at once or, if any of its dependencies are missing,
+/// buffers the whole batch until they arrive. Characters inserted one after another
+/// are sent as a single `InsertRun` operation.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct OperationBatch {
+    /// The operations in the order they were applied locally.
+    pub operations: Vec<BroadcastOperation>,
+}
+
+impl From<BroadcastOperation> for OperationBatch {
+    /// Wraps a single operation so it can be sent wherever a batch is expected.
+    fn from(operation: BroadcastOperation) -> Self {
+        return OperationBatch {
+            operations: vec![operation],
+        };
+    }
+}
+
+/// The state needed to undo a single edit made in a transaction.
+enum Undo {
+    Insert(S4Vector),
+    /// The element, whether it was deleted before and the id of the delete.
+    Delete(S4Vector, bool, S4Vector),
+    /// The element and its value, update and history before the update.
+    Update(
+        S4Vector,
+        String,
+        Option<S4Vector>,
+        BTreeMap<S4Vector, String>,
+    ),
+}
+
+/// `Transaction` groups several local edits into a single `OperationBatch`.
+///
+/// Edits are integrated as they are made, so later edits in the same transaction can
+/// refer to nodes inserted by earlier ones. Buffered remote operations are only
+/// re-checked once, when the transaction commits. If the transaction fails, every
+/// edit is rolled back and nothing is broadcast.
+///
+/// # Example
+/// ```
+/// use crdt::rga::rga::RGA;
+///
+/// let mut rga = RGA::new(1, 1);
+/// let batch = rga
+///     .transaction(|tx| {
+///         let h = tx.insert("H".to_string(), None, None)?;
+///         tx.insert("i".to_string(), Some(h), None)?;
+///         Ok(())
+///     })
+///     .unwrap();
+///
+/// let mut peer = RGA::new(1, 2);
+/// peer.remote_apply_batch(batch);
+/// assert_eq!(peer.read(), rga.read());
+/// ```
+pub struct Transaction<'a> {
+    rga: &'a mut RGA,
+    operations: Vec<BroadcastOperation>,
+    undo: Vec<Undo>,
+}
+
+impl Transaction<'_> {
+    /// Inserts a new value as part of the transaction.
+    ///
+    /// # Parameters
+    /// - `value`: The value to insert.
+    /// - `left`: The S4Vector of the left neighbor (if any).
+    /// - `right`: The S4Vector of the right neighbor (if any), see `RGA::local_insert`.
+    ///
+    /// # Returns
+    /// The `S4Vector` of the new node, `OperationError::DependancyError` if a
+    /// neighbour is unknown, or `OperationError::NeighbourError` if `left` and
+    /// `right` are not neighbours.
+    pub fn insert(
+        &mut self,
+        value: String,
+        left: Option<S4Vector>,
+        right: Option<S4Vector>,
+    ) -> Result<S4Vector, OperationError> {
+        let operation: Operation = self.rga.prepare_local_insert(value, left, right)?;
+
+        self.rga.integrate(&operation);
+        self.undo.push(Undo::Insert(operation.s4vector));
+
+        // Consecutive characters are sent as a single run
+        if let Some(run) = self.operations.last_mut().filter(|run| {
+            let run_len: u64 = run.value.as_ref().map_or(0, |value| value.chars().count()) as u64;
+            let continues_run: bool = match run.operation {
+                OperationType::Insert => run_len == 1,
+                OperationType::InsertRun => true,
+                _ => false,
+            };
+
+            continues_run
+                && run_len > 0
+                && operation.s4vector == run.s4vector.offset(run_len)
+                && operation.left == Some(run.s4vector.offset(run_len - 1))
+                && operation.right == run.right
+                && operation
+                    .value
+                    .as_ref()
+                    .is_some_and(|value| value.chars().count() == 1)
+        }) {
+            run.operation = OperationType::InsertRun;
+            run.value
+                .get_or_insert_with(String::new)
+                .push_str(operation.value.as_deref().unwrap_or_default());
+            return Ok(operation.s4vector);
+        }
+
+        let s4vector: S4Vector = operation.s4vector;
+        self.operations.push(BroadcastOperation::from(operation));
+
+        return Ok(s4vector);
+    }
+
+    /// Marks a node as logically deleted as part of the transaction.
+    ///
+    /// # Parameters
+    /// - `s4vector`: The unique identifier of the node to delete.
+    pub fn delete(&mut self, s4vector: S4Vector) -> Result<(), OperationError> {
+        let s4vector: S4Vector = self.rga.identity_of(&s4vector);
+        let tombstone: bool = self
+            .rga
+            .isolate(&s4vector)
+            .ok_or(OperationError::DependancyError)?
+            .tombstone;
+
+        let operation: Operation = self.rga.prepare_local_delete(s4vector);
+        self.rga.integrate(&operation);
+        self.undo
+            .push(Undo::Delete(s4vector, tombstone, operation.id));
+        self.operations.push(BroadcastOperation::from(operation));
+
+        return Ok(());
+    }
+
+    /// Updates the value of a node as part of the transaction.
+    ///
+    /// # Parameters
+    /// - `s4vector`: The unique identifier of the node to update.
+    /// - `value`: The new value.
+    pub fn update(&mut self, s4vector: S4Vector, value: String) -> Result<(), OperationError> {
+        let s4vector: S4Vector = self.rga.identity_of(&s4vector);
+        let node: &Node = self
+            .rga
+            .isolate(&s4vector)
+            .ok_or(OperationError::DependancyError)?;
+        let previous: (String, Option<S4Vector>, BTreeMap<S4Vector, String>) =
+            (node.value.clone(), node.updated, node.values.clone());
+
+        // Updates to deleted nodes are ignored on integration, but the value is still
+        // broadcast so every replica reads the same past versions
+        let operation: Operation = self.rga.prepare_local_update(s4vector, value);
+        self.rga.integrate(&operation);
+        self.undo
+            .push(Undo::Update(s4vector, previous.0, previous.1, previous.2));
+        self.operations.push(BroadcastOperation::from(operation));
+
+        return Ok(());
+    }
+
+    /// Reads the current state of the RGA, including the edits made so far.
+    pub fn read(&self) -> Vec<String> {
+        return self.rga.read();
+    }
+
+    /// Undoes every edit made in the transaction, most recent first.
+    fn rollback(&mut self) {
+        while let Some(undo) = self.undo.pop() {
+            match undo {
+                Undo::Insert(s4vector) => self.rga.unlink(&s4vector),
+                Undo::Delete(s4vector, tombstone, id) => {
+                    if let Some(node) = self.rga.isolate(&s4vector) {
+                        node.tombstone = tombstone;
+                        node.deletes.remove(&id);
+                    }
+                }
+                Undo::Update(s4vector, value, updated, values) => {
+                    if let Some(node) = self.rga.isolate(&s4vector) {
+                        node.value = value;
+                        node.updated = updated;
+                        node.values = values;
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl RGA {
+    /// Applies several local edits and returns them as a single `OperationBatch`.
+    ///
+    /// # Parameters
+    /// - `f`: A closure performing the edits through the provided `Transaction`.
+    ///
+    /// # Returns
+    /// The batch to broadcast, or the first error returned by the closure, in which
+    /// case none of the edits are kept.
+    pub fn transaction<F>(&mut self, f: F) -> Result<OperationBatch, OperationError>
+    where
+        F: FnOnce(&mut Transaction<'_>) -> Result<(), OperationError>,
+    {
+        return self.transaction_with(f, |_| Ok::<(), OperationError>(()));
+    }
+
+    /// Applies a transaction, passing its batch to `commit` before any subscriber
+    /// sees the edits or the batch is returned.
+    ///
+    /// # Returns
+    /// The batch, or the first error of the closure or of `commit`, in which case
+    /// none of the edits are kept.
+    pub(crate) fn transaction_with<F, C, E>(&mut self, f: F, commit: C) -> Result<OperationBatch, E>
+    where
+        F: FnOnce(&mut Transaction<'_>) -> Result<(), OperationError>,
+        C: FnOnce(&OperationBatch) -> Result<(), E>,
+        E: From<OperationError>,
+    {
+        let local_sequence: u64 = self.local_sequence();
+        let logged: Option<usize> = self.log().map(OpLog::len);
+        let mut transaction: Transaction<'_> = Transaction {
+            rga: self,
+            operations: Vec::new(),
+            undo: Vec::new(),
+        };
+
+        let result: Result<OperationBatch, E> = match f(&mut transaction) {
+            Ok(()) => {
+                let batch: OperationBatch = OperationBatch {
+                    operations: std::mem::take(&mut transaction.operations),
+                };
+                commit(&batch).map(|()| batch)
+            }
+            Err(e) => Err(E::from(e)),
+        };
+        if result.is_err() {
+            transaction.rollback();
+            self.set_local_sequence(local_sequence);
+            self.observers().discard();
+            if let (Some(log), Some(len)) = (self.log_slot().as_mut(), logged) {
+                log.rollback(len);
+            }
+            return result;
+        }
+
+        self.apply_buffered_operations();
+        return result;
+    }
+
+    /// Applies a batch received from another replica atomically.
+    ///
+    /// Either every operation in the batch becomes visible at once, or the batch is
+    /// buffered as a whole until the nodes it depends on have been received.
+    pub fn remote_apply_batch(&mut self, batch: OperationBatch) {
+        let group: Vec<Operation> = batch.operations.into_iter().map(Operation::from).collect();
+        self.apply_remote_group(group);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn insert_text(rga: &mut RGA, text: &str) -> OperationBatch {
+        return rga
+            .transaction(|tx| {
+                let mut left: Option<S4Vector> = None;
+                for c in text.chars() {
+                    left = Some(tx.insert(c.to_string(), left, None)?);
+                }
+                Ok(())
+            })
+            .unwrap();
+    }
+
+    #[test]
+    fn test_transaction_produces_single_batch() {
+        let mut rga = RGA::new(1, 1);
+        let batch = insert_text(&mut rga, "hello");
+
+        assert_eq!(batch.operations.len(), 1);
+        assert!(matches!(
+            batch.operations[0].operation,
+            OperationType::InsertRun
+        ));
+        assert_eq!(batch.operations[0].value.as_deref(), Some("hello"));
+        assert_eq!(rga.read().concat(), "hello");
+    }
+
+    #[test]
+    fn test_remote_apply_batch() {
+        let mut rga1 = RGA::new(1, 1);
+        let mut rga2 = RGA::new(1, 2);
+
+        let batch = insert_text(&mut rga1, "abc");
+        rga2.remote_apply_batch(batch);
+
+        let first = rga1.read();
+        let batch = rga1
+            .transaction(|tx| {
+                let a = tx.insert("x".to_string(), None, None)?;
+                let b = tx.insert("y".to_string(), Some(a), None)?;
+                tx.update(b, "z".to_string())?;
+                tx.delete(a)?;
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(first.concat(), "abc");
+
+        rga2.remote_apply_batch(batch);
+        assert_eq!(rga1.read(), rga2.read());
+        assert_eq!(rga2.read().concat(), "zabc");
+    }
+
+    #[test]
+    fn test_remote_apply_batch_is_atomic() {
+        let mut rga1 = RGA::new(1, 1);
+        let mut rga2 = RGA::new(1, 2);
+
+        let first = insert_text(&mut rga1, "ab");
+        let second = insert_text(&mut rga1, "cd");
+        let last = first.operations[0].s4vector.offset(1);
+        let third = rga1
+            .transaction(|tx| {
+                tx.insert("e".to_string(), Some(last), None)?;
+                tx.delete(last)?;
+                Ok(())
+            })
+            .unwrap();
+
+        // Neither batch can be applied until the first one arrives
+        rga2.remote_apply_batch(third);
+        rga2.remote_apply_batch(second);
+        assert!(rga2.read().is_empty());
+
+        rga2.remote_apply_batch(first);
+        assert_eq!(rga1.read(), rga2.read());
+        assert_eq!(rga2.read().concat(), "cdae");
+    }
+
+    #[test]
+    fn test_transaction_rolls_back_on_error() {
+        let mut rga = RGA::new(1, 1);
+        insert_text(&mut rga, "ab");
+        let missing = S4Vector {
+            ssn: 1,
+            sum: 100,
+            sid: 9,
+            seq: 9,
+        };
+
+        let result = rga.transaction(|tx| {
+            let x = tx.insert("x".to_string(), None, None)?;
+            tx.update(x, "y".to_string())?;
+            tx.delete(x)?;
+            tx.insert("z".to_string(), Some(missing), None)?;
+            Ok(())
+        });
+
+        assert!(result.is_err());
+        assert_eq!(rga.read().concat(), "ab");
+
+        let batch = insert_text(&mut rga, "c");
+        assert_eq!(batch.operations[0].s4vector.seq, 3);
+    }
+}
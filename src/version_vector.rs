@@ -0,0 +1,153 @@
+use crate::S4Vector;
+use std::collections::BTreeMap;
+
+/// `VersionVector` records, for every site, the highest sequence number up to which
+/// all of that site's operations have been integrated.
+///
+/// Every operation is identified by an `S4Vector`, so an operation has been seen by
+/// a replica iff its `seq` is at most the replica's entry for the operation's `sid`.
+///
+/// # Example
+/// ```
+/// use crdt::{S4Vector, VersionVector};
+///
+/// let mut version = VersionVector::new();
+/// version.set(1, 3);
+///
+/// let s4 = S4Vector { ssn: 1, sum: 1, sid: 1, seq: 2 };
+/// assert!(version.contains(&s4));
+/// assert!(!version.contains(&s4.offset(2)));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(transparent)]
+pub struct VersionVector {
+    #[serde(with = "site_keys")]
+    entries: BTreeMap<u64, u64>,
+}
+
+/// Serializes the entries as a map keyed by the site ID as a string, since JSON
+/// object keys are strings and serde cannot parse them back into integers once
+/// they have been buffered, as they are inside a tagged enum.
+mod site_keys {
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::collections::BTreeMap;
+
+    pub(super) fn serialize<S: Serializer>(
+        entries: &BTreeMap<u64, u64>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let keyed: BTreeMap<String, u64> = entries
+            .iter()
+            .map(|(sid, seq)| (sid.to_string(), *seq))
+            .collect();
+        return keyed.serialize(serializer);
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<BTreeMap<u64, u64>, D::Error> {
+        let keyed: BTreeMap<String, u64> = BTreeMap::deserialize(deserializer)?;
+        return keyed
+            .into_iter()
+            .map(|(sid, seq)| Ok((sid.parse().map_err(D::Error::custom)?, seq)))
+            .collect();
+    }
+}
+
+impl VersionVector {
+    /// Creates an empty `VersionVector`.
+    pub fn new() -> Self {
+        return VersionVector {
+            entries: BTreeMap::new(),
+        };
+    }
+
+    /// Returns the highest sequence number seen from `sid`, or `0` if none.
+    pub fn get(&self, sid: u64) -> u64 {
+        return self.entries.get(&sid).copied().unwrap_or(0);
+    }
+
+    /// Sets the highest sequence number seen from `sid`.
+    pub fn set(&mut self, sid: u64, seq: u64) {
+        if seq == 0 {
+            self.entries.remove(&sid);
+        } else {
+            self.entries.insert(sid, seq);
+        }
+    }
+
+    /// Checks whether the operation identified by `s4vector` is covered by this version.
+    pub fn contains(&self, s4vector: &S4Vector) -> bool {
+        return s4vector.seq <= self.get(s4vector.sid);
+    }
+
+    /// Checks whether this version has seen every operation `other` has seen.
+    pub fn dominates(&self, other: &VersionVector) -> bool {
+        return other
+            .entries
+            .iter()
+            .all(|(sid, seq)| self.get(*sid) >= *seq);
+    }
+
+    /// Merges `other` into this version, keeping the highest entry for each site.
+    pub fn merge(&mut self, other: &VersionVector) {
+        for (sid, seq) in &other.entries {
+            if self.get(*sid) < *seq {
+                self.entries.insert(*sid, *seq);
+            }
+        }
+    }
+
+    /// Iterates over the `(sid, seq)` entries in order of site ID.
+    pub fn iter(&self) -> impl Iterator<Item = (u64, u64)> + '_ {
+        return self.entries.iter().map(|(sid, seq)| (*sid, *seq));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_version_vector_contains() {
+        let mut version = VersionVector::new();
+        version.set(1, 2);
+
+        let s4 = S4Vector {
+            ssn: 1,
+            sum: 5,
+            sid: 1,
+            seq: 2,
+        };
+        assert!(version.contains(&s4));
+        assert!(!version.contains(&s4.offset(1)));
+        assert!(!version.contains(&S4Vector { sid: 2, ..s4 }));
+    }
+
+    #[test]
+    fn test_version_vector_dominates() {
+        let mut a = VersionVector::new();
+        a.set(1, 2);
+        a.set(2, 5);
+        let mut b = VersionVector::new();
+        b.set(1, 2);
+
+        assert!(a.dominates(&b));
+        assert!(!b.dominates(&a));
+        assert!(a.dominates(&VersionVector::new()));
+    }
+
+    #[test]
+    fn test_version_vector_merge() {
+        let mut a = VersionVector::new();
+        a.set(1, 2);
+        a.set(2, 1);
+        let mut b = VersionVector::new();
+        b.set(2, 4);
+        b.set(3, 1);
+
+        a.merge(&b);
+        assert_eq!(a.iter().collect::<Vec<_>>(), vec![(1, 2), (2, 4), (3, 1)]);
+    }
+}